@@ -0,0 +1,134 @@
+//! Variable lookup through chains of scopes, the interpreter's hot path
+//! for globals and captured names.
+//!
+//! `cargo bench --bench env_lookup` times looking up a global from scopes
+//! 1, 8 and 64 environments deep, counting the global one. The deeper
+//! lookups are compared as multiples of the depth 1 time, which depend on
+//! how `Env` walks its parents rather than on the machine, and the
+//! benchmark fails if a multiple is more than `TOLERANCE` above the one
+//! recorded in `env_lookup.baseline`. After a change that is meant to be
+//! faster, record new multiples with
+//! `cargo bench --bench env_lookup -- --save`.
+
+use std::{cell::RefCell, hint::black_box, path::PathBuf, rc::Rc, time::Instant};
+
+use riku::{
+    env::{Env, Value},
+    symbol::Symbol,
+};
+
+const DEPTHS: [usize; 3] = [1, 8, 64];
+const LOOKUPS: u32 = 1_000_000;
+/// Runs per depth; the fastest counts, as the one least disturbed by
+/// other work on the machine.
+const RUNS: usize = 5;
+/// How much larger than the baseline a depth's multiple of the depth 1 time
+/// may be before the benchmark fails, since timings vary between runs.
+const TOLERANCE: f64 = 0.25;
+
+fn main() {
+    let save = std::env::args().any(|arg| arg == "--save");
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches/env_lookup.baseline");
+    let times = DEPTHS.map(|depth| (depth, measure(depth)));
+    let base = times[0].1;
+    let ratios = times.map(|(depth, ns)| (depth, ns, ns / base));
+
+    if save {
+        let mut text = String::from(
+            "# Time per lookup of a global from a scope this many environments deep,\n\
+             # as a multiple of the time from depth 1, written by\n\
+             # `cargo bench --bench env_lookup -- --save`.\n",
+        );
+        for (depth, _, ratio) in &ratios[1..] {
+            text.push_str(&format!("{} {:.2}\n", depth, ratio));
+        }
+        if let Err(e) = std::fs::write(&path, text) {
+            eprintln!("Unable to write `{}`: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        for (depth, ns, ratio) in ratios {
+            println!(
+                "depth {:>2}  {:>8.1} ns  {:>6.2}x  (saved)",
+                depth, ns, ratio
+            );
+        }
+        return;
+    }
+
+    let baseline = match std::fs::read_to_string(&path) {
+        Ok(text) => parse_baseline(&text),
+        Err(_) => Vec::new(),
+    };
+    let mut regressed = false;
+    println!("depth {:>2}  {:>8.1} ns", DEPTHS[0], base);
+    for (depth, ns, ratio) in &ratios[1..] {
+        match baseline.iter().find(|(d, _)| d == depth) {
+            Some(&(_, expected)) => {
+                let change = ratio / expected - 1.0;
+                let verdict = if change > TOLERANCE {
+                    regressed = true;
+                    "REGRESSED"
+                } else {
+                    "ok"
+                };
+                println!(
+                    "depth {:>2}  {:>8.1} ns  {:>6.2}x  baseline {:>6.2}x  {:+6.1}%  {}",
+                    depth,
+                    ns,
+                    ratio,
+                    expected,
+                    change * 100.0,
+                    verdict
+                );
+            }
+            None => println!(
+                "depth {:>2}  {:>8.1} ns  {:>6.2}x  no baseline",
+                depth, ns, ratio
+            ),
+        }
+    }
+    if regressed {
+        eprintln!(
+            "Deep lookups are more than {:.0}% slower, relative to depth {}, than `{}`",
+            TOLERANCE * 100.0,
+            DEPTHS[0],
+            path.display()
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Nanoseconds per lookup of a global from a scope `depth` environments
+/// deep.
+fn measure(depth: usize) -> f64 {
+    let global = Symbol::intern("global");
+    let root = Env::new();
+    root.borrow_mut().define(global, Value::Int(1));
+    let mut env: Rc<RefCell<Env>> = root;
+    for i in 1..depth {
+        env = Env::child_env(env);
+        // Scopes usually hold a few names of their own.
+        env.borrow_mut()
+            .define(Symbol::intern(&format!("local{}", i)), Value::Int(0));
+    }
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..LOOKUPS {
+                black_box(env.borrow().get(black_box(global)));
+            }
+            start.elapsed().as_nanos() as f64 / LOOKUPS as f64
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// The `depth multiple` lines of a baseline file, skipping `#` comments.
+fn parse_baseline(text: &str) -> Vec<(usize, f64)> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (depth, ratio) = line.split_once(' ')?;
+            Some((depth.parse().ok()?, ratio.trim().parse().ok()?))
+        })
+        .collect()
+}
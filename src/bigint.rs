@@ -0,0 +1,486 @@
+use std::{cmp::Ordering, fmt};
+
+/// An arbitrary-precision integer, as made by `bigint()`.
+///
+/// The magnitude is stored as base 2^32 digits, least significant first,
+/// with no trailing zero digits. Zero is never negative.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(n: i64) -> Self {
+        let mut magnitude = n.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push(magnitude as u32);
+            magnitude >>= 32;
+        }
+        BigInt {
+            negative: n < 0,
+            digits,
+        }
+    }
+
+    /// Parses an optionally signed run of decimal digits.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut n = BigInt::default();
+        for chunk in digits.as_bytes().chunks(9) {
+            let chunk = std::str::from_utf8(chunk).unwrap();
+            mul_small(&mut n.digits, 10u32.pow(chunk.len() as u32));
+            add_small(&mut n.digits, chunk.parse().unwrap());
+        }
+        n.negative = negative;
+        Some(n.normalized())
+    }
+
+    /// The value as an `i64`, if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &d| (acc << 32) | d as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    /// The nearest float. Very large values become infinite.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &d| acc * 4294967296.0 + d as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn neg(&self) -> Self {
+        BigInt {
+            negative: !self.negative,
+            digits: self.digits.clone(),
+        }
+        .normalized()
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return BigInt {
+                negative: self.negative,
+                digits: add_mag(&self.digits, &other.digits),
+            };
+        }
+        match cmp_mag(&self.digits, &other.digits) {
+            Ordering::Less => BigInt {
+                negative: other.negative,
+                digits: sub_mag(&other.digits, &self.digits),
+            },
+            _ => BigInt {
+                negative: self.negative,
+                digits: sub_mag(&self.digits, &other.digits),
+            }
+            .normalized(),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        let mut digits = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let t = digits[i + j] as u64 + a as u64 * b as u64 + carry;
+                digits[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+        BigInt {
+            negative: self.negative != other.negative,
+            digits,
+        }
+        .normalized()
+    }
+
+    /// `self` raised to `exp`, by repeated squaring.
+    pub fn pow(&self, mut exp: u32) -> Self {
+        let mut base = self.clone();
+        let mut result = BigInt::from_i64(1);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+        result
+    }
+
+    /// Quotient and remainder rounded toward negative infinity, like `/`
+    /// and `%` on integers. Returns `None` when dividing by zero.
+    pub fn div_mod(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = div_mag(&self.digits, &other.digits);
+        let mut q = BigInt {
+            negative: self.negative != other.negative,
+            digits: q,
+        }
+        .normalized();
+        let mut r = BigInt {
+            negative: self.negative,
+            digits: r,
+        }
+        .normalized();
+        if !r.is_zero() && r.negative != other.negative {
+            q = q.sub(&BigInt::from_i64(1));
+            r = r.add(other);
+        }
+        Some((q, r))
+    }
+
+    fn normalized(mut self) -> Self {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        if self.digits.is_empty() {
+            self.negative = false;
+        }
+        self
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.digits, &other.digits),
+            (true, true) => cmp_mag(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Peel off nine decimal digits at a time.
+        let mut digits = self.digits.clone();
+        let mut chunks = Vec::new();
+        while !digits.is_empty() {
+            chunks.push(div_small(&mut digits, 1_000_000_000));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &d) in long.iter().enumerate() {
+        let t = d as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(t as u32);
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+/// `a - b` for `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &d) in a.iter().enumerate() {
+        let mut t = d as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (t < 0) as i64;
+        if t < 0 {
+            t += 1 << 32;
+        }
+        diff.push(t as u32);
+    }
+    while diff.last() == Some(&0) {
+        diff.pop();
+    }
+    diff
+}
+
+fn mul_small(digits: &mut Vec<u32>, factor: u32) {
+    let mut carry = 0u64;
+    for d in digits.iter_mut() {
+        let t = *d as u64 * factor as u64 + carry;
+        *d = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+fn add_small(digits: &mut Vec<u32>, n: u32) {
+    let mut carry = n as u64;
+    for d in digits.iter_mut() {
+        if carry == 0 {
+            return;
+        }
+        let t = *d as u64 + carry;
+        *d = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+/// Divides in place and returns the remainder.
+fn div_small(digits: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut rem = 0u64;
+    for d in digits.iter_mut().rev() {
+        let t = (rem << 32) | *d as u64;
+        *d = (t / divisor as u64) as u32;
+        rem = t % divisor as u64;
+    }
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    rem as u32
+}
+
+/// Schoolbook binary long division of magnitudes.
+fn div_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let mut q = a.to_vec();
+        let r = div_small(&mut q, b[0]);
+        let r = if r == 0 { Vec::new() } else { vec![r] };
+        return (q, r);
+    }
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+    for i in (0..a.len() * 32).rev() {
+        // r = r * 2 + bit i of a
+        let mut carry = (a[i / 32] >> (i % 32)) & 1;
+        for d in r.iter_mut() {
+            let next = *d >> 31;
+            *d = (*d << 1) | carry;
+            carry = next;
+        }
+        if carry > 0 {
+            r.push(carry);
+        }
+        if cmp_mag(&r, b) != Ordering::Less {
+            r = sub_mag(&r, b);
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+    while q.last() == Some(&0) {
+        q.pop();
+    }
+    (q, r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap()
+    }
+
+    /// Values around the digit boundaries, with their signs flipped too.
+    fn edges() -> Vec<i128> {
+        let positive = [
+            0,
+            1,
+            2,
+            9,
+            1_000_000_000,
+            u32::MAX as i128,
+            1 << 32,
+            (1 << 32) + 1,
+            i64::MAX as i128,
+            1 << 63,
+            u64::MAX as i128,
+            1 << 64,
+            (1 << 96) - 1,
+        ];
+        positive.iter().flat_map(|&n| [n, -n]).collect()
+    }
+
+    /// Quotient and remainder rounded toward negative infinity.
+    fn floor_div_mod(a: i128, b: i128) -> (i128, i128) {
+        let (q, r) = (a / b, a % b);
+        if r != 0 && (r < 0) != (b < 0) {
+            (q - 1, r + b)
+        } else {
+            (q, r)
+        }
+    }
+
+    #[test]
+    fn arithmetic_matches_i128_across_digit_boundaries() {
+        for a in edges() {
+            for b in edges() {
+                let (x, y) = (big(&a.to_string()), big(&b.to_string()));
+                if let Some(sum) = a.checked_add(b) {
+                    assert_eq!(x.add(&y).to_string(), sum.to_string(), "{} + {}", a, b);
+                }
+                if let Some(diff) = a.checked_sub(b) {
+                    assert_eq!(x.sub(&y).to_string(), diff.to_string(), "{} - {}", a, b);
+                }
+                if let Some(product) = a.checked_mul(b) {
+                    assert_eq!(x.mul(&y).to_string(), product.to_string(), "{} * {}", a, b);
+                }
+                if b != 0 {
+                    let (q, r) = floor_div_mod(a, b);
+                    let (bq, br) = x.div_mod(&y).unwrap();
+                    assert_eq!(
+                        (bq.to_string(), br.to_string()),
+                        (q.to_string(), r.to_string()),
+                        "{} div {}",
+                        a,
+                        b
+                    );
+                }
+                assert_eq!(x.cmp(&y), a.cmp(&b), "{} <=> {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn carries_and_borrows_cross_digits() {
+        let digit_max = BigInt::from_i64(u32::MAX as i64);
+        let one = BigInt::from_i64(1);
+        assert_eq!(digit_max.add(&one).to_string(), "4294967296");
+        assert_eq!(
+            big("18446744073709551615").add(&one).to_string(),
+            "18446744073709551616"
+        );
+        assert_eq!(
+            big("18446744073709551616").sub(&one).to_string(),
+            "18446744073709551615"
+        );
+        assert_eq!(
+            big("340282366920938463463374607431768211456")
+                .sub(&one)
+                .to_string(),
+            "340282366920938463463374607431768211455"
+        );
+        assert_eq!(
+            digit_max.mul(&digit_max).to_string(),
+            "18446744065119617025"
+        );
+        assert_eq!(
+            BigInt::from_i64(2).pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+    }
+
+    #[test]
+    fn division_by_multi_digit_divisors() {
+        let a = BigInt::from_i64(3).pow(100);
+        let b = BigInt::from_i64(7).pow(30);
+        let (q, r) = a.div_mod(&b).unwrap();
+        assert_eq!(q.mul(&b).add(&r), a);
+        assert!(r < b && !r.negative);
+        let (q, r) = a.neg().div_mod(&b).unwrap();
+        assert_eq!(q.mul(&b).add(&r), a.neg());
+        assert!(r < b && !r.negative);
+        assert_eq!(a.div_mod(&BigInt::default()), None);
+    }
+
+    #[test]
+    fn zero_is_never_negative() {
+        let zero = BigInt::default();
+        for n in [
+            big("-0"),
+            zero.neg(),
+            BigInt::from_i64(-5).add(&BigInt::from_i64(5)),
+            BigInt::from_i64(-5).mul(&zero),
+            BigInt::from_i64(-6)
+                .div_mod(&BigInt::from_i64(3))
+                .unwrap()
+                .1,
+            BigInt::from_i64(-2)
+                .div_mod(&BigInt::from_i64(3))
+                .unwrap()
+                .0
+                .add(&BigInt::from_i64(1)),
+        ] {
+            assert_eq!(n, zero);
+            assert_eq!(n.to_string(), "0");
+        }
+    }
+
+    #[test]
+    fn to_i64_fits_exactly_the_i64_range() {
+        assert_eq!(big("9223372036854775807").to_i64(), Some(i64::MAX));
+        assert_eq!(big("-9223372036854775808").to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-9223372036854775809").to_i64(), None);
+        assert_eq!(big("18446744073709551616").to_i64(), None);
+        assert_eq!(
+            BigInt::from_i64(i64::MIN).to_string(),
+            "-9223372036854775808"
+        );
+    }
+
+    #[test]
+    fn parse_takes_only_signed_digits() {
+        assert_eq!(big("+12").to_string(), "12");
+        assert_eq!(
+            big("-000123456789012345678901").to_string(),
+            "-123456789012345678901"
+        );
+        for text in ["", "-", "+", "1a", "+-1", "1.5", " 1"] {
+            assert_eq!(BigInt::parse(text), None, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use crate::std_fn::Capability;
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+static QUIET_INPUT: AtomicBool = AtomicBool::new(false);
+static MATH: AtomicBool = AtomicBool::new(false);
+static ALLOW_SHADOW_BUILTINS: AtomicBool = AtomicBool::new(false);
+static GRANTED: AtomicU8 = AtomicU8::new(u8::MAX);
+static MAX_NESTING: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_NESTING);
+static MAX_RECURSION: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_RECURSION);
+static WARNED_PRECISION: AtomicBool = AtomicBool::new(false);
+
+/// How deeply expressions and blocks may nest unless `--max-nesting`
+/// says otherwise. Deeper programs are a syntax error rather than a
+/// stack overflow in the parser or interpreter.
+pub const DEFAULT_MAX_NESTING: usize = 256;
+
+/// How many calls of riku functions may be running at once unless
+/// `--max-recursion` says otherwise. Deeper recursion is a runtime error
+/// rather than a stack overflow.
+pub const DEFAULT_MAX_RECURSION: usize = 1000;
+
+/// Turns strict mode on or off for the whole process. In strict mode the
+/// interpreter refuses implicit conversions instead of reporting them and
+/// carrying on with a default value.
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+pub fn strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Turns math mode on or off, for `--math`. In math mode the lexer reads
+/// `^` as a power and the parser reads a `!` after an operand as a
+/// factorial and `|x|` as an absolute value, so `|a| expr` lambdas must
+/// be written with `fn`.
+pub fn set_math(math: bool) {
+    MATH.store(math, Ordering::Relaxed);
+}
+
+pub fn math() -> bool {
+    MATH.load(Ordering::Relaxed)
+}
+
+/// Sets how many levels of nested expressions and blocks the parser
+/// accepts, for `--max-nesting`.
+pub fn set_max_nesting(depth: usize) {
+    MAX_NESTING.store(depth, Ordering::Relaxed);
+}
+
+pub fn max_nesting() -> usize {
+    MAX_NESTING.load(Ordering::Relaxed)
+}
+
+/// Sets how many nested calls of riku functions are allowed, for
+/// `--max-recursion`.
+pub fn set_max_recursion(depth: usize) {
+    MAX_RECURSION.store(depth, Ordering::Relaxed);
+}
+
+pub fn max_recursion() -> usize {
+    MAX_RECURSION.load(Ordering::Relaxed)
+}
+
+/// Turns off the parser's warning for a variable, parameter or function
+/// that hides a builtin, for `--allow-shadow-builtins`.
+pub fn set_allow_shadow_builtins(allow: bool) {
+    ALLOW_SHADOW_BUILTINS.store(allow, Ordering::Relaxed);
+}
+
+pub fn allow_shadow_builtins() -> bool {
+    ALLOW_SHADOW_BUILTINS.load(Ordering::Relaxed)
+}
+
+/// When on, `input()` does not print its prompt if standard output is not a
+/// terminal, so piped output only holds what the script prints itself.
+pub fn set_quiet_input(quiet: bool) {
+    QUIET_INPUT.store(quiet, Ordering::Relaxed);
+}
+
+pub fn quiet_input() -> bool {
+    QUIET_INPUT.load(Ordering::Relaxed)
+}
+
+/// Limits builtins to the given capabilities, as declared by a script's
+/// `//! requires:` header. Everything is granted until this is called.
+pub fn grant_only(capabilities: &[Capability]) {
+    let mask = capabilities.iter().fold(0, |mask, c| mask | bit(*c));
+    GRANTED.store(mask, Ordering::Relaxed);
+}
+
+/// Whether builtins needing `capability` may run. Pure builtins always can.
+pub fn granted(capability: Capability) -> bool {
+    capability == Capability::Pure || GRANTED.load(Ordering::Relaxed) & bit(capability) != 0
+}
+
+/// Forgets the warnings given by the previous run, so that each script,
+/// REPL input or loaded file gets its own once-only warnings.
+pub fn start_run() {
+    WARNED_PRECISION.store(false, Ordering::Relaxed);
+}
+
+/// Whether the warning about lost integer precision is still due in this
+/// run. It is given once per run, so this also marks it as given.
+pub fn first_precision_warning() -> bool {
+    !WARNED_PRECISION.swap(true, Ordering::Relaxed)
+}
+
+fn bit(capability: Capability) -> u8 {
+    1 << capability as u8
+}
@@ -0,0 +1,66 @@
+use std::{
+    io::{IsTerminal, stderr},
+    sync::OnceLock,
+};
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether error output may use ANSI colors: stderr is a terminal, `NO_COLOR`
+/// is unset and the console understands escape sequences.
+pub fn color_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none() && enable_ansi()
+    })
+}
+
+/// Wraps `text` in red when colors are enabled.
+pub fn red(text: &str) -> String {
+    paint(RED, text)
+}
+
+/// Wraps `text` in yellow when colors are enabled.
+pub fn yellow(text: &str) -> String {
+    paint(YELLOW, text)
+}
+
+fn paint(color: &str, text: &str) -> String {
+    if color_enabled() {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(not(windows))]
+fn enable_ansi() -> bool {
+    true
+}
+
+/// Windows 10 and later interpret escape sequences once virtual terminal
+/// processing is switched on; older consoles refuse and get plain text.
+#[cfg(windows)]
+fn enable_ansi() -> bool {
+    use std::ffi::c_void;
+
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    unsafe extern "system" {
+        fn GetStdHandle(handle: u32) -> *mut c_void;
+        fn GetConsoleMode(handle: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(handle: *mut c_void, mode: u32) -> i32;
+    }
+
+    unsafe {
+        let handle = GetStdHandle(STD_ERROR_HANDLE);
+        let mut mode = 0;
+        if handle.is_null() || GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
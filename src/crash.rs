@@ -0,0 +1,152 @@
+use std::{
+    any::Any,
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+    process,
+};
+
+use crate::{error::exit, output::flush_stdout, stmt::Stmt};
+
+/// How many of the statements run last a crash report lists.
+const TRAIL_LEN: usize = 8;
+
+const ISSUES_URL: &str = "https://github.com/Sanu-2004/riku/issues";
+
+/// The line and kind of the last statements run, as a ring that `record`
+/// overwrites in turn, and how many statements were recorded. It is
+/// written for every statement, so it is kept in fixed storage.
+struct Trail {
+    entries: [Cell<(usize, &'static str)>; TRAIL_LEN],
+    count: Cell<usize>,
+}
+
+thread_local! {
+    static TRAIL: Trail = const {
+        Trail {
+            entries: [const { Cell::new((0, "")) }; TRAIL_LEN],
+            count: Cell::new(0),
+        }
+    };
+    /// Whether this thread runs under [`guard`], whose panics it reports.
+    static GUARDED: Cell<bool> = const { Cell::new(false) };
+    /// The message and place of the panic, and the backtrace if
+    /// `RUST_BACKTRACE` asks for one, kept by the panic hook for the report.
+    static PANIC: RefCell<Option<(String, Option<String>)>> = const { RefCell::new(None) };
+    /// The script file being run, if there is one.
+    static SCRIPT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Notes that `stmt` is about to run. Blocks are left out, since the
+/// statements in them are listed, and so are statements without a line.
+#[inline]
+pub fn record(stmt: &Stmt) {
+    if let Stmt::Group(_) = stmt {
+        return;
+    }
+    if let Some(line) = stmt.line() {
+        TRAIL.with(|trail| {
+            let count = trail.count.get();
+            trail.entries[count % TRAIL_LEN].set((line, stmt.kind()));
+            trail.count.set(count + 1);
+        });
+    }
+}
+
+/// Names the file whose statements are recorded, so a report can quote
+/// their lines.
+pub fn set_script(path: &str) {
+    SCRIPT.with(|script| *script.borrow_mut() = Some(path.to_string()));
+}
+
+/// Runs `run`, turning a panic, which is always a bug in the interpreter,
+/// into a report that says so and asks for a bug report, instead of a
+/// Rust backtrace. Exits with `SOFTWARE` after a panic.
+pub fn guard(run: impl FnOnce()) {
+    GUARDED.set(true);
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !GUARDED.get() {
+            default_hook(info);
+            return;
+        }
+        let location = info
+            .location()
+            .map_or(String::new(), |l| format!(" at {}:{}", l.file(), l.line()));
+        let detail = format!("{}{}", message(info.payload()), location);
+        let backtrace = Backtrace::capture();
+        let backtrace =
+            (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string());
+        PANIC.with(|panic| *panic.borrow_mut() = Some((detail, backtrace)));
+    }));
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(run)) {
+        let (detail, backtrace) = PANIC
+            .with(|panic| panic.borrow_mut().take())
+            .unwrap_or_else(|| (message(payload.as_ref()), None));
+        report(&detail, backtrace.as_deref());
+        process::exit(exit::SOFTWARE);
+    }
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn report(detail: &str, backtrace: Option<&str>) {
+    flush_stdout();
+    let script = SCRIPT.with(|script| script.borrow().clone());
+    let source = script
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok());
+    let trail = TRAIL.with(|trail| {
+        let count = trail.count.get();
+        (count.saturating_sub(TRAIL_LEN)..count)
+            .map(|i| trail.entries[i % TRAIL_LEN].get())
+            .collect::<Vec<_>>()
+    });
+
+    eprintln!();
+    eprintln!("riku crashed. This is a bug in the interpreter, not in your script.");
+    eprintln!();
+    if let Some(path) = &script {
+        match trail.last() {
+            Some((line, _)) => eprintln!("  while running {}, line {}", path, line),
+            None => eprintln!("  while running {}", path),
+        }
+    }
+    eprintln!(
+        "  riku {} ({})",
+        env!("CARGO_PKG_VERSION"),
+        env!("RIKU_GIT_HASH")
+    );
+    eprintln!("  internal error: {}", detail);
+    if !trail.is_empty() {
+        eprintln!();
+        eprintln!("The last statements run, most recent last:");
+        for (line, kind) in &trail {
+            let text = source
+                .as_ref()
+                .zip(line.checked_sub(1))
+                .and_then(|(source, i)| source.lines().nth(i))
+                .map_or(String::new(), |text| format!("  {}", text.trim()));
+            eprintln!("  line {:>4}  {:<10}{}", line, kind, text);
+        }
+    }
+    eprintln!();
+    eprintln!(
+        "Please report it at {} with everything above and, if you can, the \
+         script or a smaller one that crashes the same way.",
+        ISSUES_URL
+    );
+    if let Some(backtrace) = backtrace {
+        eprintln!();
+        eprintln!("Backtrace:");
+        eprint!("{}", backtrace);
+    }
+}
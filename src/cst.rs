@@ -0,0 +1,253 @@
+use std::fmt;
+
+use crate::{
+    source::Source,
+    token::{Span, TokenType},
+};
+
+/// Source text that carries no meaning for evaluation but must survive a
+/// lossless round trip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    Whitespace(String),
+    Comment(String),
+}
+
+impl Trivia {
+    pub fn text(&self) -> &str {
+        match self {
+            Trivia::Whitespace(s) | Trivia::Comment(s) => s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstToken {
+    pub token_type: TokenType,
+    pub text: String,
+    pub line: usize,
+    pub span: Span,
+    pub leading: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Root,
+    Stmt,
+    Block,
+    Group,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    Node(Node),
+    Token(CstToken),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Self {
+        Node {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn tokens(&self) -> Vec<&CstToken> {
+        let mut tokens = Vec::new();
+        for child in &self.children {
+            match child {
+                Element::Node(node) => tokens.extend(node.tokens()),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            for trivia in &token.leading {
+                write!(f, "{}", trivia.text())?;
+            }
+            write!(f, "{}", token.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `input` into a lossless concrete syntax tree. Printing the
+/// returned root reproduces `input` byte for byte.
+pub fn parse(input: &str) -> Node {
+    let mut source = Source::new(input.to_string());
+    source.tokenize();
+    let tokens = source
+        .get_tokens()
+        .iter()
+        .scan(0, |prev_end, token| {
+            let leading = trivia(&input[*prev_end..token.span.start]);
+            *prev_end = token.span.end;
+            Some(CstToken {
+                token_type: token.token_type,
+                text: input[token.span.start..token.span.end].to_string(),
+                line: token.line,
+                span: token.span,
+                leading,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut stack = vec![Node::new(NodeKind::Root), Node::new(NodeKind::Stmt)];
+    for token in tokens {
+        match token.token_type {
+            TokenType::LBrace | TokenType::LParen => {
+                let kind = if token.token_type == TokenType::LBrace {
+                    NodeKind::Block
+                } else {
+                    NodeKind::Group
+                };
+                let mut node = Node::new(kind);
+                node.children.push(Element::Token(token));
+                stack.push(node);
+                if kind == NodeKind::Block {
+                    stack.push(Node::new(NodeKind::Stmt));
+                }
+            }
+            TokenType::RBrace | TokenType::RParen => {
+                let kind = if token.token_type == TokenType::RBrace {
+                    NodeKind::Block
+                } else {
+                    NodeKind::Group
+                };
+                if kind == NodeKind::Block {
+                    close_stmt(&mut stack);
+                }
+                if stack.last().map(|n| n.kind) == Some(kind) {
+                    let mut node = stack.pop().unwrap();
+                    node.children.push(Element::Token(token));
+                    stack.last_mut().unwrap().children.push(Element::Node(node));
+                } else {
+                    // Unbalanced closer: keep it in place so the text survives.
+                    push_token(&mut stack, token);
+                }
+            }
+            TokenType::EOL => {
+                push_token(&mut stack, token);
+                if stack.last().map(|n| n.kind) == Some(NodeKind::Stmt) {
+                    close_stmt(&mut stack);
+                    stack.push(Node::new(NodeKind::Stmt));
+                }
+            }
+            TokenType::EOF => {
+                while stack.len() > 1 {
+                    close_stmt(&mut stack);
+                    if stack.len() > 1 {
+                        let node = stack.pop().unwrap();
+                        stack.last_mut().unwrap().children.push(Element::Node(node));
+                    }
+                }
+                stack[0].children.push(Element::Token(token));
+            }
+            _ => push_token(&mut stack, token),
+        }
+    }
+    stack.pop().unwrap()
+}
+
+fn push_token(stack: &mut [Node], token: CstToken) {
+    stack
+        .last_mut()
+        .unwrap()
+        .children
+        .push(Element::Token(token));
+}
+
+fn close_stmt(stack: &mut Vec<Node>) {
+    if stack.last().map(|n| n.kind) != Some(NodeKind::Stmt) {
+        return;
+    }
+    let stmt = stack.pop().unwrap();
+    if !stmt.children.is_empty() {
+        stack.last_mut().unwrap().children.push(Element::Node(stmt));
+    }
+}
+
+fn trivia(text: &str) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with("//") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            trivia.push(Trivia::Comment(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let end = rest.find("//").unwrap_or(rest.len());
+            trivia.push(Trivia::Whitespace(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+    }
+    trivia
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn assert_round_trip(input: &str) {
+        assert_eq!(parse(input).to_string(), input);
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut checked = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            // The `riku` binaries in examples/ are not text.
+            let Ok(source) = fs::read_to_string(&path) else {
+                continue;
+            };
+            assert_eq!(parse(&source).to_string(), source, "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn comments_round_trip() {
+        assert_round_trip("// leading\nlet x = 1 // trailing\n// last, no newline");
+        assert_round_trip("if x {\n    // inside\n}\n//\n");
+    }
+
+    #[test]
+    fn whitespace_round_trips() {
+        assert_round_trip("\tlet x =\t1\n\t\tprintln(x)  \n");
+        assert_round_trip("let x = 1   \n\n\n   \n");
+        assert_round_trip("  \n");
+        assert_round_trip("");
+    }
+
+    #[test]
+    fn crlf_round_trips() {
+        assert_round_trip("let x = 1\r\nif x {\r\n    println(x) // one\r\n}\r\n");
+    }
+
+    #[test]
+    fn string_escapes_round_trip() {
+        assert_round_trip(r#"println("say \"hi\"", "\\", "a\$b", "\n\t")"#);
+        assert_round_trip("let s = \"// not a comment\"\n");
+    }
+
+    #[test]
+    fn unbalanced_closers_round_trip() {
+        assert_round_trip("println(1))\n}\n");
+    }
+}
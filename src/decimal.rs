@@ -0,0 +1,445 @@
+use std::{cmp::Ordering, fmt, ops::Neg};
+
+/// Most digits kept after the point. Division stops here when the result
+/// does not terminate, like `1 / 3`.
+const MAX_SCALE: u32 = 28;
+
+/// An exact decimal number, as made by `decimal("0.1")`: `mantissa / 10^scale`.
+///
+/// The mantissa holds up to 38 significant digits; arithmetic that needs
+/// more returns `None`. Trailing zeros are kept, so `decimal("1.50")` prints
+/// as `1.50`, and like other numbers decimals compare by value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn from_i64(n: i64) -> Self {
+        Decimal {
+            mantissa: n as i128,
+            scale: 0,
+        }
+    }
+
+    /// Parses an optionally signed decimal such as `-12.50`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let digits = format!("{}{}", whole, fraction);
+        if digits.is_empty()
+            || !digits.bytes().all(|b| b.is_ascii_digit())
+            || fraction.len() > MAX_SCALE as usize
+        {
+            return None;
+        }
+        let mantissa = digits.parse::<i128>().ok()?;
+        Some(Decimal {
+            mantissa: if negative { -mantissa } else { mantissa },
+            scale: fraction.len() as u32,
+        })
+    }
+
+    /// The decimal a float is written as, so `0.1` becomes exactly `0.1`.
+    pub fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() {
+            return None;
+        }
+        Decimal::parse(&n.to_string())
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let (l, r, scale) = align(self, other)?;
+        Some(Decimal {
+            mantissa: l.checked_add(r)?,
+            scale,
+        })
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(-other)
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        let product = Decimal {
+            mantissa: self.mantissa.checked_mul(other.mantissa)?,
+            scale: self.scale + other.scale,
+        };
+        Some(product.round(MAX_SCALE))
+    }
+
+    /// `self` raised to `exp`, by repeated squaring. `None` on overflow.
+    pub fn checked_pow(self, mut exp: u32) -> Option<Self> {
+        let mut base = self;
+        let mut result = Decimal::from_i64(1);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.checked_mul(base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.checked_mul(base)?;
+            }
+        }
+        Some(result)
+    }
+
+    /// The quotient with as many digits as needed, up to `MAX_SCALE`, and
+    /// the last digit rounded half to even. `None` for a zero divisor or an
+    /// overflow.
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        for scale in (0..=MAX_SCALE).rev() {
+            // quotient * 10^scale = self.mantissa * 10^shift / other.mantissa
+            let shift = scale as i64 + other.scale as i64 - self.scale as i64;
+            let (num, den) = if shift >= 0 {
+                (
+                    pow10(shift as u32).and_then(|p| self.mantissa.checked_mul(p)),
+                    Some(other.mantissa),
+                )
+            } else {
+                (
+                    Some(self.mantissa),
+                    pow10((-shift) as u32).and_then(|p| other.mantissa.checked_mul(p)),
+                )
+            };
+            let (Some(num), Some(den)) = (num, den) else {
+                continue;
+            };
+            let mantissa = round_half_even(num / den, num % den, den);
+            return Some(Decimal { mantissa, scale }.trimmed());
+        }
+        None
+    }
+
+    /// The quotient rounded toward negative infinity, as a whole number.
+    pub fn checked_floor_div(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let (l, r, _) = align(self, other)?;
+        let q = l.checked_div(r)?;
+        let mantissa = if l % r != 0 && (l < 0) != (r < 0) {
+            q - 1
+        } else {
+            q
+        };
+        Some(Decimal { mantissa, scale: 0 })
+    }
+
+    /// The remainder of `/` rounded toward negative infinity, so it has the
+    /// sign of the divisor like `%` on integers.
+    pub fn checked_rem(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let (l, r, scale) = align(self, other)?;
+        let mut m = l.checked_rem(r)?;
+        if m != 0 && (m < 0) != (r < 0) {
+            m += r;
+        }
+        Some(Decimal { mantissa: m, scale })
+    }
+
+    /// Rounds half to even to at most `places` digits after the point.
+    pub fn round(self, places: u32) -> Self {
+        if self.scale <= places {
+            return self;
+        }
+        let divisor = pow10(self.scale - places).unwrap_or(i128::MAX);
+        Decimal {
+            mantissa: round_half_even(self.mantissa / divisor, self.mantissa % divisor, divisor),
+            scale: places,
+        }
+    }
+
+    /// The value with exactly `places` digits after the point.
+    pub fn to_fixed(self, places: u32) -> String {
+        let rounded = self.round(places);
+        match pow10(places - rounded.scale).and_then(|p| rounded.mantissa.checked_mul(p)) {
+            Some(mantissa) => Decimal {
+                mantissa,
+                scale: places,
+            }
+            .to_string(),
+            None => rounded.to_string(),
+        }
+    }
+
+    /// Rounds toward negative infinity.
+    pub fn floor(self) -> i128 {
+        let divisor = pow10(self.scale).unwrap_or(i128::MAX);
+        let q = self.mantissa / divisor;
+        if self.mantissa % divisor < 0 {
+            q - 1
+        } else {
+            q
+        }
+    }
+
+    fn trimmed(mut self) -> Self {
+        while self.scale > 0 && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+
+    /// The whole part and the fraction scaled to `MAX_SCALE` digits, which
+    /// order decimals without risking overflow.
+    fn parts(self) -> (i128, i128) {
+        let divisor = pow10(self.scale).unwrap();
+        let fraction = self.mantissa % divisor * pow10(MAX_SCALE - self.scale).unwrap();
+        (self.mantissa / divisor, fraction)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Decimal {
+            mantissa: -self.mantissa,
+            ..self
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.parts().cmp(&other.parts())
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!(
+            "{:0>width$}",
+            self.mantissa.unsigned_abs(),
+            width = self.scale as usize + 1
+        );
+        let (whole, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        if self.mantissa < 0 {
+            write!(f, "-")?;
+        }
+        if fraction.is_empty() {
+            write!(f, "{}", whole)
+        } else {
+            write!(f, "{}.{}", whole, fraction)
+        }
+    }
+}
+
+fn pow10(n: u32) -> Option<i128> {
+    10i128.checked_pow(n)
+}
+
+/// Both mantissas at the larger of the two scales.
+fn align(a: Decimal, b: Decimal) -> Option<(i128, i128, u32)> {
+    let scale = a.scale.max(b.scale);
+    let l = a.mantissa.checked_mul(pow10(scale - a.scale)?)?;
+    let r = b.mantissa.checked_mul(pow10(scale - b.scale)?)?;
+    Some((l, r, scale))
+}
+
+/// Rounds a truncated quotient using its remainder: halves go to the even
+/// neighbour, so repeated rounding does not drift.
+fn round_half_even(quotient: i128, remainder: i128, divisor: i128) -> i128 {
+    let twice = remainder.unsigned_abs() * 2;
+    let divisor_abs = divisor.unsigned_abs();
+    let away = match twice.cmp(&divisor_abs) {
+        Ordering::Greater => true,
+        Ordering::Equal => quotient % 2 != 0,
+        Ordering::Less => false,
+    };
+    if !away {
+        return quotient;
+    }
+    if (remainder < 0) != (divisor < 0) {
+        quotient - 1
+    } else {
+        quotient + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(text: &str) -> Decimal {
+        Decimal::parse(text).unwrap()
+    }
+
+    /// Applies `op` to the decimals written `a` and `b` and prints the result.
+    fn show(a: &str, b: &str, op: fn(Decimal, Decimal) -> Option<Decimal>) -> String {
+        op(dec(a), dec(b)).unwrap().to_string()
+    }
+
+    #[test]
+    fn parse_keeps_the_written_scale() {
+        for (text, shown) in [
+            ("1.50", "1.50"),
+            ("-12.5", "-12.5"),
+            ("+3", "3"),
+            (".5", "0.5"),
+            ("1.", "1"),
+            ("-0.000", "0.000"),
+            (
+                "0.1000000000000000000000000001",
+                "0.1000000000000000000000000001",
+            ),
+        ] {
+            assert_eq!(dec(text).to_string(), shown, "{}", text);
+        }
+        for text in [
+            "",
+            "-",
+            ".",
+            "1.2.3",
+            "1e5",
+            "--1",
+            "0.12345678901234567890123456789",
+        ] {
+            assert_eq!(
+                Decimal::parse(text).map(|d| d.to_string()),
+                None,
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn addition_and_multiplication_keep_the_larger_scale() {
+        assert_eq!(show("1.50", "1", Decimal::checked_add), "2.50");
+        assert_eq!(show("0.1", "0.2", Decimal::checked_add), "0.3");
+        assert_eq!(show("1.5", "-1.50", Decimal::checked_add), "0.00");
+        assert_eq!(show("-0.1", "0.25", Decimal::checked_sub), "-0.35");
+        assert_eq!(show("1.5", "1.5", Decimal::checked_mul), "2.25");
+        assert_eq!(show("-0.5", "0.2", Decimal::checked_mul), "-0.10");
+    }
+
+    #[test]
+    fn comparison_is_by_value_and_sign() {
+        assert_eq!(dec("1.50"), dec("1.5"));
+        assert_eq!(dec("-0.0"), dec("0"));
+        assert!(dec("-1.5") < dec("-1.2"));
+        assert!(dec("-0.5") < dec("0.2"));
+        assert!(dec("-1") < dec("-0.999"));
+        assert!(dec("10") > dec("9.9999999999999999999999999999"));
+    }
+
+    #[test]
+    fn division_stops_at_the_largest_scale_and_rounds_half_to_even() {
+        assert_eq!(show("1", "8", Decimal::checked_div), "0.125");
+        assert_eq!(show("10", "4", Decimal::checked_div), "2.5");
+        assert_eq!(show("1.50", "1", Decimal::checked_div), "1.5");
+        assert_eq!(
+            show("1", "3", Decimal::checked_div),
+            "0.3333333333333333333333333333"
+        );
+        assert_eq!(
+            show("2", "3", Decimal::checked_div),
+            "0.6666666666666666666666666667"
+        );
+        assert_eq!(
+            show("-2", "3", Decimal::checked_div),
+            "-0.6666666666666666666666666667"
+        );
+        assert_eq!(
+            show("2", "-3", Decimal::checked_div),
+            "-0.6666666666666666666666666667"
+        );
+        assert_eq!(show("-1", "-8", Decimal::checked_div), "0.125");
+        assert_eq!(show("0.001", "0.1", Decimal::checked_div), "0.01");
+        assert_eq!(dec("1").checked_div(dec("0.00")), None);
+    }
+
+    #[test]
+    fn floor_division_and_remainder_follow_the_divisor_sign() {
+        for (a, b, q, r) in [
+            ("7.5", "2", "3", "1.5"),
+            ("-7.5", "2", "-4", "0.5"),
+            ("7.5", "-2", "-4", "-0.5"),
+            ("-7.5", "-2", "3", "-1.5"),
+            ("6", "1.5", "4", "0.0"),
+        ] {
+            assert_eq!(show(a, b, Decimal::checked_floor_div), q, "{} div {}", a, b);
+            assert_eq!(show(a, b, Decimal::checked_rem), r, "{} mod {}", a, b);
+        }
+        assert_eq!(dec("1").checked_floor_div(dec("0")), None);
+        assert_eq!(dec("1").checked_rem(dec("0")), None);
+    }
+
+    #[test]
+    fn rounding_goes_half_to_even() {
+        for (text, places, shown) in [
+            ("2.5", 0, "2"),
+            ("3.5", 0, "4"),
+            ("-2.5", 0, "-2"),
+            ("-3.5", 0, "-4"),
+            ("2.45", 1, "2.4"),
+            ("2.451", 1, "2.5"),
+            ("-2.449", 2, "-2.45"),
+            ("1.5", 3, "1.5"),
+        ] {
+            assert_eq!(
+                dec(text).round(places).to_string(),
+                shown,
+                "{} to {}",
+                text,
+                places
+            );
+        }
+        assert_eq!(dec("2.5").to_fixed(3), "2.500");
+        assert_eq!(dec("-0.125").to_fixed(2), "-0.12");
+    }
+
+    #[test]
+    fn floor_rounds_toward_negative_infinity() {
+        for (text, floor) in [
+            ("2.7", 2),
+            ("-2.7", -3),
+            ("-2", -2),
+            ("-0.001", -1),
+            ("0.999", 0),
+        ] {
+            assert_eq!(dec(text).floor(), floor, "{}", text);
+        }
+    }
+
+    #[test]
+    fn overflow_is_none() {
+        let big = dec("99999999999999999999999999999999999999");
+        let max = dec(&i128::MAX.to_string());
+        assert_eq!(max.checked_add(dec("1")), None);
+        assert_eq!(dec("0.1").checked_add(max), None);
+        assert_eq!(big.checked_mul(dec("10")), None);
+        assert_eq!(dec("10").checked_pow(40), None);
+        assert_eq!(dec("1.1").checked_pow(2).unwrap().to_string(), "1.21");
+    }
+}
@@ -1,19 +1,53 @@
-use std::{cell::RefCell, collections::HashMap, fmt, process, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    rc::Rc,
+};
 
+#[cfg(feature = "bigint")]
+use crate::bigint::BigInt;
 use crate::{
-    error::{ErrorType, error},
+    decimal::Decimal,
+    error::{ErrorType, fatal, line_fatal},
+    expr::call_value,
+    plugin::Plugin,
+    stats,
     std_fn::std_fn,
-    stmt::Stmt,
+    stmt::{Method, Param, Stmt},
+    symbol::Symbol,
+    types::Type,
 };
 
 #[derive(Debug, Clone)]
 pub enum Value {
+    Int(i64),
     Number(f64),
     Bool(bool),
     String(String),
+    List(Rc<RefCell<Vec<Value>>>),
+    /// A fixed, immutable group of values, `(a, b)`.
+    Tuple(Rc<[Value]>),
+    Decimal(Rc<Decimal>),
+    #[cfg(feature = "bigint")]
+    BigInt(Rc<BigInt>),
+    Map(Rc<RefCell<BTreeMap<String, Value>>>),
+    /// An instance of a `struct` declaration. Like lists and maps it is
+    /// shared, so assigning to a field is seen through every reference.
+    Struct(Rc<RefCell<Struct>>),
+    StructDef(Rc<StructDef>),
+    /// A method read from an instance, `counter.inc`, which remembers the
+    /// instance to pass as `self`.
+    BoundMethod {
+        receiver: Rc<RefCell<Struct>>,
+        name: Symbol,
+    },
+    /// A riku function. The parameters are shared between copies of it,
+    /// which keeps `Value` at 64 bytes.
     Function {
         name: String,
-        params: Vec<String>,
+        params: Rc<[Param]>,
+        returns: Option<Type>,
         body: Box<Stmt>,
         closure: Rc<RefCell<Env>>,
     },
@@ -21,25 +55,467 @@ pub enum Value {
         name: String,
         body: fn(Vec<Value>) -> Value,
     },
+    FuncPlugin {
+        name: String,
+        plugin: Rc<Plugin>,
+    },
     Nil,
 }
 
+/// How many significant digits a float is printed with. Fewer than the
+/// 17 that identify every float, so results such as `0.1 + 0.2` print as
+/// `0.3` rather than showing the error of binary fractions.
+const DISPLAY_DIGITS: i32 = 15;
+
+/// A float as riku prints it: whole numbers without a fraction, others
+/// with up to `DISPLAY_DIGITS` significant digits and no trailing zeros,
+/// and very large or small magnitudes with an exponent, as in `1e21`.
+/// `to_fixed` and format specs give exact control.
+fn display_float(n: f64) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    let magnitude = n.abs();
+    if (1e-5..1e16).contains(&magnitude) {
+        let integer_digits = magnitude.log10().floor() as i32 + 1;
+        let decimals = (DISPLAY_DIGITS - integer_digits).max(0) as usize;
+        trim_fraction(format!("{:.*}", decimals, n))
+    } else {
+        let text = format!("{:.*e}", DISPLAY_DIGITS as usize - 1, n);
+        let (mantissa, exponent) = text.split_once('e').unwrap();
+        format!("{}e{}", trim_fraction(mantissa.to_string()), exponent)
+    }
+}
+
+/// `1.2500` as `1.25` and `3.000` as `3`.
+fn trim_fraction(mut text: String) -> String {
+    if text.contains('.') {
+        text.truncate(text.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    text
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_value(f, &mut Vec::new())
+    }
+}
+
+impl Value {
+    /// The numeric value as a float, for arithmetic that mixes integers
+    /// and floats.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Number(n) => Some(*n),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => Some(n.to_f64()),
+            _ => None,
+        }
+    }
+
+    /// The name of the value's type, as written in type patterns.
+    pub fn type_name(&self) -> &'static str {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
-            Value::Bool(b) => write!(f, "{}", b),
-            Value::String(s) => write!(f, "{}", s),
-            Value::Nil => write!(f, "nil"),
-            Value::Function { name, .. } => write!(f, "<function {}>", name),
-            Value::FuncBuiltIn { name, .. } => write!(f, "<builtin function {}>", name),
+            Value::Int(_) | Value::Number(_) => "number",
+            #[cfg(feature = "bigint")]
+            Value::BigInt(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Decimal(_) => "decimal",
+            Value::Map(_) => "map",
+            Value::Struct(_) => "struct",
+            Value::StructDef(_) => "type",
+            Value::Function { .. }
+            | Value::FuncBuiltIn { .. }
+            | Value::FuncPlugin { .. }
+            | Value::BoundMethod { .. } => "function",
+            Value::Nil => "nil",
         }
     }
+
+    /// Integers and decimals as decimals, for arithmetic with a `decimal()`
+    /// operand. Floats are left out so they never mix in silently.
+    pub fn to_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Int(n) => Some(Decimal::from_i64(*n)),
+            Value::Decimal(n) => Some(**n),
+            _ => None,
+        }
+    }
+
+    /// Integers as arbitrary-precision values, for arithmetic with a
+    /// `bigint()` operand.
+    #[cfg(feature = "bigint")]
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(n) => Some(BigInt::from_i64(*n)),
+            Value::BigInt(n) => Some((**n).clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the value counts as true in a condition, with `!`, `&` and
+    /// `|`, and for `bool()`. `nil`, `false`, numbers that are not
+    /// positive, and empty strings and collections are false; everything
+    /// else is true.
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Nil => false,
+            Value::Int(n) => *n > 0,
+            Value::Number(n) => *n > 0.0,
+            Value::Decimal(n) => **n > Decimal::default(),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => **n > BigInt::default(),
+            Value::String(s) => !s.is_empty(),
+            Value::List(items) => !items.borrow().is_empty(),
+            Value::Tuple(items) => !items.is_empty(),
+            Value::Map(map) => !map.borrow().is_empty(),
+            Value::Struct(_)
+            | Value::StructDef(_)
+            | Value::BoundMethod { .. }
+            | Value::Function { .. }
+            | Value::FuncBuiltIn { .. }
+            | Value::FuncPlugin { .. } => true,
+        }
+    }
+
+    /// Builtin and plugin functions, which scripts cannot redefine globally.
+    pub fn is_builtin(&self) -> bool {
+        matches!(self, Value::FuncBuiltIn { .. } | Value::FuncPlugin { .. })
+    }
+
+    /// Address of the shared storage behind a list or map, used to spot
+    /// collections that contain themselves.
+    fn shared_ptr(&self) -> Option<*const ()> {
+        match self {
+            Value::List(items) => Some(Rc::as_ptr(items) as *const ()),
+            Value::Map(map) => Some(Rc::as_ptr(map) as *const ()),
+            Value::Struct(instance) => Some(Rc::as_ptr(instance) as *const ()),
+            _ => None,
+        }
+    }
+
+    /// `seen` holds the collections currently being printed; meeting one of
+    /// them again means the value is cyclic and is shown as `[...]`/`{...}`.
+    fn fmt_value(&self, f: &mut fmt::Formatter<'_>, seen: &mut Vec<*const ()>) -> fmt::Result {
+        if let Some(ptr) = self.shared_ptr() {
+            if seen.contains(&ptr) {
+                return match self {
+                    Value::List(_) => write!(f, "[...]"),
+                    Value::Struct(instance) => write!(f, "{} {{...}}", instance.borrow().def.name),
+                    _ => write!(f, "{{...}}"),
+                };
+            }
+            seen.push(ptr);
+        }
+        match self {
+            Value::Int(n) => write!(f, "{}", n)?,
+            Value::Number(n) => write!(f, "{}", display_float(*n))?,
+            Value::Decimal(n) => write!(f, "{}", n)?,
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => write!(f, "{}", n)?,
+            Value::Bool(b) => write!(f, "{}", b)?,
+            Value::String(s) => write!(f, "{}", s)?,
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f, seen)?;
+                }
+                write!(f, "]")?;
+            }
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f, seen)?;
+                }
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")?;
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: ", key)?;
+                    value.fmt_nested(f, seen)?;
+                }
+                write!(f, "}}")?;
+            }
+            Value::Struct(instance) => match user_to_str(instance) {
+                Some(text) => write!(f, "{}", text)?,
+                None => {
+                    let instance = instance.borrow();
+                    write!(f, "{} {{", instance.def.name)?;
+                    for (i, (name, value)) in
+                        instance.def.fields.iter().zip(&instance.fields).enumerate()
+                    {
+                        write!(f, "{}{}: ", if i > 0 { ", " } else { " " }, name)?;
+                        value.fmt_nested(f, seen)?;
+                    }
+                    if !instance.fields.is_empty() {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "}}")?;
+                }
+            },
+            Value::StructDef(def) => {
+                let keyword = if def.class { "class" } else { "struct" };
+                write!(f, "<{} {}>", keyword, def.name)?
+            }
+            Value::BoundMethod { receiver, name } => {
+                write!(f, "<method {}.{}>", receiver.borrow().def.name, name)?
+            }
+            Value::Nil => write!(f, "nil")?,
+            Value::Function { name, .. } => write!(f, "<function {}>", name)?,
+            Value::FuncBuiltIn { name, .. } | Value::FuncPlugin { name, .. } => {
+                write!(f, "<builtin function {}>", name)?
+            }
+        }
+        if self.shared_ptr().is_some() {
+            seen.pop();
+        }
+        Ok(())
+    }
+
+    /// Formats a value inside a collection, where strings are quoted.
+    fn fmt_nested(&self, f: &mut fmt::Formatter<'_>, seen: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{:?}", s),
+            _ => self.fmt_value(f, seen),
+        }
+    }
+
+    /// Structural equality for lists and maps. Comparing two collections
+    /// that are both self-referential is a runtime error rather than an
+    /// endless recursion.
+    pub fn deep_eq(&self, other: &Value) -> bool {
+        self.deep_eq_inner(other, &mut Vec::new())
+    }
+
+    fn deep_eq_inner(&self, other: &Value, seen: &mut Vec<(*const (), *const ())>) -> bool {
+        if let (Some(l), Some(r)) = (self.shared_ptr(), other.shared_ptr()) {
+            if l == r {
+                return true;
+            }
+            if seen.contains(&(l, r)) {
+                fatal(
+                    ErrorType::RuntimeError,
+                    "Cannot compare values that contain themselves".to_string(),
+                );
+            }
+            seen.push((l, r));
+        }
+        let equal = match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l == r,
+            (Value::Decimal(_), Value::Decimal(_) | Value::Int(_))
+            | (Value::Int(_), Value::Decimal(_)) => self.to_decimal() == other.to_decimal(),
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(_) | Value::Int(_), Value::BigInt(_) | Value::Int(_)) => {
+                self.to_bigint() == other.to_bigint()
+            }
+            (Value::Int(_) | Value::Number(_), Value::Int(_) | Value::Number(_)) => {
+                self.as_f64() == other.as_f64()
+            }
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::List(l), Value::List(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|(a, b)| a.deep_eq_inner(b, seen))
+            }
+            (Value::Tuple(l), Value::Tuple(r)) => {
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|(a, b)| a.deep_eq_inner(b, seen))
+            }
+            (Value::Map(l), Value::Map(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|((ka, a), (kb, b))| ka == kb && a.deep_eq_inner(b, seen))
+            }
+            (Value::Struct(l), Value::Struct(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                Rc::ptr_eq(&l.def, &r.def)
+                    && l.fields
+                        .iter()
+                        .zip(r.fields.iter())
+                        .all(|(a, b)| a.deep_eq_inner(b, seen))
+            }
+            (Value::StructDef(l), Value::StructDef(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        };
+        if self.shared_ptr().is_some() && other.shared_ptr().is_some() {
+            seen.pop();
+        }
+        equal
+    }
+}
+
+thread_local! {
+    /// The instances whose `to_str` method is running, so that printing
+    /// `self` inside it gives the default form instead of recursing.
+    static IN_TO_STR: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The text of an instance whose declaration has a `to_str` method, which
+/// printing uses in place of the default `Name { field: value }` form.
+fn user_to_str(instance: &Rc<RefCell<Struct>>) -> Option<String> {
+    let name = Symbol::from("to_str");
+    let ptr = Rc::as_ptr(instance) as *const ();
+    if instance.borrow().def.method(name).is_none()
+        || IN_TO_STR.with(|running| running.borrow().contains(&ptr))
+    {
+        return None;
+    }
+    IN_TO_STR.with(|running| running.borrow_mut().push(ptr));
+    let _done = ToStrDone;
+    let method = Value::BoundMethod {
+        receiver: instance.clone(),
+        name,
+    };
+    Some(match call_value(method, Vec::new()) {
+        Value::String(s) => s,
+        value => value.to_string(),
+    })
+}
+
+/// Pops the instance pushed by [`user_to_str`], also when a caught error
+/// unwinds out of its `to_str`.
+struct ToStrDone;
+
+impl Drop for ToStrDone {
+    fn drop(&mut self) {
+        IN_TO_STR.with(|running| running.borrow_mut().pop());
+    }
+}
+
+/// A `struct` or `class` declaration, stored in the environment under its
+/// name like a function. Methods close over the environment the class was
+/// declared in.
+#[derive(Debug)]
+pub struct StructDef {
+    pub name: Symbol,
+    pub class: bool,
+    pub fields: Vec<Symbol>,
+    pub methods: Vec<Method>,
+    pub closure: Rc<RefCell<Env>>,
+}
+
+impl StructDef {
+    pub fn field_index(&self, name: Symbol) -> Option<usize> {
+        self.fields.iter().position(|f| *f == name)
+    }
+
+    pub fn method(&self, name: Symbol) -> Option<&Method> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+}
+
+/// The field values of a struct instance, in declaration order.
+#[derive(Debug)]
+pub struct Struct {
+    pub def: Rc<StructDef>,
+    pub fields: Vec<Value>,
+}
+
+impl Struct {
+    /// Reads a field, or binds a method to the instance.
+    pub fn get(this: &Rc<RefCell<Struct>>, name: Symbol) -> Value {
+        let instance = this.borrow();
+        if let Some(i) = instance.def.field_index(name) {
+            instance.fields[i].clone()
+        } else if instance.def.method(name).is_some() {
+            Value::BoundMethod {
+                receiver: this.clone(),
+                name,
+            }
+        } else if instance.def.class {
+            fatal(
+                ErrorType::RuntimeError,
+                format!(
+                    "Class `{}` has no field or method `{}`",
+                    instance.def.name, name
+                ),
+            );
+        } else {
+            instance.no_field(name)
+        }
+    }
+
+    /// Whether the instance has a field or method called `name`.
+    pub fn has_member(&self, name: Symbol) -> bool {
+        self.def.field_index(name).is_some() || self.def.method(name).is_some()
+    }
+
+    pub fn set(&mut self, name: Symbol, value: Value) {
+        match self.def.field_index(name) {
+            Some(i) => self.fields[i] = value,
+            None => self.no_field(name),
+        }
+    }
+
+    fn no_field(&self, name: Symbol) -> ! {
+        fatal(
+            ErrorType::RuntimeError,
+            format!("Struct `{}` has no field `{}`", self.def.name, name),
+        );
+    }
+}
+
+/// Whether assignments may replace a binding. Only a `const` also refuses
+/// to be redeclared in its scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutability {
+    Mutable,
+    Immutable,
+    Const,
+}
+
+impl Mutability {
+    /// The mutability of a `let`, or of a `let mut` when `mutable` is set.
+    pub fn of_let(mutable: bool) -> Self {
+        if mutable {
+            Mutability::Mutable
+        } else {
+            Mutability::Immutable
+        }
+    }
+}
+
+/// A variable's value, whether assignments may replace it, and the type
+/// it was declared with, which they must keep to.
+#[derive(Debug)]
+pub struct Binding {
+    pub value: Value,
+    pub mutability: Mutability,
+    pub ty: Option<Type>,
 }
 
 #[derive(Debug)]
 pub struct Env {
-    pub map: HashMap<String, Value>,
+    pub map: HashMap<Symbol, Binding>,
     pub parent: Option<Rc<RefCell<Env>>>,
 }
 
@@ -54,33 +530,146 @@ impl Env {
     }
 
     pub fn child_env(parent: Rc<RefCell<Self>>) -> Rc<RefCell<Self>> {
+        if stats::enabled() {
+            stats::env_depth(Env::depth(&parent) + 1);
+        }
         Rc::new(RefCell::new(Env {
             map: HashMap::new(),
             parent: Some(parent.clone()),
         }))
     }
 
-    pub fn define(&mut self, name: String, value: Value) {
-        self.map.insert(name, value);
+    /// How many environments make up the chain ending at `env`.
+    fn depth(env: &Rc<RefCell<Self>>) -> u64 {
+        let mut depth = 1;
+        let mut parent = env.borrow().parent.clone();
+        while let Some(env) = parent {
+            depth += 1;
+            parent = env.borrow().parent.clone();
+        }
+        depth
     }
 
-    pub fn assign(&mut self, name: String, value: Value) {
-        if let Some(v) = self.map.get_mut(&name) {
-            *v = value;
+    /// Stops if `name` cannot be declared in this scope, reporting `line`.
+    /// Statements check this before defining so the error has a line.
+    pub fn check_define(&self, name: Symbol, line: usize) {
+        if let Some(message) = self.redefinition(name) {
+            line_fatal(ErrorType::RuntimeError, line, message);
+        }
+    }
+
+    /// Why `name` cannot be declared in this scope: it is a builtin at the
+    /// top level or a `const`.
+    fn redefinition(&self, name: Symbol) -> Option<String> {
+        match self.map.get(&name) {
+            Some(b) if self.parent.is_none() && b.value.is_builtin() => Some(format!(
+                "Cannot redefine builtin `{}`; choose another name or shadow it inside a block",
+                name
+            )),
+            Some(b) if b.mutability == Mutability::Const => Some(format!(
+                "Cannot redefine constant `{}`; shadow it inside a block instead",
+                name
+            )),
+            _ => None,
+        }
+    }
+
+    /// Defines a mutable binding, as for parameters, loop variables and
+    /// functions.
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.define_as(name, value, Mutability::Mutable, None);
+    }
+
+    /// Defines a `const`, which `assign` refuses to change.
+    pub fn define_const(&mut self, name: Symbol, value: Value) {
+        self.define_as(name, value, Mutability::Const, None);
+    }
+
+    /// Defines `name` in this scope. The caller has checked `value`
+    /// against the type `ty`.
+    pub fn define_as(
+        &mut self,
+        name: Symbol,
+        value: Value,
+        mutability: Mutability,
+        ty: Option<Type>,
+    ) {
+        if let Some(message) = self.redefinition(name) {
+            fatal(ErrorType::RuntimeError, message);
+        }
+        self.map.insert(
+            name,
+            Binding {
+                value,
+                mutability,
+                ty,
+            },
+        );
+    }
+
+    pub fn assign(&mut self, name: Symbol, value: Value) {
+        if let Some(binding) = self.map.get_mut(&name) {
+            if self.parent.is_none() && binding.value.is_builtin() {
+                fatal(
+                    ErrorType::RuntimeError,
+                    format!("Cannot assign to builtin `{}`", name),
+                );
+            }
+            match binding.mutability {
+                Mutability::Mutable => {}
+                Mutability::Immutable => fatal(
+                    ErrorType::RuntimeError,
+                    format!(
+                        "Cannot assign twice to immutable variable `{}`; declare it with `let mut {}`",
+                        name, name
+                    ),
+                ),
+                Mutability::Const => fatal(
+                    ErrorType::RuntimeError,
+                    format!("Cannot assign to constant `{}`", name),
+                ),
+            }
+            if let Some(ty) = binding.ty
+                && !ty.accepts(&value)
+            {
+                fatal(
+                    ErrorType::TypeError,
+                    format!(
+                        "Cannot assign {} `{}` to `{}`, which is declared as `{}`",
+                        value.type_name(),
+                        value,
+                        name,
+                        ty
+                    ),
+                );
+            }
+            binding.value = value;
         } else if let Some(parent) = &self.parent {
             parent.borrow_mut().assign(name, value);
         } else {
-            error(
+            fatal(
                 ErrorType::RuntimeError,
                 format!("Undefined variable `{}`", name),
             );
-            process::exit(1);
         }
     }
 
-    pub fn get(&self, name: &str) -> Option<Value> {
-        if let Some(value) = self.map.get(name) {
-            Some(value.clone())
+    /// A copy of the binding of `name`, for `fn[name]`. Later assignments
+    /// to either do not change the other.
+    pub fn snapshot(&self, name: Symbol) -> Option<Binding> {
+        match self.map.get(&name) {
+            Some(binding) => Some(Binding {
+                value: binding.value.clone(),
+                mutability: binding.mutability,
+                ty: binding.ty,
+            }),
+            None => self.parent.as_ref()?.borrow().snapshot(name),
+        }
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        if let Some(binding) = self.map.get(&name) {
+            Some(binding.value.clone())
         } else if let Some(parent) = &self.parent {
             parent.borrow().get(name)
         } else {
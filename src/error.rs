@@ -1,4 +1,26 @@
-#[derive(Debug)]
+use std::{
+    cell::Cell,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    process,
+};
+
+use crate::console::{red, yellow};
+use crate::stats;
+
+/// Exit statuses used by the binary, following the BSD `sysexits.h` names.
+pub mod exit {
+    pub const OK: i32 = 0;
+    pub const USAGE: i32 = 64;
+    pub const DATAERR: i32 = 65;
+    pub const NOINPUT: i32 = 66;
+    pub const UNAVAILABLE: i32 = 69;
+    pub const SOFTWARE: i32 = 70;
+    pub const IOERR: i32 = 74;
+    pub const CONFIG: i32 = 78;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorType {
     TypeError,
     SyntaxError,
@@ -6,10 +28,186 @@ pub enum ErrorType {
     UndefinedVariable,
 }
 
+impl ErrorType {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorType::SyntaxError => exit::DATAERR,
+            ErrorType::TypeError | ErrorType::RuntimeError | ErrorType::UndefinedVariable => {
+                exit::SOFTWARE
+            }
+        }
+    }
+}
+
+/// An error that stopped a script run through [`catch`], which would
+/// otherwise have been printed before the process exited.
+#[derive(Debug, Clone)]
+pub struct RikuError {
+    pub kind: ErrorType,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for RikuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{:?} on line: {}: {}", self.kind, line, self.message),
+            None => write!(f, "{:?}: {}", self.kind, self.message),
+        }
+    }
+}
+
+impl std::error::Error for RikuError {}
+
+thread_local! {
+    /// Set while [`catch`] runs, so that fatal errors unwind to it instead
+    /// of ending the process.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// Set while [`catch_exit`] runs, so that `exit()` unwinds to it.
+    static CATCHING_EXIT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `run`, returning the first fatal error as a [`RikuError`] instead
+/// of printing it and exiting. For hosts that embed the interpreter.
+/// `exit()` in a script still ends the process, unless [`catch_exit`] is
+/// around it.
+pub fn catch<T>(run: impl FnOnce() -> T) -> Result<T, RikuError> {
+    let outer = CATCHING.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(run));
+    CATCHING.set(outer);
+    result.map_err(|payload| match payload.downcast::<RikuError>() {
+        Ok(error) => *error,
+        Err(payload) => panic::resume_unwind(payload),
+    })
+}
+
+/// The status a script passed to `exit()` while running under
+/// [`catch_exit`].
+struct Exit(i32);
+
+/// Runs `run`, returning the status of an `exit()` in the script as the
+/// `Err` instead of ending the process. For hosts such as `riku serve`
+/// that run many scripts in one process. Fatal errors are not caught; run
+/// [`catch`] inside it for those.
+pub fn catch_exit<T>(run: impl FnOnce() -> T) -> Result<T, i32> {
+    let outer = CATCHING_EXIT.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(run));
+    CATCHING_EXIT.set(outer);
+    result.map_err(|payload| match payload.downcast::<Exit>() {
+        Ok(exit) => exit.0,
+        Err(payload) => panic::resume_unwind(payload),
+    })
+}
+
+/// Ends the program with `status` for `exit()`, or unwinds to the
+/// enclosing [`catch_exit`], if there is one.
+pub fn exit_program(status: i32) -> ! {
+    if CATCHING_EXIT.get() {
+        panic::resume_unwind(Box::new(Exit(status)));
+    }
+    stats::report();
+    process::exit(status);
+}
+
+/// Unwinds to the enclosing [`catch`], if there is one. `resume_unwind`
+/// skips the panic hook, so nothing is printed.
+fn raise_if_caught(kind: ErrorType, line: Option<usize>, message: &str) {
+    if CATCHING.get() {
+        panic::resume_unwind(Box::new(RikuError {
+            kind,
+            line,
+            message: message.to_string(),
+        }));
+    }
+}
+
 pub fn error(error: ErrorType, message: String) {
-    eprintln!("{:?}: {}", error, message);
+    eprintln!("{}: {}", red(&format!("{:?}", error)), message);
+}
+
+/// Reports something suspicious that does not stop the script.
+pub fn warning(message: String) {
+    eprintln!("{}: {}", yellow("Warning"), message);
 }
 
 pub fn line_error(error: ErrorType, line: usize, message: String) {
-    eprintln!("{:?} on line: {}: {}", error, line, message);
+    eprintln!(
+        "{} on line: {}: {}",
+        red(&format!("{:?}", error)),
+        line,
+        message
+    );
+}
+
+/// Reports the error with its line, then the part of the source line around
+/// byte `column` of `text` with a caret under it, and stops the interpreter.
+pub fn snippet_fatal(
+    error: ErrorType,
+    line: usize,
+    text: &str,
+    column: usize,
+    message: String,
+) -> ! {
+    raise_if_caught(error, Some(line), &message);
+    let code = error.exit_code();
+    line_error(error, line, message);
+    let (snippet, caret) = snippet(text, column);
+    eprintln!("    {}", snippet);
+    eprintln!("    {}^", " ".repeat(caret));
+    stats::report();
+    process::exit(code);
+}
+
+/// Characters of context kept on each side of a snippet's caret, so a
+/// megabyte-long line of generated code still prints as one short line.
+const SNIPPET_CONTEXT: usize = 30;
+
+/// The window of `text` around byte `column`, with `...` where it was cut,
+/// and the caret's position in it counted in characters. Only the window
+/// is scanned, never the whole line.
+fn snippet(text: &str, column: usize) -> (String, usize) {
+    let column = column.min(text.len());
+    let start = text[..column]
+        .char_indices()
+        .rev()
+        .take(SNIPPET_CONTEXT)
+        .last()
+        .map_or(column, |(i, _)| i);
+    let end = text[column..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(i, _)| column + i);
+    let prefix = if start > 0 { "..." } else { "" };
+    let suffix = if end < text.len() { "..." } else { "" };
+    let window = text[start..end].replace('\t', " ");
+    let caret = prefix.len() + text[start..column].chars().count();
+    (format!("{}{}{}", prefix, window, suffix), caret)
+}
+
+/// Source text quoted in a message, cut short if it is long, such as a
+/// string literal that runs for pages.
+pub fn excerpt(text: &str) -> String {
+    const MAX: usize = 40;
+    match text.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Reports the error and stops the interpreter.
+pub fn fatal(error: ErrorType, message: String) -> ! {
+    raise_if_caught(error, None, &message);
+    let code = error.exit_code();
+    self::error(error, message);
+    stats::report();
+    process::exit(code);
+}
+
+/// Reports the error with its line and stops the interpreter.
+pub fn line_fatal(error: ErrorType, line: usize, message: String) -> ! {
+    raise_if_caught(error, Some(line), &message);
+    let code = error.exit_code();
+    line_error(error, line, message);
+    stats::report();
+    process::exit(code);
 }
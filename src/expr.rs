@@ -1,10 +1,26 @@
-use std::{cell::RefCell, fmt, process, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fmt,
+    rc::Rc,
+};
 
+#[cfg(feature = "bigint")]
+use crate::bigint::BigInt;
 use crate::{
-    env::{Env, Value},
-    error::{ErrorType, error, line_error},
-    stmt::ControlFlow,
-    token::{Token, TokenType},
+    config,
+    decimal::Decimal,
+    env::{Env, Mutability, Struct, Value},
+    error::{ErrorType, error, fatal, line_error, line_fatal, warning},
+    format::FormatSpec,
+    iter, methods,
+    pattern::MatchArm,
+    stats,
+    std_fn::{self, Arity},
+    stmt::{ControlFlow, Param, Stmt, assign_field, assign_index, is_initializing},
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
+    types::Type,
 };
 
 #[derive(Debug, Clone)]
@@ -13,7 +29,15 @@ pub enum Op {
     Sub,
     Mul,
     Div,
+    /// `div`, division rounded toward negative infinity.
+    FloorDiv,
     Mod,
+    /// `^` in math mode.
+    Pow,
+    /// A `!` after an operand, in math mode.
+    Factorial,
+    /// `|x|` in math mode.
+    Abs,
     And,
     Or,
     Not,
@@ -27,46 +51,153 @@ pub enum Op {
 
 #[derive(Debug, Clone)]
 pub enum Expr {
+    Int(i64),
     Number(f64),
     Bool(bool),
+    Nil,
     String(String),
     Binary(Box<Expr>, Op, Box<Expr>),
     Logic(Box<Expr>, Op, Box<Expr>),
+    /// `a < b < c`, which is `a < b && b < c` with `b` evaluated once.
+    /// Evaluation stops at the first comparison that fails.
+    Chain(Box<Expr>, Box<[(Op, Expr)]>),
     Unary(Op, Box<Expr>),
     Group(Box<Expr>),
-    Variable(Token),
-    Call { callee: Box<Expr>, args: Vec<Expr> },
+    Variable {
+        name: Symbol,
+        line: usize,
+        span: Span,
+    },
+    /// A call, with the line of the call site for arity errors.
+    Call {
+        callee: Box<Expr>,
+        args: Box<[Expr]>,
+        line: u32,
+    },
+    List(Vec<Expr>),
+    /// `...xs` in the arguments of a call or the elements of a list, which
+    /// is replaced by the elements of `xs`.
+    Spread(Box<Expr>),
+    Tuple(Box<[Expr]>),
+    Map(Vec<(Expr, Expr)>),
+    Index(Box<Expr>, Box<Expr>),
+    OptionalIndex(Box<Expr>, Box<Expr>),
+    /// `target.field` on a struct instance.
+    Field(Box<Expr>, Symbol),
+    /// `target?.field`, which is `nil` when the target is. As the callee of
+    /// a call, `target?.method(...)` also gives `nil` without evaluating the
+    /// arguments.
+    OptionalField(Box<Expr>, Symbol),
+    /// `Point { x: 1, y: 2 }`; the first expression names the struct.
+    StructLit(Box<Expr>, Box<[(Symbol, Expr)]>),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    Format(Box<Expr>, Box<FormatSpec>),
+    Match(Box<Expr>, Box<[MatchArm]>),
+    /// `if` used as a value: the branch's trailing expression, or `nil`
+    /// when no branch runs.
+    If(Box<Expr>, Box<Stmt>, Option<Box<Stmt>>),
+    Lambda(Box<[Param]>, Box<Stmt>),
+    /// `target = value` used as a value, as in `a = b = 0`. The target is
+    /// a variable, index or field, and the value is what was assigned.
+    Assign(Box<Expr>, Box<Expr>),
+    /// `target += value` and the other compound assignments, with the
+    /// operator they apply. The parts of the target, as the list and index
+    /// of `xs[i] += 1`, are evaluated once. Gives the new value.
+    CompoundAssign {
+        target: Box<Expr>,
+        op: Op,
+        value: Box<Expr>,
+    },
+    /// `x++` or `++x`, with `Op::Add`, and `x--` or `--x`, with `Op::Sub`:
+    /// adds or subtracts 1 and gives the old value, or the new one when
+    /// the operator comes first.
+    Increment {
+        target: Box<Expr>,
+        op: Op,
+        prefix: bool,
+    },
+    /// `fn[x, y](...) { ... }`: a lambda whose closure holds copies of `x`
+    /// and `y` as they are when it is created.
+    Capture {
+        names: Box<[Symbol]>,
+        lambda: Box<Expr>,
+        line: u32,
+    },
 }
 
+// Every node is as large as the largest variant. Payloads that would grow
+// `Expr` past this limit go behind a `Box`.
+const _: () = assert!(std::mem::size_of::<Expr>() <= 32);
+
 impl Expr {
     pub fn new(token: Token) -> Self {
         match token.token_type {
+            TokenType::Number if !token.lexeme.contains(['.', 'e', 'E']) => {
+                match token.lexeme.parse::<i64>() {
+                    Ok(n) => Expr::Int(n),
+                    // Too large for an integer, so it becomes a float.
+                    Err(_) => Expr::Number(token.lexeme.parse::<f64>().unwrap_or(f64::MAX)),
+                }
+            }
             TokenType::Number => {
-                let value = token.lexeme.parse::<f64>().unwrap_or(0.0);
+                let value = token.lexeme.parse::<f64>().unwrap_or_else(|_| {
+                    if config::strict() {
+                        line_fatal(
+                            ErrorType::SyntaxError,
+                            token.line,
+                            format!("Invalid number `{}`", token.lexeme),
+                        );
+                    }
+                    0.0
+                });
                 Expr::Number(value)
             }
             TokenType::String => Expr::String(token.lexeme),
             TokenType::True => Expr::Bool(true),
             TokenType::False => Expr::Bool(false),
-            TokenType::Ident => Expr::Variable(token),
+            TokenType::Nil => Expr::Nil,
+            TokenType::Ident => Expr::Variable {
+                name: Symbol::intern(&token.lexeme),
+                line: token.line,
+                span: token.span,
+            },
             _ => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     token.line,
                     format!("expected a expr but found `{}`", token.lexeme),
                 );
-                process::exit(1);
             }
         }
     }
 
-    pub fn new_call(callee: Expr, args: Vec<Expr>) -> Self {
+    /// The value of a literal expression built by `Expr::new`.
+    pub fn literal_value(&self) -> Value {
+        match self {
+            Expr::Int(n) => Value::Int(*n),
+            Expr::Number(n) => Value::Number(*n),
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::String(s) => Value::String(s.clone()),
+            _ => Value::Nil,
+        }
+    }
+
+    pub fn new_call(callee: Expr, args: Vec<Expr>, line: usize) -> Self {
         Expr::Call {
             callee: Box::new(callee),
-            args,
+            args: args.into_boxed_slice(),
+            line: line as u32,
         }
     }
 
+    pub fn new_index(target: Expr, index: Expr) -> Self {
+        Expr::Index(Box::new(target), Box::new(index))
+    }
+
+    pub fn new_ternary(cond: Expr, then: Expr, otherwise: Expr) -> Self {
+        Expr::Ternary(Box::new(cond), Box::new(then), Box::new(otherwise))
+    }
+
     pub fn new_group(expr: Expr) -> Self {
         Expr::Group(Box::new(expr))
     }
@@ -76,122 +207,901 @@ impl Expr {
         Expr::Binary(Box::new(left), op, Box::new(right))
     }
 
+    pub fn new_compound(target: Expr, op: &Token, value: Expr) -> Self {
+        Expr::CompoundAssign {
+            target: Box::new(target),
+            op: Op::new(op),
+            value: Box::new(value),
+        }
+    }
+
     pub fn new_logic(left: Expr, op: &Token, right: Expr) -> Self {
         let op = Op::new(op);
         Expr::Logic(Box::new(left), op, Box::new(right))
     }
 
+    pub fn new_chain(first: Expr, rest: Vec<(Token, Expr)>) -> Self {
+        let rest = rest.into_iter().map(|(op, e)| (Op::new(&op), e));
+        Expr::Chain(Box::new(first), rest.collect())
+    }
+
     pub fn new_unary(op: &Token, right: Expr) -> Self {
         let op = match op.token_type {
             TokenType::Minus => Op::Sub,
             TokenType::Bang => Op::Not,
             _ => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     op.line,
                     format!("Only support unary minus operator, found `{}`", op.lexeme),
                 );
-                process::exit(1);
             }
         };
         Expr::Unary(op, Box::new(right))
     }
 
-    pub fn condition_eval(&self, env: &mut Rc<RefCell<Env>>) -> bool {
-        match self.eval(env) {
-            Value::Bool(b) => b,
-            Value::Number(n) => n > 0.0,
+    fn field_value(target: &Value, name: Symbol) -> Value {
+        match target {
+            Value::Struct(instance) => Struct::get(instance, name),
+            target => fatal(
+                ErrorType::TypeError,
+                format!("`{}` has no fields, cannot read `.{}`", target, name),
+            ),
+        }
+    }
+
+    fn index_value(target: Value, index: Value) -> Value {
+        match &target {
+            Value::Map(map) => {
+                let key = map_key(&index);
+                map.borrow().get(&key).cloned().unwrap_or_else(|| {
+                    fatal(
+                        ErrorType::RuntimeError,
+                        format!("Key {:?} not found in map", key),
+                    );
+                })
+            }
+            Value::List(items) => {
+                let items = items.borrow();
+                let i = list_index(&index, items.len());
+                items[i].clone()
+            }
+            Value::Tuple(items) => {
+                let i = list_index(&index, items.len());
+                items[i].clone()
+            }
+            Value::String(s) => {
+                let chars = s.chars().collect::<Vec<_>>();
+                let i = list_index(&index, chars.len());
+                Value::String(chars[i].to_string())
+            }
             _ => {
-                error(
+                fatal(
                     ErrorType::TypeError,
-                    "Invalid condition, expected boolean or number".to_string(),
+                    format!("`{}` is not indexable", target),
                 );
+            }
+        }
+    }
+
+    /// Stores `f` of the value of this variable, index or field back in it,
+    /// evaluating the list and index or the instance only once. Gives the
+    /// old and the new value.
+    fn update(
+        &self,
+        env: &mut Rc<RefCell<Env>>,
+        f: impl FnOnce(&Value, &mut Rc<RefCell<Env>>) -> Value,
+    ) -> (Value, Value) {
+        match self {
+            Self::Variable { name, .. } => {
+                let old = self.eval(env);
+                let new = f(&old, env);
+                env.borrow_mut().assign(*name, new.clone());
+                (old, new)
+            }
+            Self::Index(target, index) => {
+                let target = target.eval(env);
+                let index = index.eval(env);
+                let old = Expr::index_value(target.clone(), index.clone());
+                let new = f(&old, env);
+                assign_index(&target, &index, new.clone());
+                (old, new)
+            }
+            Self::Field(target, name) => {
+                let target = target.eval(env);
+                let old = Expr::field_value(&target, *name);
+                let new = f(&old, env);
+                assign_field(&target, *name, new.clone());
+                (old, new)
+            }
+            _ => unreachable!("the parser only updates variables, indexes and fields"),
+        }
+    }
+
+    pub fn condition_eval(&self, env: &mut Rc<RefCell<Env>>) -> bool {
+        match self.eval(env) {
+            Value::Bool(b) => b,
+            value if config::strict() => {
+                type_error(format!("Invalid condition `{}`, expected boolean", value));
                 false
             }
+            value => value.truthy(),
         }
     }
 
     pub fn eval(&self, env: &mut Rc<RefCell<Env>>) -> Value {
         match self {
+            Self::Int(n) => Value::Int(*n),
             Self::Number(n) => Value::Number(*n),
             Self::Bool(b) => Value::Bool(*b),
+            Self::Nil => Value::Nil,
             Self::Binary(l, op, r) => {
                 let left = l.eval(env);
                 let right = r.eval(env);
-                let num = op.eval_binary(left, right);
-                Value::Number(num)
+                stats::alloc(op.eval_binary(left, right))
             }
-            Self::Unary(op, r) => op.eval_unary(r.eval(env)),
+            Self::Unary(op, r) => stats::alloc(op.eval_unary(r.eval(env))),
             Self::Group(expr) => expr.eval(env),
             Self::Logic(l, op, r) => {
                 let left = l.eval(env);
                 let right = r.eval(env);
                 op.eval_logic(left, right)
             }
-            Self::Variable(t) => env.borrow().get(&t.lexeme).unwrap_or_else(|| {
-                error(
-                    ErrorType::RuntimeError,
-                    format!("Undefined variable `{}`", t.lexeme),
-                );
-                process::exit(1);
+            Self::Chain(first, rest) => {
+                let mut left = first.eval(env);
+                for (op, expr) in rest {
+                    let right = expr.eval(env);
+                    if !matches!(op.eval_logic(left, right.clone()), Value::Bool(true)) {
+                        return Value::Bool(false);
+                    }
+                    left = right;
+                }
+                Value::Bool(true)
+            }
+            Self::Variable { name, line, .. } => env.borrow().get(*name).unwrap_or_else(|| {
+                let message = if is_initializing(*name) {
+                    format!(
+                        "Use of `{}` before initialization in its own declaration",
+                        name
+                    )
+                } else {
+                    format!("Undefined variable `{}`", name)
+                };
+                line_fatal(ErrorType::RuntimeError, *line, message);
             }),
-            Self::String(s) => Value::String(s.clone()),
-            Self::Call { callee, args } => {
-                let func = callee.eval(env);
-                let args = args.iter().map(|a| a.eval(env)).collect::<Vec<_>>();
-                match func {
-                    Value::Function {
-                        params,
-                        body,
-                        closure,
-                        ..
-                    } => {
-                        if args.len() != params.len() {
-                            error(
+            Self::String(s) => stats::alloc(Value::String(s.clone())),
+            Self::List(items) => {
+                let items = eval_items(items, env);
+                stats::alloc(Value::List(Rc::new(RefCell::new(items))))
+            }
+            Self::Spread(_) => unreachable!("`...` is only parsed in calls and lists"),
+            Self::Tuple(items) => stats::alloc(Value::Tuple(
+                items.iter().map(|item| item.eval(env)).collect(),
+            )),
+            Self::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (key, value) in entries {
+                    let key = map_key(&key.eval(env));
+                    map.insert(key, value.eval(env));
+                }
+                stats::alloc(Value::Map(Rc::new(RefCell::new(map))))
+            }
+            Self::OptionalIndex(target, index) => match target.eval(env) {
+                Value::Nil => Value::Nil,
+                target => Expr::index_value(target, index.eval(env)),
+            },
+            Self::Field(target, name) => Expr::field_value(&target.eval(env), *name),
+            Self::OptionalField(target, name) => match target.eval(env) {
+                Value::Nil => Value::Nil,
+                target => Expr::field_value(&target, *name),
+            },
+            Self::StructLit(def, fields) => {
+                let Value::StructDef(def) = def.eval(env) else {
+                    fatal(ErrorType::TypeError, format!("`{}` is not a struct", def));
+                };
+                // Fields are evaluated in the order they are written.
+                let mut values = vec![None; def.fields.len()];
+                for (name, value) in fields {
+                    let Some(i) = def.field_index(*name) else {
+                        fatal(
+                            ErrorType::RuntimeError,
+                            format!("Struct `{}` has no field `{}`", def.name, name),
+                        );
+                    };
+                    values[i] = Some(value.eval(env));
+                }
+                let values = values
+                    .into_iter()
+                    .zip(&def.fields)
+                    .map(|(value, field)| {
+                        value.unwrap_or_else(|| {
+                            fatal(
                                 ErrorType::RuntimeError,
-                                format!(
-                                    "Expected {} arguments but got {}",
-                                    params.len(),
-                                    args.len()
-                                ),
-                            );
-                            process::exit(1);
-                        }
-                        let mut child_env = Env::child_env(closure);
-                        for (param, arg) in params.iter().zip(args) {
-                            child_env.borrow_mut().define(param.clone(), arg);
-                        }
-                        match body.eval(&mut child_env) {
-                            ControlFlow::Return(v) => v,
-                            _ => Value::Nil,
-                        }
-                    }
-                    Value::FuncBuiltIn { body, .. } => body(args),
-                    _ => {
-                        error(
-                            ErrorType::TypeError,
-                            format!("`{}` is not a function", func),
+                                format!("Missing field `{}` in `{}`", field, def.name),
+                            )
+                        })
+                    })
+                    .collect();
+                stats::alloc(Value::Struct(Rc::new(RefCell::new(Struct {
+                    def,
+                    fields: values,
+                }))))
+            }
+            Self::Format(expr, spec) => stats::alloc(Value::String(spec.apply(&expr.eval(env)))),
+            Self::Match(subject, arms) => {
+                let value = subject.eval(env);
+                match MatchArm::eval(arms, value, env) {
+                    Some(ControlFlow::Value(value)) => value,
+                    Some(ControlFlow::None) | None => Value::Nil,
+                    _ => fatal(
+                        ErrorType::RuntimeError,
+                        "`break`, `continue` and `return` cannot leave a match expression"
+                            .to_string(),
+                    ),
+                }
+            }
+            Self::If(condition, then, else_stmt) => {
+                let branch = if condition.condition_eval(env) {
+                    Some(then)
+                } else {
+                    else_stmt.as_ref()
+                };
+                match branch.map(|stmt| stmt.eval(env)) {
+                    Some(ControlFlow::Value(value)) => value,
+                    Some(ControlFlow::None) | None => Value::Nil,
+                    _ => fatal(
+                        ErrorType::RuntimeError,
+                        "`break`, `continue` and `return` cannot leave an if expression"
+                            .to_string(),
+                    ),
+                }
+            }
+            Self::Lambda(params, body) => stats::alloc(Value::Function {
+                name: "lambda".to_string(),
+                params: params[..].into(),
+                returns: None,
+                body: body.clone(),
+                closure: env.clone(),
+            }),
+            Self::Assign(target, value) => match &**target {
+                Self::Variable { name, .. } => {
+                    let value = value.eval(env);
+                    env.borrow_mut().assign(*name, value.clone());
+                    value
+                }
+                Self::Index(target, index) => {
+                    let target = target.eval(env);
+                    let index = index.eval(env);
+                    let value = value.eval(env);
+                    assign_index(&target, &index, value.clone());
+                    value
+                }
+                Self::Field(target, name) => {
+                    let target = target.eval(env);
+                    let value = value.eval(env);
+                    assign_field(&target, *name, value.clone());
+                    value
+                }
+                _ => unreachable!("the parser only assigns to variables, indexes and fields"),
+            },
+            Self::CompoundAssign { target, op, value } => {
+                let (_, new) = target.update(env, |old, env| {
+                    let value = value.eval(env);
+                    stats::alloc(op.eval_binary(old.clone(), value))
+                });
+                new
+            }
+            Self::Increment { target, op, prefix } => {
+                let (old, new) = target.update(env, |old, _| op.step(old));
+                if *prefix { new } else { old }
+            }
+            Self::Capture {
+                names,
+                lambda,
+                line,
+            } => {
+                let mut closure = Env::child_env(env.clone());
+                for &name in names.iter() {
+                    let Some(binding) = env.borrow().snapshot(name) else {
+                        line_fatal(
+                            ErrorType::RuntimeError,
+                            *line as usize,
+                            format!("Cannot capture undefined variable `{}`", name),
                         );
-                        Value::Nil
+                    };
+                    closure.borrow_mut().define_as(
+                        name,
+                        binding.value,
+                        binding.mutability,
+                        binding.ty,
+                    );
+                }
+                lambda.eval(&mut closure)
+            }
+            Self::Ternary(cond, then, otherwise) => {
+                if cond.condition_eval(env) {
+                    then.eval(env)
+                } else {
+                    otherwise.eval(env)
+                }
+            }
+            Self::Index(target, index) => {
+                let target = target.eval(env);
+                let index = index.eval(env);
+                Expr::index_value(target, index)
+            }
+            Self::Call { callee, args, line } => match Expr::prepare_call(callee, args, *line, env)
+            {
+                Ok(call) => call_at(call.func, call.args, Some(call.line)),
+                Err(value) => value,
+            },
+        }
+    }
+
+    /// The line of the first token of the expression that records one.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::Variable { line, .. } => Some(*line),
+            Self::Call { callee, line, .. } => callee.line().or(Some(*line as usize)),
+            Self::Binary(left, ..)
+            | Self::Logic(left, ..)
+            | Self::Chain(left, _)
+            | Self::Unary(_, left)
+            | Self::Group(left)
+            | Self::Spread(left)
+            | Self::Index(left, _)
+            | Self::OptionalIndex(left, _)
+            | Self::Field(left, _)
+            | Self::OptionalField(left, _)
+            | Self::StructLit(left, _)
+            | Self::Ternary(left, ..)
+            | Self::Format(left, _)
+            | Self::Match(left, _)
+            | Self::If(left, ..) => left.line(),
+            Self::List(items) => items.first().and_then(Expr::line),
+            Self::Tuple(items) => items.first().and_then(Expr::line),
+            Self::Map(entries) => entries.first().and_then(|(key, _)| key.line()),
+            Self::Lambda(_, body) => body.line(),
+            Self::Capture { line, .. } => Some(*line as usize),
+            Self::Assign(target, _)
+            | Self::CompoundAssign { target, .. }
+            | Self::Increment { target, .. } => target.line(),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the value of `return expr`. A call is handed back to the
+    /// caller unmade when a function is running, so that the caller's loop
+    /// in `call_at` makes it without another level of recursion.
+    pub fn eval_return(&self, env: &mut Rc<RefCell<Env>>) -> ControlFlow {
+        match self {
+            Self::Call { callee, args, line } if CALL_DEPTH.get() > 0 => {
+                match Expr::prepare_call(callee, args, *line, env) {
+                    Ok(call) => ControlFlow::TailCall(Box::new(call)),
+                    Err(value) => ControlFlow::Return(value),
+                }
+            }
+            expr => ControlFlow::Return(expr.eval(env)),
+        }
+    }
+
+    /// Evaluates the function and arguments of a call without making it. A
+    /// method of a builtin type, as in `xs.push(1)`, has no function value
+    /// of its own, so it is called right away and its result is the `Err`.
+    fn prepare_call(
+        callee: &Expr,
+        args: &[Expr],
+        line: u32,
+        env: &mut Rc<RefCell<Env>>,
+    ) -> Result<TailCall, Value> {
+        let func = match callee {
+            Self::Field(target, name) => Expr::member(target.eval(env), *name, args, line, env)?,
+            Self::OptionalField(target, name) => match target.eval(env) {
+                Value::Nil => return Err(Value::Nil),
+                target => Expr::member(target, *name, args, line, env)?,
+            },
+            callee => callee.eval(env),
+        };
+        Ok(TailCall {
+            func,
+            args: eval_items(args, env),
+            line: line as usize,
+        })
+    }
+
+    /// The method `name` of `target` for a call, or, for a method of a
+    /// builtin type, the result of calling it as the `Err`.
+    fn member(
+        target: Value,
+        name: Symbol,
+        args: &[Expr],
+        line: u32,
+        env: &mut Rc<RefCell<Env>>,
+    ) -> Result<Value, Value> {
+        match target {
+            Value::Struct(instance)
+                if instance.borrow().has_member(name) || !methods::is_universal(name) =>
+            {
+                Ok(Struct::get(&instance, name))
+            }
+            target => {
+                let args = eval_items(args, env);
+                Err(methods::call(target, name, args, line as usize))
+            }
+        }
+    }
+}
+
+/// A call whose function and arguments are evaluated, waiting to be made.
+#[derive(Debug, Clone)]
+pub struct TailCall {
+    pub func: Value,
+    pub args: Vec<Value>,
+    pub line: usize,
+}
+
+/// Evaluates call arguments or list elements, expanding each `...xs` into
+/// the elements of the list or tuple `xs`.
+fn eval_items(items: &[Expr], env: &mut Rc<RefCell<Env>>) -> Vec<Value> {
+    let mut values = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Expr::Spread(inner) => {
+                let value = inner.eval(env);
+                match iter::iter(&value) {
+                    Some(items) => values.extend(items),
+                    None => type_error(format!(
+                        "Cannot spread {} `{}`, expected something iterable",
+                        value.type_name(),
+                        value
+                    )),
+                }
+            }
+            item => values.push(item.eval(env)),
+        }
+    }
+    values
+}
+
+/// `left + right`, for builtins that add values up.
+pub fn add(left: Value, right: Value) -> Value {
+    Op::Add.eval_binary(left, right)
+}
+
+/// Calls a riku or builtin function with already evaluated arguments, for
+/// builtins that take callbacks.
+pub fn call_value(func: Value, args: Vec<Value>) -> Value {
+    call_at(func, args, None)
+}
+
+/// Calls a function from the call site on `line`, which errors point at.
+///
+/// A riku function that ends in `return g(x)` gives back the call to `g`
+/// instead of making it, and this loop makes it in its place, so a tail
+/// call reuses the Rust stack frame and the recursion limit's count.
+pub fn call_at(func: Value, args: Vec<Value>, line: Option<usize>) -> Value {
+    stats::call();
+    let (mut func, mut args, mut line) = (func, args, line);
+    let mut depth = None;
+    // The declared return types of the calls that handed their result on
+    // to a tail call, innermost last. A function calling itself is only
+    // listed once, so tail recursion still runs in constant space.
+    let mut pending: Vec<(Type, String, Option<usize>)> = Vec::new();
+    let mut expect = |ty: Option<Type>, name: &str, line: Option<usize>| {
+        if let Some(ty) = ty
+            && pending
+                .last()
+                .is_none_or(|(t, n, _)| (*t, n.as_str()) != (ty, name))
+        {
+            pending.push((ty, name.to_string(), line));
+        }
+    };
+    let value = loop {
+        let flow = match &func {
+            Value::Function {
+                name,
+                params,
+                returns,
+                body,
+                closure,
+            } => {
+                check_arity(name, param_arity(params), args.len(), line);
+                depth.get_or_insert_with(|| CallDepth::enter(name, line));
+                if let Some(ty) = returns {
+                    ty.check_known(&closure.borrow(), line);
+                }
+                expect(*returns, name, line);
+                let env = Env::child_env(closure.clone());
+                call_function(name, params, body, env, args, line)
+            }
+            Value::BoundMethod { receiver, name } => {
+                let def = receiver.borrow().def.clone();
+                let Some(method) = def.method(*name) else {
+                    unreachable!("only methods are bound");
+                };
+                check_arity(name.as_str(), param_arity(&method.params), args.len(), line);
+                depth.get_or_insert_with(|| CallDepth::enter(name.as_str(), line));
+                if let Some(ty) = method.returns {
+                    ty.check_known(&def.closure.borrow(), line);
+                }
+                expect(method.returns, name.as_str(), line);
+                let env = Env::child_env(def.closure.clone());
+                env.borrow_mut()
+                    .define(Symbol::intern("self"), Value::Struct(receiver.clone()));
+                call_function(name.as_str(), &method.params, &method.body, env, args, line)
+            }
+            Value::FuncBuiltIn { name, body } => {
+                if let Some(arity) = std_fn::arity(name) {
+                    check_arity(name, arity, args.len(), line);
+                }
+                std_fn::check_capability(name);
+                let _line = std_fn::CallLine::enter(line);
+                break stats::alloc(body(args));
+            }
+            Value::FuncPlugin { name, plugin } => {
+                std_fn::require(name, std_fn::Capability::Plugins);
+                break stats::alloc(plugin.call(name, args));
+            }
+            _ => {
+                let message = format!("`{}` is not a function", func);
+                match line {
+                    Some(line) if config::strict() => {
+                        line_fatal(ErrorType::TypeError, line, message)
                     }
+                    Some(line) => line_error(ErrorType::TypeError, line, message),
+                    None => type_error(message),
                 }
+                break Value::Nil;
+            }
+        };
+        match flow {
+            ControlFlow::TailCall(call) => {
+                stats::call();
+                (func, args, line) = (call.func, call.args, Some(call.line));
+            }
+            ControlFlow::Return(value) => break value,
+            _ => break Value::Nil,
+        }
+    };
+    for (ty, name, line) in pending.iter().rev() {
+        if !ty.accepts(&value) {
+            let message = format!(
+                "`{}` is declared to return `{}` but returned {} `{}`",
+                name,
+                ty,
+                value.type_name(),
+                value
+            );
+            match line {
+                Some(line) => line_fatal(ErrorType::TypeError, *line, message),
+                None => fatal(ErrorType::TypeError, message),
+            }
+        }
+    }
+    value
+}
+
+thread_local! {
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// One running call of a riku function, counted against
+/// `config::max_recursion` so that runaway recursion is an error instead
+/// of a stack overflow. Dropping it, also while a caught error unwinds,
+/// ends the call.
+struct CallDepth;
+
+impl CallDepth {
+    fn enter(name: &str, line: Option<usize>) -> Self {
+        let depth = CALL_DEPTH.get() + 1;
+        if depth > config::max_recursion() {
+            let message = format!(
+                "Maximum recursion depth of {} exceeded in `{}` \
+                 (raise the limit with `--max-recursion`)",
+                config::max_recursion(),
+                name
+            );
+            match line {
+                Some(line) => line_fatal(ErrorType::RuntimeError, line, message),
+                None => fatal(ErrorType::RuntimeError, message),
             }
         }
+        CALL_DEPTH.set(depth);
+        CallDepth
     }
 }
 
+impl Drop for CallDepth {
+    fn drop(&mut self) {
+        CALL_DEPTH.set(CALL_DEPTH.get() - 1);
+    }
+}
+
+/// How many arguments a riku function takes: every parameter without a
+/// default, up to all of them unless the last collects the rest.
+pub(crate) fn param_arity(params: &[Param]) -> Arity {
+    let required = params
+        .iter()
+        .filter(|p| p.default.is_none() && !p.rest)
+        .count();
+    Arity {
+        min: required,
+        max: if params.last().is_some_and(|p| p.rest) {
+            None
+        } else {
+            Some(params.len())
+        },
+    }
+}
+
+/// Stops with `f() expected 2 arguments, got 3` when a call has the wrong
+/// number of arguments.
+pub fn check_arity(name: &str, arity: Arity, got: usize, line: Option<usize>) {
+    if arity.accepts(got) {
+        return;
+    }
+    let message = arity_message(name, arity, got);
+    match line {
+        Some(line) => line_fatal(ErrorType::RuntimeError, line, message),
+        None => fatal(ErrorType::RuntimeError, message),
+    }
+}
+
+/// `f() expected 2 arguments, got 3`.
+pub fn arity_message(name: &str, arity: Arity, got: usize) -> String {
+    let plural = if arity.max == Some(1) && arity.min == 1 {
+        ""
+    } else {
+        "s"
+    };
+    format!(
+        "{}() expected {} argument{}, got {}",
+        name, arity, plural, got
+    )
+}
+
+/// Binds the arguments to `params` in `env` and runs the body there,
+/// giving back how it ended. The caller has checked the number of
+/// arguments; an argument that does not match its parameter's type stops
+/// the program at the call on `line`.
+fn call_function(
+    name: &str,
+    params: &[Param],
+    body: &Stmt,
+    mut env: Rc<RefCell<Env>>,
+    args: Vec<Value>,
+    line: Option<usize>,
+) -> ControlFlow {
+    let mut args = args.into_iter();
+    for param in params.iter() {
+        if param.rest {
+            let rest = args.by_ref().collect::<Vec<_>>();
+            let rest = Value::List(Rc::new(RefCell::new(rest)));
+            env.borrow_mut().define(param.name, rest);
+            break;
+        }
+        let value = match (args.next(), &param.default) {
+            (Some(arg), _) => arg,
+            (None, Some(default)) => default.eval(&mut env),
+            (None, None) => unreachable!("arity was checked above"),
+        };
+        if let Some(ty) = param.ty {
+            ty.check_known(&env.borrow(), line);
+        }
+        if let Some(ty) = param.ty
+            && !ty.accepts(&value)
+        {
+            let message = format!(
+                "Parameter `{}` of `{}` is declared as `{}` but was given {} `{}`",
+                param.name,
+                name,
+                ty,
+                value.type_name(),
+                value
+            );
+            match line {
+                Some(line) => line_fatal(ErrorType::TypeError, line, message),
+                None => fatal(ErrorType::TypeError, message),
+            }
+        }
+        env.borrow_mut()
+            .define_as(param.name, value, Mutability::Mutable, param.ty);
+    }
+    body.eval(&mut env)
+}
+
+/// Integers from 2^53 on cannot all be represented as floats.
+const MAX_SAFE_INTEGER: f64 = 9007199254740992.0;
+
+/// Warns once per run that float arithmetic lost integer precision, since
+/// a loop would otherwise repeat the warning on every iteration.
+fn warn_precision(detail: String) {
+    if config::first_precision_warning() {
+        warning(format!(
+            "{}; floats cannot hold every integer beyond 2^53, so the result may be inexact",
+            detail
+        ));
+    }
+}
+
+fn warn_lossy_operand(value: &Value) {
+    if let Value::Int(n) = value
+        && n.unsigned_abs() > MAX_SAFE_INTEGER as u64
+    {
+        warn_precision(format!("integer `{}` is converted to a float", n));
+    }
+}
+
+/// The negation of a `bigint()` value.
+#[cfg(feature = "bigint")]
+fn negate_bigint(value: &Value) -> Option<Value> {
+    match value {
+        Value::BigInt(n) => Some(Value::BigInt(Rc::new(n.neg()))),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+fn negate_bigint(_: &Value) -> Option<Value> {
+    None
+}
+
+#[cfg(feature = "bigint")]
+fn abs_bigint(value: &Value) -> Option<Value> {
+    match value {
+        Value::BigInt(n) if **n < BigInt::default() => Some(Value::BigInt(Rc::new(n.neg()))),
+        Value::BigInt(_) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+fn abs_bigint(_: &Value) -> Option<Value> {
+    None
+}
+
+/// `n!` in math mode. An integer result too large for 64 bits becomes a
+/// float, like other integer arithmetic.
+fn factorial(value: Value) -> Value {
+    let n = match value {
+        Value::Int(n) => n,
+        Value::Number(n) if n.fract() == 0.0 && n <= i64::MAX as f64 => n as i64,
+        _ => {
+            type_error(format!(
+                "Invalid operand of `!`, expected a whole number, found `{}`",
+                value
+            ));
+            return Value::Number(0.0);
+        }
+    };
+    if n < 0 {
+        fatal(
+            ErrorType::RuntimeError,
+            format!("Factorial of negative number `{}`", n),
+        );
+    }
+    match (2..=n).try_fold(1i64, |acc, k| acc.checked_mul(k)) {
+        Some(product) if matches!(value, Value::Int(_)) => Value::Int(product),
+        // Stops once the product is infinite, which `171!` already is.
+        _ => Value::Number(
+            (2..=n)
+                .try_fold(1.0, |acc: f64, k| {
+                    Some(acc * k as f64).filter(|next| next.is_finite())
+                })
+                .unwrap_or(f64::INFINITY),
+        ),
+    }
+}
+
+/// `items * count` for a string or list and an integer, in either order:
+/// the items repeated `count` times. `None` for other operands. A list's
+/// elements are not copied, so `[[]] * 3` holds the same list three times.
+fn repeat(items: &Value, count: &Value) -> Option<Value> {
+    let Value::Int(count) = *count else {
+        return None;
+    };
+    let len = match items {
+        Value::String(s) => s.len(),
+        Value::List(list) => list.borrow().len(),
+        _ => return None,
+    };
+    let Ok(count) = usize::try_from(count) else {
+        fatal(
+            ErrorType::RuntimeError,
+            format!("Cannot repeat `{}` a negative number of times", items),
+        );
+    };
+    let too_large = || -> ! {
+        fatal(
+            ErrorType::RuntimeError,
+            format!("Repeating `{}` {} times is too large", items, count),
+        );
+    };
+    let total = len.checked_mul(count).unwrap_or_else(|| too_large());
+    Some(match items {
+        Value::String(s) => {
+            let mut repeated = String::new();
+            if repeated.try_reserve_exact(total).is_err() {
+                too_large();
+            }
+            (0..count).for_each(|_| repeated.push_str(s));
+            Value::String(repeated)
+        }
+        Value::List(list) => {
+            let mut repeated = Vec::new();
+            if repeated.try_reserve_exact(total).is_err() {
+                too_large();
+            }
+            let list = list.borrow();
+            repeated.extend(list.iter().cycle().take(total).cloned());
+            Value::List(Rc::new(RefCell::new(repeated)))
+        }
+        _ => unreachable!(),
+    })
+}
+
+/// Reports a type error. Evaluation continues with a default value unless
+/// strict mode is on.
+fn type_error(message: String) {
+    if config::strict() {
+        fatal(ErrorType::TypeError, message);
+    }
+    error(ErrorType::TypeError, message);
+}
+
+/// Map keys are strings; anything else is a type error.
+pub fn map_key(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        _ => fatal(
+            ErrorType::TypeError,
+            format!("Map key must be a string, found `{}`", key),
+        ),
+    }
+}
+
+/// Checks that `index` is a whole number inside `0..len` and converts it.
+pub fn list_index(index: &Value, len: usize) -> usize {
+    let n = match index {
+        Value::Int(n) => *n as f64,
+        Value::Number(n) => *n,
+        _ => fatal(
+            ErrorType::TypeError,
+            format!("Index must be a number, found `{}`", index),
+        ),
+    };
+    if n.fract() != 0.0 || n < 0.0 || n as usize >= len {
+        fatal(
+            ErrorType::RuntimeError,
+            format!("Index {} out of range for length {}", n, len),
+        );
+    }
+    n as usize
+}
+
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::Int(n) => write!(f, "{}", n),
             Self::Number(n) => write!(f, "{}", n),
             Self::Binary(l, op, r) => write!(f, "{} {} {}", l, op, r),
+            Self::Unary(Op::Factorial, r) => write!(f, "{}!", r),
+            Self::Unary(Op::Abs, r) => write!(f, "|{}|", r),
             Self::Unary(op, r) => write!(f, "{}{}", op, r),
             Self::Group(expr) => write!(f, "({})", expr),
             Self::Bool(b) => write!(f, "{}", b),
+            Self::Nil => write!(f, "nil"),
             Self::Logic(l, op, r) => write!(f, "({} {} {})", l, op, r),
-            Self::Variable(t) => write!(f, "{}", t.lexeme),
+            Self::Chain(first, rest) => {
+                write!(f, "({}", first)?;
+                for (op, expr) in rest {
+                    write!(f, " {} {}", op, expr)?;
+                }
+                write!(f, ")")
+            }
+            Self::Variable { name, .. } => write!(f, "{}", name),
             Self::String(s) => write!(f, "{}", s),
-            Self::Call { callee, args } => {
+            Self::Call { callee, args, .. } => {
                 let args_str = args
                     .iter()
                     .map(|arg| arg.to_string())
@@ -199,18 +1109,110 @@ impl fmt::Display for Expr {
                     .join(", ");
                 write!(f, "{}({})", callee, args_str)
             }
+            Self::Tuple(items) => {
+                let items_str = items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if items.len() == 1 {
+                    write!(f, "({},)", items_str)
+                } else {
+                    write!(f, "({})", items_str)
+                }
+            }
+            Self::List(items) => {
+                let items_str = items
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "[{}]", items_str)
+            }
+            Self::Spread(inner) => write!(f, "...{}", inner),
+            Self::Map(entries) => {
+                let entries_str = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{{{}}}", entries_str)
+            }
+            Self::Index(target, index) => write!(f, "{}[{}]", target, index),
+            Self::OptionalIndex(target, index) => write!(f, "{}?.[{}]", target, index),
+            Self::Field(target, name) => write!(f, "{}.{}", target, name),
+            Self::OptionalField(target, name) => write!(f, "{}?.{}", target, name),
+            Self::StructLit(def, fields) => {
+                let fields_str = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{} {{ {} }}", def, fields_str)
+            }
+            Self::Format(expr, spec) => write!(f, "{}:{}", expr, spec),
+            Self::Match(subject, arms) => {
+                write!(f, "match {} {{ ", subject)?;
+                for arm in arms {
+                    let patterns = arm.patterns.iter().map(|p| p.to_string());
+                    write!(f, "{} => ..., ", patterns.collect::<Vec<_>>().join(" | "))?;
+                }
+                write!(f, "}}")
+            }
+            Self::If(condition, _, else_stmt) => match else_stmt {
+                Some(_) => write!(f, "if {} {{ ... }} else {{ ... }}", condition),
+                None => write!(f, "if {} {{ ... }}", condition),
+            },
+            Self::Lambda(params, _) => {
+                let params = params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                write!(f, "|{}| ...", params.join(", "))
+            }
+            Self::Capture { names, lambda, .. } => {
+                let names = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+                write!(f, "[{}] {}", names.join(", "), lambda)
+            }
+            Self::Assign(target, value) => write!(f, "{} = {}", target, value),
+            Self::CompoundAssign { target, op, value } => {
+                write!(f, "{} {}= {}", target, op, value)
+            }
+            Self::Increment { target, op, prefix } => {
+                let op = if let Op::Add = op { "++" } else { "--" };
+                if *prefix {
+                    write!(f, "{}{}", op, target)
+                } else {
+                    write!(f, "{}{}", target, op)
+                }
+            }
+            Self::Ternary(cond, then, otherwise) => {
+                write!(f, "{} ? {} : {}", cond, then, otherwise)
+            }
         }
     }
 }
 
+/// Calls the method `name` that overloads an operator, if `receiver` is an
+/// instance whose class declares it.
+fn overload(receiver: &Value, name: &str, args: Vec<Value>) -> Option<Value> {
+    let Value::Struct(instance) = receiver else {
+        return None;
+    };
+    let name = Symbol::from(name);
+    instance.borrow().def.method(name)?;
+    let method = Value::BoundMethod {
+        receiver: instance.clone(),
+        name,
+    };
+    Some(call_value(method, args))
+}
+
 impl Op {
     fn new(op: &Token) -> Self {
         match op.token_type {
-            TokenType::Plus => Op::Add,
-            TokenType::Minus => Op::Sub,
-            TokenType::Star => Op::Mul,
-            TokenType::Slash => Op::Div,
-            TokenType::Modulo => Op::Mod,
+            TokenType::Plus | TokenType::PlusEqual => Op::Add,
+            TokenType::Minus | TokenType::MinusEqual => Op::Sub,
+            TokenType::Star | TokenType::StarEqual => Op::Mul,
+            TokenType::Slash | TokenType::SlashEqual => Op::Div,
+            TokenType::Modulo | TokenType::ModuloEqual => Op::Mod,
             TokenType::Ampersand => Op::And,
             TokenType::Pipe => Op::Or,
             TokenType::Bang => Op::Not,
@@ -221,99 +1223,364 @@ impl Op {
             TokenType::Less => Op::Lt,
             TokenType::LessEqual => Op::Le,
             _ => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     op.line,
                     format!("Unexpected operator `{}`", op.lexeme),
                 );
-                process::exit(1);
             }
         }
     }
 
+    /// The method a class declares to overload this operator between two
+    /// values, as in `fn __add(self, other)`. `!=` uses `__eq`.
+    fn method_name(&self) -> Option<&'static str> {
+        match self {
+            Op::Add => Some("__add"),
+            Op::Sub => Some("__sub"),
+            Op::Mul => Some("__mul"),
+            Op::Div => Some("__div"),
+            Op::FloorDiv => Some("__floordiv"),
+            Op::Mod => Some("__mod"),
+            Op::Pow => Some("__pow"),
+            Op::Eq | Op::Ne => Some("__eq"),
+            Op::Lt => Some("__lt"),
+            Op::Le => Some("__le"),
+            Op::Gt => Some("__gt"),
+            Op::Ge => Some("__ge"),
+            _ => None,
+        }
+    }
+
+    /// The value after `value++` or `value--`. Anything but a number is
+    /// a type error and stays as it is.
+    fn step(&self, value: &Value) -> Value {
+        if value.as_f64().is_none() && !matches!(value, Value::Decimal(_)) {
+            let verb = if let Op::Add = self {
+                "increment"
+            } else {
+                "decrement"
+            };
+            type_error(format!(
+                "Cannot {} {} `{}`, expected a number",
+                verb,
+                value.type_name(),
+                value
+            ));
+            return value.clone();
+        }
+        self.eval_binary(value.clone(), Value::Int(1))
+    }
+
     fn eval_unary(&self, right: Value) -> Value {
+        if let Op::Sub = self
+            && let Some(result) = overload(&right, "__neg", Vec::new())
+        {
+            return result;
+        }
         match self {
-            Op::Not => {
-                if let Value::Bool(b) = right {
-                    Value::Bool(!b)
-                } else {
-                    error(
-                        ErrorType::TypeError,
-                        "Invalid operand, expected boolean".to_string(),
-                    );
+            Op::Not => match right {
+                Value::Bool(b) => Value::Bool(!b),
+                Value::Nil => Value::Bool(true),
+                _ if config::strict() => {
+                    type_error("Invalid operand, expected boolean".to_string());
                     Value::Bool(false)
                 }
-            }
+                value => Value::Bool(!value.truthy()),
+            },
             Op::Sub => {
-                if let Value::Number(n) = right {
+                if let Value::Int(n) = right {
+                    n.checked_neg()
+                        .map_or(Value::Number(-(n as f64)), Value::Int)
+                } else if let Value::Number(n) = right {
                     Value::Number(-n)
+                } else if let Value::Decimal(n) = right {
+                    Value::Decimal(Rc::new(-*n))
+                } else if let Some(n) = negate_bigint(&right) {
+                    n
                 } else {
-                    error(
-                        ErrorType::TypeError,
-                        "Invalid operand, expected number".to_string(),
-                    );
+                    type_error("Invalid operand, expected number".to_string());
                     Value::Number(0.0)
                 }
             }
+            Op::Abs => match right {
+                Value::Int(n) => n
+                    .checked_abs()
+                    .map_or(Value::Number((n as f64).abs()), Value::Int),
+                Value::Number(n) => Value::Number(n.abs()),
+                Value::Decimal(n) if *n < Decimal::default() => Value::Decimal(Rc::new(-*n)),
+                Value::Decimal(_) => right,
+                _ => abs_bigint(&right).unwrap_or_else(|| {
+                    type_error(format!(
+                        "Invalid operand of `|x|`, expected number, found `{}`",
+                        right
+                    ));
+                    Value::Number(0.0)
+                }),
+            },
+            Op::Factorial => factorial(right),
             _ => {
-                error(
-                    ErrorType::TypeError,
-                    format!("Invalid unary operator `{}`", self),
-                );
+                type_error(format!("Invalid unary operator `{}`", self));
                 Value::Number(0.0)
             }
         }
     }
 
-    fn eval_binary(&self, left: Value, right: Value) -> f64 {
-        let (left, right) = match (left, right) {
-            (Value::Number(l), Value::Number(r)) => (l, r),
+    fn eval_binary(&self, left: Value, right: Value) -> Value {
+        if let Value::Struct(_) = left
+            && let Some(name) = self.method_name()
+            && let Some(result) = overload(&left, name, vec![right.clone()])
+        {
+            return result;
+        }
+        if let Op::Add = self
+            && (matches!(left, Value::String(_)) || matches!(right, Value::String(_)))
+        {
+            if config::strict() && !matches!((&left, &right), (Value::String(_), Value::String(_)))
+            {
+                type_error(format!(
+                    "Cannot add {} `{}` and {} `{}`, convert with `str()` first",
+                    left.type_name(),
+                    left,
+                    right.type_name(),
+                    right
+                ));
+            }
+            return Value::String(format!("{}{}", left, right));
+        }
+        if let Op::Mul = self
+            && let Some(result) = repeat(&left, &right).or_else(|| repeat(&right, &left))
+        {
+            return result;
+        }
+        if let (Value::Int(l), Value::Int(r)) = (&left, &right) {
+            return self.int_binary(*l, *r);
+        }
+        #[cfg(feature = "bigint")]
+        if (matches!(left, Value::BigInt(_)) || matches!(right, Value::BigInt(_)))
+            && let (Some(l), Some(r)) = (left.to_bigint(), right.to_bigint())
+        {
+            return self.big_binary(&l, &r);
+        }
+        if matches!(left, Value::Decimal(_)) || matches!(right, Value::Decimal(_)) {
+            return self.decimal_binary(&left, &right);
+        }
+        if config::strict() {
+            warn_lossy_operand(&left);
+            warn_lossy_operand(&right);
+        }
+        let (left, right) = match (left.as_f64(), right.as_f64()) {
+            (Some(l), Some(r)) => (l, r),
             _ => {
-                error(
-                    ErrorType::TypeError,
-                    "Invalid operands, expected numbers".to_string(),
-                );
+                type_error("Invalid operands, expected numbers".to_string());
                 (0.0, 0.0)
             }
         };
-        match self {
+        let num = match self {
             Op::Add => left + right,
             Op::Sub => left - right,
             Op::Mul => left * right,
             Op::Div => left / right,
-            Op::Mod => left % right,
+            Op::FloorDiv => (left / right).floor(),
+            Op::Mod => left - right * (left / right).floor(),
+            Op::Pow => left.powf(right),
             _ => {
-                error(
-                    ErrorType::TypeError,
-                    "Invalid operands, expected numbers".to_string(),
-                );
+                type_error("Invalid operands, expected numbers".to_string());
                 0.0
             }
+        };
+        if config::strict() && num.fract() == 0.0 && num.abs() > MAX_SAFE_INTEGER {
+            warn_precision(format!("`{} {} {}` is {}", left, self, right, num));
+        }
+        Value::Number(num)
+    }
+
+    /// Decimal arithmetic with a decimal or integer on the other side.
+    /// Floats are refused, since mixing them in would make the result inexact.
+    fn decimal_binary(&self, left: &Value, right: &Value) -> Value {
+        let (Some(l), Some(r)) = (left.to_decimal(), right.to_decimal()) else {
+            let (decimal, other) = match left {
+                Value::Decimal(_) => (left, right),
+                _ => (right, left),
+            };
+            type_error(format!(
+                "Cannot mix decimal `{}` with {} `{}`; convert it with decimal()",
+                decimal,
+                other.type_name(),
+                other
+            ));
+            return Value::Decimal(Rc::default());
+        };
+        if matches!(self, Op::Div | Op::FloorDiv | Op::Mod) && r.is_zero() {
+            fatal(ErrorType::RuntimeError, "Division by zero".to_string());
+        }
+        let result = match self {
+            Op::Add => l.checked_add(r),
+            Op::Sub => l.checked_sub(r),
+            Op::Mul => l.checked_mul(r),
+            Op::Div => l.checked_div(r),
+            Op::FloorDiv => l.checked_floor_div(r),
+            Op::Mod => l.checked_rem(r),
+            Op::Pow => match u32::try_from(r.floor()) {
+                Ok(exp) if Decimal::from_i64(exp as i64) == r => l.checked_pow(exp),
+                _ => {
+                    type_error(format!(
+                        "A decimal can only be raised to a whole power of 0 or more, not `{}`",
+                        r
+                    ));
+                    Some(Decimal::default())
+                }
+            },
+            _ => {
+                type_error("Invalid operands, expected numbers".to_string());
+                Some(Decimal::default())
+            }
+        };
+        match result {
+            Some(n) => Value::Decimal(Rc::new(n)),
+            None => fatal(
+                ErrorType::RuntimeError,
+                format!("Decimal overflow in `{} {} {}`", l, self, r),
+            ),
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    fn big_binary(&self, l: &BigInt, r: &BigInt) -> Value {
+        let result = match self {
+            Op::Add => l.add(r),
+            Op::Sub => l.sub(r),
+            Op::Mul => l.mul(r),
+            Op::Div | Op::FloorDiv | Op::Mod => {
+                let Some((q, m)) = l.div_mod(r) else {
+                    fatal(ErrorType::RuntimeError, "Division by zero".to_string());
+                };
+                if matches!(self, Op::Mod) { m } else { q }
+            }
+            Op::Pow => match r.to_i64().and_then(|r| u32::try_from(r).ok()) {
+                Some(exp) => l.pow(exp),
+                None => return Value::Number(l.to_f64().powf(r.to_f64())),
+            },
+            _ => {
+                type_error("Invalid operands, expected numbers".to_string());
+                BigInt::default()
+            }
+        };
+        Value::BigInt(Rc::new(result))
+    }
+
+    /// Integer arithmetic. `/` and `%` round toward negative infinity, so
+    /// `/` is the same as `div`, and a result that does not fit in an
+    /// integer becomes a float.
+    fn int_binary(&self, l: i64, r: i64) -> Value {
+        if matches!(self, Op::Div | Op::FloorDiv | Op::Mod) && r == 0 {
+            fatal(ErrorType::RuntimeError, "Division by zero".to_string());
+        }
+        if matches!(self, Op::Pow) && r < 0 {
+            return Value::Number((l as f64).powf(r as f64));
+        }
+        let result = match self {
+            Op::Add => l.checked_add(r),
+            Op::Sub => l.checked_sub(r),
+            Op::Mul => l.checked_mul(r),
+            Op::Div | Op::FloorDiv => l.checked_div(r).map(|q| {
+                if l % r != 0 && (l < 0) != (r < 0) {
+                    q - 1
+                } else {
+                    q
+                }
+            }),
+            Op::Pow => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+            Op::Mod => l.checked_rem(r).map(|m| {
+                if m != 0 && (m < 0) != (r < 0) {
+                    m + r
+                } else {
+                    m
+                }
+            }),
+            _ => {
+                type_error("Invalid operands, expected numbers".to_string());
+                Some(0)
+            }
+        };
+        match result {
+            Some(n) => Value::Int(n),
+            None => {
+                if config::strict() {
+                    warn_precision(format!("`{} {} {}` overflows an integer", l, self, r));
+                }
+                self.eval_binary(Value::Number(l as f64), Value::Number(r as f64))
+            }
         }
     }
 
     fn eval_logic(&self, l: Value, r: Value) -> Value {
+        if let Value::Struct(_) = l
+            && let Some(name) = self.method_name()
+            && let Some(result) = overload(&l, name, vec![r.clone()])
+        {
+            return match (self, result) {
+                (Op::Ne, Value::Bool(equal)) => Value::Bool(!equal),
+                (_, Value::Bool(b)) => Value::Bool(b),
+                (_, result) => {
+                    type_error(format!(
+                        "`{}` must return a bool, not `{}`",
+                        name,
+                        result.type_name()
+                    ));
+                    Value::Bool(false)
+                }
+            };
+        }
+        // Outside strict mode `&` and `|` take any values by truthiness.
+        if matches!(self, Op::And | Op::Or) && !config::strict() {
+            let (l, r) = (l.truthy(), r.truthy());
+            return Value::Bool(if let Op::And = self { l && r } else { l || r });
+        }
         match (&l, &r) {
             (Value::Bool(l), Value::Bool(r)) => {
                 let res = self.logic_bool(*l, *r);
                 Value::Bool(res)
             }
-            (Value::Number(l), Value::Number(r)) => {
-                let res = self.logic_num(*l, *r);
+            (Value::Int(l), Value::Int(r)) => Value::Bool(self.logic_num(*l, *r)),
+            (Value::Decimal(_), Value::Decimal(_) | Value::Int(_))
+            | (Value::Int(_), Value::Decimal(_)) => {
+                Value::Bool(self.logic_num(l.to_decimal().unwrap(), r.to_decimal().unwrap()))
+            }
+            #[cfg(feature = "bigint")]
+            (Value::BigInt(_) | Value::Int(_), Value::BigInt(_) | Value::Int(_)) => {
+                Value::Bool(self.logic_num(l.to_bigint().unwrap(), r.to_bigint().unwrap()))
+            }
+            (Value::Int(_) | Value::Number(_), Value::Int(_) | Value::Number(_)) => {
+                let res = self.logic_num(l.as_f64().unwrap(), r.as_f64().unwrap());
                 Value::Bool(res)
             }
             (Value::String(l), Value::String(r)) => {
                 let res = self.logic_string(l.clone(), r.clone());
                 Value::Bool(res)
             }
+            // Anything can be compared with `nil`, which only equals itself.
+            (Value::Nil, _) | (_, Value::Nil) if matches!(self, Op::Eq | Op::Ne) => {
+                let both = matches!((&l, &r), (Value::Nil, Value::Nil));
+                Value::Bool(both == matches!(self, Op::Eq))
+            }
+            (Value::List(_), Value::List(_))
+            | (Value::Tuple(_), Value::Tuple(_))
+            | (Value::Map(_), Value::Map(_))
+            | (Value::Struct(_), Value::Struct(_))
+            | (Value::StructDef(_), Value::StructDef(_)) => match self {
+                Op::Eq => Value::Bool(l.deep_eq(&r)),
+                Op::Ne => Value::Bool(!l.deep_eq(&r)),
+                _ => {
+                    type_error(format!("Invalid operator `{}` for collections", self));
+                    Value::Bool(false)
+                }
+            },
             _ => {
-                error(
-                    ErrorType::TypeError,
-                    format!(
-                        "Invalid Comparison Type: `{:?}` and `{:?}` both must be same type",
-                        l, r
-                    ),
-                );
+                type_error(format!(
+                    "Invalid Comparison Type: `{}` and `{}` both must be same type",
+                    l, r
+                ));
                 Value::Number(0.0)
             }
         }
@@ -321,8 +1588,6 @@ impl Op {
 
     fn logic_string(&self, l: String, r: String) -> bool {
         match self {
-            Op::And => !l.is_empty() && !r.is_empty(),
-            Op::Or => !l.is_empty() || !r.is_empty(),
             Op::Eq => l == r,
             Op::Ne => l != r,
             Op::Gt => l > r,
@@ -330,15 +1595,13 @@ impl Op {
             Op::Lt => l < r,
             Op::Le => l <= r,
             _ => {
-                error(
-                    ErrorType::TypeError,
-                    format!("Invalid operator `{}` for string", self),
-                );
+                type_error(format!("Invalid operator `{}` for string", self));
                 false
             }
         }
     }
 
+    #[allow(clippy::bool_comparison)]
     fn logic_bool(&self, l: bool, r: bool) -> bool {
         match self {
             Op::And => l && r,
@@ -350,19 +1613,14 @@ impl Op {
             Op::Lt => l < r,
             Op::Le => l <= r,
             _ => {
-                error(
-                    ErrorType::TypeError,
-                    format!("Invalid operator `{}` for boolean", self),
-                );
+                type_error(format!("Invalid operator `{}` for boolean", self));
                 false
             }
         }
     }
 
-    fn logic_num(&self, l: f64, r: f64) -> bool {
+    fn logic_num<T: PartialOrd + Default>(&self, l: T, r: T) -> bool {
         match self {
-            Op::And => l > 0.0 && r > 0.0,
-            Op::Or => l > 0.0 || r > 0.0,
             Op::Eq => l == r,
             Op::Ne => l != r,
             Op::Gt => l > r,
@@ -370,10 +1628,7 @@ impl Op {
             Op::Lt => l < r,
             Op::Le => l <= r,
             _ => {
-                error(
-                    ErrorType::TypeError,
-                    format!("Invalid operator `{}` for number", self),
-                );
+                type_error(format!("Invalid operator `{}` for number", self));
                 false
             }
         }
@@ -387,7 +1642,11 @@ impl fmt::Display for Op {
             Self::Sub => write!(f, "-"),
             Self::Mul => write!(f, "*"),
             Self::Div => write!(f, "/"),
+            Self::FloorDiv => write!(f, "div"),
             Self::Mod => write!(f, "%"),
+            Self::Pow => write!(f, "^"),
+            Self::Factorial => write!(f, "!"),
+            Self::Abs => write!(f, "|"),
             Self::And => write!(f, "&"),
             Self::Or => write!(f, "|"),
             Self::Not => write!(f, "!"),
@@ -0,0 +1,126 @@
+use std::fmt;
+
+use crate::env::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// A format spec as written after the `:` in `${value:>10.2}` or passed to
+/// `format()`: `[[fill]align][width][.precision]`.
+///
+/// Precision is the number of decimals for numbers and the maximum length
+/// for everything else. Numbers are right aligned by default, other values
+/// left aligned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid format spec `{}`", spec);
+        let mut chars = spec.chars();
+        let mut fill = ' ';
+        let mut align = None;
+        let mut lookahead = chars.clone();
+        let first = lookahead.next();
+        if let Some(a) = lookahead.next().and_then(align_of) {
+            fill = first.unwrap();
+            align = Some(a);
+            chars.next();
+            chars.next();
+        } else if let Some(a) = first.and_then(align_of) {
+            align = Some(a);
+            chars.next();
+        }
+
+        let rest = chars.collect::<String>();
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => {
+                let precision = precision.parse::<usize>().map_err(|_| invalid())?;
+                (width, Some(precision))
+            }
+            None => (rest.as_str(), None),
+        };
+        let width = if width.is_empty() {
+            None
+        } else {
+            Some(width.parse::<usize>().map_err(|_| invalid())?)
+        };
+        Ok(FormatSpec {
+            fill,
+            align,
+            width,
+            precision,
+        })
+    }
+
+    pub fn apply(&self, value: &Value) -> String {
+        let text = match (value, self.precision) {
+            (Value::Decimal(n), Some(precision)) => n.to_fixed(precision as u32),
+            (Value::Int(_) | Value::Number(_), Some(precision)) => {
+                format!("{:.*}", precision, value.as_f64().unwrap())
+            }
+            (_, Some(precision)) => value.to_string().chars().take(precision).collect(),
+            (_, None) => value.to_string(),
+        };
+        let Some(width) = self.width else {
+            return text;
+        };
+        let len = text.chars().count();
+        if len >= width {
+            return text;
+        }
+        let pad = width - len;
+        let align = self.align.unwrap_or(match value {
+            Value::Int(_) | Value::Number(_) | Value::Decimal(_) => Align::Right,
+            _ => Align::Left,
+        });
+        let (before, after) = match align {
+            Align::Left => (0, pad),
+            Align::Right => (pad, 0),
+            Align::Center => (pad / 2, pad - pad / 2),
+        };
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        format!("{}{}{}", fill(before), text, fill(after))
+    }
+}
+
+impl fmt::Display for FormatSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(align) = self.align {
+            if self.fill != ' ' {
+                write!(f, "{}", self.fill)?;
+            }
+            let align = match align {
+                Align::Left => '<',
+                Align::Right => '>',
+                Align::Center => '^',
+            };
+            write!(f, "{}", align)?;
+        }
+        if let Some(width) = self.width {
+            write!(f, "{}", width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        Ok(())
+    }
+}
+
+fn align_of(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
@@ -0,0 +1,577 @@
+use crate::{
+    cst::{self, CstToken, Trivia},
+    parser::Parser,
+    source::Source,
+    stmt::Stmt,
+    token::{TokenType, match_subject_follows, starts_operand},
+};
+
+/// How `riku fmt` lays code out, read from the `[fmt]` table of
+/// `riku.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatConfig {
+    /// Spaces per level of nesting.
+    pub indent: usize,
+    /// Lines longer than this have their list and map literals broken up,
+    /// one element per line. Lines that cannot be broken are left long.
+    pub max_width: usize,
+    pub brace_style: BraceStyle,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        FormatConfig {
+            indent: 4,
+            max_width: 100,
+            brace_style: BraceStyle::SameLine,
+        }
+    }
+}
+
+/// Where the `{` of an `if`, `else`, `while`, `for`, `fn`, `struct` or
+/// `class` block goes. `match`, `loop` and `do` always keep theirs on the
+/// same line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BraceStyle {
+    SameLine,
+    NextLine,
+}
+
+#[derive(Clone, Copy)]
+enum Item<'a> {
+    Token(&'a CstToken),
+    Comment(&'a str),
+    /// A `,` added after the last element of a literal broken over lines.
+    TrailingComma,
+}
+
+/// Formats riku source code with the default settings.
+pub fn format(input: &str) -> String {
+    format_with(input, &FormatConfig::default())
+}
+
+/// Formats riku source code. Comments are kept, indentation is rebuilt from
+/// the brace structure and spacing between tokens is normalized.
+pub fn format_with(input: &str, config: &FormatConfig) -> String {
+    let tree = cst::parse(input);
+    let mut lines: Vec<Vec<Item>> = vec![Vec::new()];
+    for token in tree.tokens() {
+        for trivia in &token.leading {
+            if let Trivia::Comment(c) = trivia {
+                lines.last_mut().unwrap().push(Item::Comment(c.trim_end()));
+            }
+        }
+        match token.token_type {
+            TokenType::EOF => {}
+            TokenType::EOL if token.text == "\n" => lines.push(Vec::new()),
+            _ => lines.last_mut().unwrap().push(Item::Token(token)),
+        }
+    }
+
+    let lines = match config.brace_style {
+        BraceStyle::SameLine => join_braces(lines),
+        BraceStyle::NextLine => split_braces(lines),
+    };
+
+    let mut out = String::new();
+    let mut depth: usize = 0;
+    let mut blank = false;
+    for line in &lines {
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        emit_line(&mut out, line, &mut depth, config);
+    }
+    out
+}
+
+/// Writes one line at the indentation of `depth`, breaking it up first if
+/// it is too wide, and moves `depth` past its brackets.
+fn emit_line(out: &mut String, line: &[Item], depth: &mut usize, config: &FormatConfig) {
+    let closes = matches!(line.first(), Some(Item::Token(t)) if matches!(t.token_type, TokenType::RBrace | TokenType::RBracket));
+    let indent = if closes {
+        depth.saturating_sub(1)
+    } else {
+        *depth
+    };
+    let text = format_line(line);
+    if indent * config.indent + text.chars().count() > config.max_width
+        && let Some(parts) = break_literal(line)
+    {
+        for part in &parts {
+            emit_line(out, part, depth, config);
+        }
+        return;
+    }
+    out.push_str(&" ".repeat(indent * config.indent));
+    out.push_str(&text);
+    out.push('\n');
+    for item in line {
+        if let Item::Token(t) = item {
+            match t.token_type {
+                TokenType::LBrace | TokenType::LBracket => *depth += 1,
+                TokenType::RBrace | TokenType::RBracket => *depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Splits the first list or map literal that is not nested in other
+/// brackets into one line per element, with a trailing comma, as in
+///
+/// ```text
+/// let colors = [
+///     "red",
+///     "green",
+/// ]
+/// ```
+///
+/// Gives `None` if the line holds no such literal with at least two
+/// elements, or if a comment sits in the middle of it.
+fn break_literal<'a>(line: &[Item<'a>]) -> Option<Vec<Vec<Item<'a>>>> {
+    let end = line
+        .iter()
+        .rposition(|item| !matches!(item, Item::Comment(_)))?
+        + 1;
+    if line[..end]
+        .iter()
+        .any(|item| matches!(item, Item::Comment(_)))
+    {
+        return None;
+    }
+    let mut depth = 0;
+    let mut prev: Option<&CstToken> = None;
+    let mut open = None;
+    for (i, item) in line[..end].iter().enumerate() {
+        let Item::Token(token) = item else {
+            continue;
+        };
+        if depth == 0 && opens_literal(prev, token) {
+            open = Some(i);
+            break;
+        }
+        depth += bracket_change(token);
+        prev = Some(token);
+    }
+    let open = open?;
+
+    let mut parts = vec![line[..=open].to_vec()];
+    let mut element = Vec::new();
+    let mut depth = 0;
+    for (i, item) in line[..end].iter().enumerate().skip(open + 1) {
+        let change = match item {
+            Item::Token(token) => bracket_change(token),
+            _ => 0,
+        };
+        match item {
+            Item::Token(token) if depth == 0 && token.token_type == TokenType::Comma => {
+                element.push(*item);
+                parts.push(std::mem::take(&mut element));
+                continue;
+            }
+            _ if depth + change < 0 => {
+                if !element.is_empty() {
+                    element.push(Item::TrailingComma);
+                    parts.push(element);
+                }
+                // The opening line and at least two elements.
+                if parts.len() < 3 {
+                    return None;
+                }
+                parts.push(line[i..].to_vec());
+                return Some(parts);
+            }
+            _ => {}
+        }
+        depth += change;
+        element.push(*item);
+    }
+    None
+}
+
+/// Whether `token` opens a list or map literal rather than an index or a
+/// block.
+fn opens_literal(prev: Option<&CstToken>, token: &CstToken) -> bool {
+    match token.token_type {
+        TokenType::LBracket => !ends_operand(prev),
+        TokenType::LBrace => prev.is_none_or(|prev| {
+            matches!(
+                prev.token_type,
+                TokenType::Equal
+                    | TokenType::LParen
+                    | TokenType::LBracket
+                    | TokenType::Comma
+                    | TokenType::Colon
+                    | TokenType::FatArrow
+                    | TokenType::Return
+            )
+        }),
+        _ => false,
+    }
+}
+
+fn bracket_change(token: &CstToken) -> i32 {
+    match token.token_type {
+        TokenType::LParen | TokenType::LBracket | TokenType::LBrace | TokenType::InterpStart => 1,
+        TokenType::RParen | TokenType::RBracket | TokenType::RBrace | TokenType::InterpEnd => -1,
+        _ => 0,
+    }
+}
+
+fn first_token_type(line: &[Item]) -> Option<TokenType> {
+    line.iter().find_map(|item| match item {
+        Item::Token(t) => Some(t.token_type),
+        _ => None,
+    })
+}
+
+/// Whether `line` starts a block whose brace follows the brace style,
+/// possibly after the `}` of the block before an `else`.
+fn starts_block(line: &[Item]) -> bool {
+    let mut tokens = line
+        .iter()
+        .filter_map(|item| match item {
+            Item::Token(t) => Some(t.token_type),
+            _ => None,
+        })
+        .peekable();
+    let closes = tokens.next_if_eq(&TokenType::RBrace).is_some();
+    if is_elif(line, usize::from(closes)) {
+        return true;
+    }
+    match tokens.next() {
+        Some(
+            TokenType::If
+            | TokenType::Else
+            | TokenType::While
+            | TokenType::For
+            | TokenType::Struct
+            | TokenType::Class,
+        ) => true,
+        Some(TokenType::Fn) => tokens.next() == Some(TokenType::Ident),
+        _ => false,
+    }
+}
+
+/// For `BraceStyle::NextLine`: moves the `{` that ends a block's first
+/// line onto a line of its own, and `else` or `elif` onto the line after
+/// the `}`.
+fn split_braces(lines: Vec<Vec<Item>>) -> Vec<Vec<Item>> {
+    let mut out = Vec::new();
+    for mut line in lines {
+        if first_token_type(&line) == Some(TokenType::RBrace)
+            && (matches!(line.get(1), Some(Item::Token(t)) if t.token_type == TokenType::Else)
+                || is_elif(&line, 1))
+        {
+            let rest = line.split_off(1);
+            out.push(line);
+            line = rest;
+        }
+        if starts_block(&line)
+            && let Some(brace) = line
+                .iter()
+                .rposition(|item| !matches!(item, Item::Comment(_)))
+            && matches!(line[brace], Item::Token(t) if t.token_type == TokenType::LBrace)
+            && brace > 0
+        {
+            let rest = line.split_off(brace);
+            out.push(line);
+            line = rest;
+        }
+        out.push(line);
+    }
+    out
+}
+
+/// For `BraceStyle::SameLine`: joins a `{` on its own line to the block's
+/// first line, and an `else` or `elif` to the `}` before it. Blank lines between
+/// them are dropped.
+fn join_braces(lines: Vec<Vec<Item>>) -> Vec<Vec<Item>> {
+    let mut out: Vec<Vec<Item>> = Vec::new();
+    for line in lines {
+        if let Some(prev) = out.iter().rposition(|l| !l.is_empty())
+            && joins_previous(&out[prev], &line)
+        {
+            out.truncate(prev + 1);
+            out[prev].extend(line);
+            continue;
+        }
+        out.push(line);
+    }
+    out
+}
+
+/// Whether `line` starts with a `{`, `else` or `elif` that belongs at the end of
+/// `prev`. A trailing comment on `prev` keeps them apart.
+fn joins_previous(prev: &[Item], line: &[Item]) -> bool {
+    let Some(Item::Token(last)) = prev.last() else {
+        return false;
+    };
+    match line.first() {
+        Some(Item::Token(t)) if t.token_type == TokenType::LBrace => {
+            starts_block(prev) && !matches!(last.token_type, TokenType::LBrace | TokenType::RBrace)
+        }
+        Some(Item::Token(t)) if t.token_type == TokenType::Else || is_elif(line, 0) => {
+            last.token_type == TokenType::RBrace
+        }
+        _ => false,
+    }
+}
+
+/// Whether `line[i]` is an `elif` continuing an `if`: the name followed by
+/// a condition, as the parser reads it after a `}`.
+fn is_elif(line: &[Item], i: usize) -> bool {
+    matches!(line.get(i), Some(Item::Token(t)) if t.token_type == TokenType::Ident && t.text == "elif")
+        && matches!(line.get(i + 1), Some(Item::Token(t)) if starts_operand(t.token_type))
+}
+
+/// Checks that formatting `input` keeps its meaning: the formatted text must
+/// parse to the same statements as the original. Lines and spans are left
+/// out of the comparison, since moving code around is what formatting does.
+pub fn verify(input: &str) -> Result<(), String> {
+    verify_with(input, &FormatConfig::default())
+}
+
+/// Like `verify`, with the given settings. Also checks that formatting is
+/// idempotent: formatting the formatted text again must not change it.
+pub fn verify_with(input: &str, config: &FormatConfig) -> Result<(), String> {
+    let formatted = format_with(input, config);
+    let again = format_with(&formatted, config);
+    if again != formatted {
+        let same = formatted
+            .lines()
+            .zip(again.lines())
+            .take_while(|(a, b)| a == b)
+            .count();
+        return Err(format!(
+            "formatting is not idempotent: a second pass changes line {}",
+            same + 1
+        ));
+    }
+    let before = statement_shapes(input);
+    let after = statement_shapes(&formatted);
+    match before.iter().zip(&after).position(|(b, a)| b != a) {
+        Some(i) => Err(format!(
+            "statement {} parses differently once formatted",
+            i + 1
+        )),
+        None if before.len() != after.len() => Err(format!(
+            "{} statements become {} once formatted",
+            before.len(),
+            after.len()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The parsed top-level statements, printed without source positions.
+fn statement_shapes(input: &str) -> Vec<String> {
+    let mut source = Source::new(input.to_string());
+    source.tokenize();
+    let mut parser = Parser::new(source.get_tokens());
+    parser.parse();
+    parser.into_stmts().iter().map(Stmt::shape).collect()
+}
+
+fn format_line(line: &[Item]) -> String {
+    let mut out = String::new();
+    let mut prev: Option<&CstToken> = None;
+    let mut prev_unary = false;
+    // `?` waiting for the `:` of a ternary, which is spaced unlike the
+    // colon of a map entry.
+    let mut ternaries = 0;
+    // Inside the `|a, b|` parameter list of a lambda.
+    let mut params = false;
+    let tokens = line
+        .iter()
+        .filter_map(|item| match item {
+            Item::Token(token) => Some(*token),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut index = 0;
+    let mut prev_keyword = false;
+    for item in line {
+        match item {
+            Item::Comment(c) => {
+                if !out.is_empty() {
+                    out.push(' ');
+                }
+                out.push_str(c);
+            }
+            Item::TrailingComma => out.push(','),
+            Item::Token(token) => {
+                let unary = is_unary(prev, token) || (prev_keyword && is_unary(None, token));
+                let ternary_colon = token.token_type == TokenType::Colon && ternaries > 0;
+                let optional_type = is_optional_type(&tokens, index);
+                let opens_params =
+                    token.token_type == TokenType::Pipe && !params && !ends_operand(prev);
+                let closes_params = token.token_type == TokenType::Pipe && params;
+                let after_open = prev.is_some_and(|p| p.token_type == TokenType::Pipe) && params;
+                match token.token_type {
+                    TokenType::Question if !optional_type => ternaries += 1,
+                    TokenType::Colon if ternary_colon => ternaries -= 1,
+                    _ => {}
+                }
+                if let Some(prev) = prev
+                    && !closes_params
+                    && !after_open
+                    && !optional_type
+                    && (ternary_colon || prev_keyword || needs_space(prev, prev_unary, token))
+                {
+                    out.push(' ');
+                }
+                if opens_params || closes_params {
+                    params = opens_params;
+                }
+                out.push_str(&token.text);
+                prev = Some(token);
+                prev_unary = unary;
+                prev_keyword = is_contextual_keyword(&tokens, index);
+                index += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Whether `tokens[i]` is the `?` of an optional type such as
+/// `let name: string? = nil`, which is written without a space, rather
+/// than the start of a ternary.
+fn is_optional_type(tokens: &[&CstToken], i: usize) -> bool {
+    tokens[i].token_type == TokenType::Question
+        && i >= 2
+        && matches!(tokens[i - 1].token_type, TokenType::Ident | TokenType::Nil)
+        && matches!(
+            tokens[i - 2].token_type,
+            TokenType::Colon | TokenType::Arrow
+        )
+        && tokens.get(i + 1).is_none_or(|t| {
+            matches!(
+                t.token_type,
+                TokenType::Equal
+                    | TokenType::Comma
+                    | TokenType::RParen
+                    | TokenType::LBrace
+                    | TokenType::Pipe
+                    | TokenType::EOL
+            )
+        })
+}
+
+/// Whether the name at `tokens[i]` is used as a contextual keyword, so it
+/// is spaced like a keyword: `in [1, 2]`, not `in[1, 2]` as for an index.
+fn is_contextual_keyword(tokens: &[&CstToken], i: usize) -> bool {
+    let token = tokens[i];
+    if token.token_type != TokenType::Ident {
+        return false;
+    }
+    let is = |back: usize, token_type: TokenType| {
+        i.checked_sub(back)
+            .is_some_and(|j| tokens[j].token_type == token_type)
+    };
+    match token.text.as_str() {
+        "in" => is(2, TokenType::For) || (is(4, TokenType::For) && is(2, TokenType::Comma)),
+        "mut" => is(1, TokenType::Let),
+        "elif" => {
+            tokens[..i]
+                .iter()
+                .rfind(|t| t.token_type != TokenType::EOL)
+                .is_some_and(|t| t.token_type == TokenType::RBrace)
+                && tokens
+                    .get(i + 1)
+                    .is_some_and(|t| starts_operand(t.token_type))
+        }
+        "step" => {
+            tokens[..i].iter().any(|t| t.token_type == TokenType::For)
+                && tokens[..i]
+                    .iter()
+                    .any(|t| t.token_type == TokenType::DotDot)
+        }
+        "match" => match_subject_follows(tokens[i + 1..].iter().map(|t| t.token_type)),
+        "mod" | "div" => {
+            ends_operand(i.checked_sub(1).map(|j| tokens[j]))
+                && tokens
+                    .get(i + 1)
+                    .is_some_and(|t| starts_operand(t.token_type))
+        }
+        // `assert(x)` is left as written; only a following operand such as
+        // `-x` needs the keyword spacing.
+        "assert" => {
+            (i == 0 || matches!(tokens[i - 1].token_type, TokenType::EOL | TokenType::LBrace))
+                && tokens.get(i + 1).is_some_and(|t| {
+                    t.token_type != TokenType::LParen && starts_operand(t.token_type)
+                })
+        }
+        _ => false,
+    }
+}
+
+fn is_unary(prev: Option<&CstToken>, token: &CstToken) -> bool {
+    if !matches!(
+        token.token_type,
+        TokenType::Minus | TokenType::Bang | TokenType::PlusPlus | TokenType::MinusMinus
+    ) {
+        return false;
+    }
+    !ends_operand(prev)
+}
+
+/// Whether `prev` can end an operand, so that a following `-` or `|` is a
+/// binary operator. A `++` or `--` that could start one is only ever
+/// written after an operand it ends, as in `i++ - 1`.
+fn ends_operand(prev: Option<&CstToken>) -> bool {
+    prev.is_some_and(|prev| {
+        matches!(
+            prev.token_type,
+            TokenType::Number
+                | TokenType::String
+                | TokenType::InterpEnd
+                | TokenType::Ident
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Nil
+                | TokenType::RParen
+                | TokenType::RBracket
+                | TokenType::PlusPlus
+                | TokenType::MinusMinus
+        )
+    })
+}
+
+fn needs_space(prev: &CstToken, prev_unary: bool, token: &CstToken) -> bool {
+    use TokenType::*;
+    if prev_unary {
+        return false;
+    }
+    !matches!(
+        (prev.token_type, token.token_type),
+        (LParen, _)
+            | (_, RParen)
+            | (LBracket, _)
+            | (LBrace, RBrace)
+            | (_, RBracket)
+            | (_, Comma)
+            | (_, Colon)
+            | (InterpStart | InterpMid, _)
+            | (_, InterpMid | InterpEnd | FormatSpec)
+            | (QuestionDot, _)
+            | (_, QuestionDot)
+            | (Dot, _)
+            | (_, Dot)
+            | (_, EOL)
+            | (DotDot, _)
+            | (_, DotDot)
+            | (Ellipsis, _)
+            | (Ident, Ellipsis)
+            | (Ident | RParen | RBracket | Fn, LParen)
+            | (Ident | RParen | RBracket | Fn, LBracket)
+            | (Ident | RParen | RBracket, PlusPlus | MinusMinus)
+    )
+}
@@ -0,0 +1,382 @@
+use std::fmt::Write;
+
+use crate::{
+    expr::{Expr, Op},
+    parser::Parser,
+    pattern::MatchArm,
+    source::Source,
+    stmt::{Param, Stmt},
+    symbol::Symbol,
+};
+
+/// Parses `input` and describes its syntax tree in Graphviz DOT, one node per
+/// statement or expression with edges to its children. Edges are labelled
+/// where the role of a child is not obvious, like the branches of an `if`.
+pub fn to_dot(input: &str) -> String {
+    let mut source = Source::new(input.to_string());
+    source.tokenize();
+    let mut parser = Parser::new(source.get_tokens());
+    parser.parse();
+
+    let mut graph = Graph {
+        out: String::from("digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n"),
+        nodes: 0,
+    };
+    let root = graph.node("program");
+    for stmt in parser.into_stmts() {
+        let child = graph.stmt(&stmt);
+        graph.edge(root, child, "");
+    }
+    graph.out += "}\n";
+    graph.out
+}
+
+struct Graph {
+    out: String,
+    nodes: usize,
+}
+
+impl Graph {
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.nodes;
+        self.nodes += 1;
+        writeln!(self.out, "    n{} [label=\"{}\"];", id, escape(label)).unwrap();
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) {
+        if label.is_empty() {
+            writeln!(self.out, "    n{} -> n{};", from, to).unwrap();
+        } else {
+            writeln!(
+                self.out,
+                "    n{} -> n{} [label=\"{}\"];",
+                from,
+                to,
+                escape(label)
+            )
+            .unwrap();
+        }
+    }
+
+    /// A node with the given children, each under its edge label.
+    fn branch(&mut self, label: &str, children: Vec<(&str, usize)>) -> usize {
+        let id = self.node(label);
+        for (edge, child) in children {
+            self.edge(id, child, edge);
+        }
+        id
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> usize {
+        match stmt {
+            // A bare expression is drawn as itself.
+            Stmt::Expr(expr) | Stmt::Semi(expr) => self.expr(expr),
+            Stmt::Let(name, expr, mutable, _) => {
+                let value = self.expr(expr);
+                let keyword = if *mutable { "let mut" } else { "let" };
+                self.branch(&format!("{} {}", keyword, name.lexeme), vec![("", value)])
+            }
+            Stmt::Const(name, expr, _) => {
+                let value = self.expr(expr);
+                self.branch(&format!("const {}", name.lexeme), vec![("", value)])
+            }
+            Stmt::Destructure(pattern, expr, mutable, _) => {
+                let value = self.expr(expr);
+                let keyword = if *mutable { "let mut" } else { "let" };
+                self.branch(&format!("{} {}", keyword, pattern), vec![("", value)])
+            }
+            Stmt::Assign(name, expr) => {
+                let value = self.expr(expr);
+                self.branch(&format!("{} =", name.lexeme), vec![("", value)])
+            }
+            Stmt::IndexAssign(target, index, expr) => {
+                let target = self.expr(target);
+                let index = self.expr(index);
+                let value = self.expr(expr);
+                self.branch(
+                    "[] =",
+                    vec![("target", target), ("index", index), ("value", value)],
+                )
+            }
+            Stmt::FieldAssign(target, name, expr) => {
+                let target = self.expr(target);
+                let value = self.expr(expr);
+                self.branch(
+                    &format!(".{} =", name),
+                    vec![("target", target), ("value", value)],
+                )
+            }
+            Stmt::Group(stmts) => {
+                let children = stmts.iter().map(|s| ("", self.stmt(s))).collect();
+                self.branch("block", children)
+            }
+            Stmt::If(condition, then, else_stmt) => {
+                let mut children = vec![("condition", self.expr(condition))];
+                children.push(("then", self.stmt(then)));
+                if let Some(else_stmt) = else_stmt {
+                    children.push(("else", self.stmt(else_stmt)));
+                }
+                self.branch("if", children)
+            }
+            Stmt::While(condition, body) => {
+                let condition = self.expr(condition);
+                let body = self.stmt(body);
+                self.branch("while", vec![("condition", condition), ("body", body)])
+            }
+            Stmt::DoWhile(body, condition) => {
+                let body = self.stmt(body);
+                let condition = self.expr(condition);
+                self.branch("do while", vec![("body", body), ("condition", condition)])
+            }
+            Stmt::For(var, start, end, step, body) => {
+                let mut children = vec![("start", self.expr(start)), ("end", self.expr(end))];
+                if let Some(step) = step {
+                    children.push(("step", self.expr(step)));
+                }
+                children.push(("body", self.stmt(body)));
+                self.branch(&format!("for {} in ..", var.lexeme), children)
+            }
+            Stmt::ForIn(var, second, iterable, body) => {
+                let iterable = self.expr(iterable);
+                let body = self.stmt(body);
+                let names = match second {
+                    Some(second) => format!("{}, {}", var.lexeme, second.lexeme),
+                    None => var.lexeme.clone(),
+                };
+                self.branch(
+                    &format!("for {} in", names),
+                    vec![("iterable", iterable), ("body", body)],
+                )
+            }
+            Stmt::Function(name, params, _, body) => self.function(&name.lexeme, params, body),
+            Stmt::Struct(decl) => {
+                let keyword = if decl.class { "class" } else { "struct" };
+                let fields = decl.fields.iter().map(|f| f.to_string());
+                let label = format!(
+                    "{} {} {{ {} }}",
+                    keyword,
+                    decl.name.lexeme,
+                    fields.collect::<Vec<_>>().join(", ")
+                );
+                let children = decl
+                    .methods
+                    .iter()
+                    .map(|m| ("", self.function(m.name.as_str(), &m.params, &m.body)))
+                    .collect();
+                self.branch(&label, children)
+            }
+            Stmt::Labeled(label, stmt) => {
+                let stmt = self.stmt(stmt);
+                self.branch(&format!("{}:", label), vec![("", stmt)])
+            }
+            Stmt::Break(label) => self.jump("break", *label),
+            Stmt::Continue(label) => self.jump("continue", *label),
+            Stmt::Return(expr) => {
+                let children = expr.iter().map(|e| ("", self.expr(e))).collect();
+                self.branch("return", children)
+            }
+            Stmt::Match(subject, arms) => self.match_arms(subject, arms),
+            Stmt::Assert(condition, message, _) => {
+                let mut children = vec![("condition", self.expr(condition))];
+                if let Some(message) = message {
+                    children.push(("message", self.expr(message)));
+                }
+                self.branch("assert", children)
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> usize {
+        match expr {
+            Expr::Int(_) | Expr::Number(_) | Expr::Bool(_) | Expr::Nil | Expr::Variable { .. } => {
+                self.node(&expr.to_string())
+            }
+            Expr::String(s) => self.node(&format!("{:?}", s)),
+            Expr::Binary(l, op, r) | Expr::Logic(l, op, r) => {
+                let l = self.expr(l);
+                let r = self.expr(r);
+                self.branch(&op.to_string(), vec![("", l), ("", r)])
+            }
+            Expr::Chain(first, rest) => {
+                let mut children = vec![("", self.expr(first))];
+                let mut label = String::from("chain");
+                for (op, expr) in rest {
+                    label += &format!(" {}", op);
+                    children.push(("", self.expr(expr)));
+                }
+                self.branch(&label, children)
+            }
+            Expr::Unary(op, r) => {
+                let r = self.expr(r);
+                self.branch(&op.to_string(), vec![("", r)])
+            }
+            Expr::Group(inner) => {
+                let inner = self.expr(inner);
+                self.branch("( )", vec![("", inner)])
+            }
+            Expr::Call { callee, args, .. } => {
+                let mut children = vec![("callee", self.expr(callee))];
+                children.extend(args.iter().map(|a| ("", self.expr(a))));
+                self.branch("call", children)
+            }
+            Expr::List(items) => {
+                let children = items.iter().map(|i| ("", self.expr(i))).collect();
+                self.branch("list", children)
+            }
+            Expr::Spread(inner) => {
+                let inner = self.expr(inner);
+                self.branch("...", vec![("", inner)])
+            }
+            Expr::Tuple(items) => {
+                let children = items.iter().map(|i| ("", self.expr(i))).collect();
+                self.branch("tuple", children)
+            }
+            Expr::Map(entries) => {
+                let mut children = Vec::new();
+                for (key, value) in entries {
+                    let key = self.expr(key);
+                    let value = self.expr(value);
+                    children.push(("", self.branch(":", vec![("key", key), ("value", value)])));
+                }
+                self.branch("map", children)
+            }
+            Expr::Index(target, index) | Expr::OptionalIndex(target, index) => {
+                let label = if matches!(expr, Expr::Index(..)) {
+                    "[]"
+                } else {
+                    "?.[]"
+                };
+                let target = self.expr(target);
+                let index = self.expr(index);
+                self.branch(label, vec![("target", target), ("index", index)])
+            }
+            Expr::Field(target, name) => {
+                let target = self.expr(target);
+                self.branch(&format!(".{}", name), vec![("", target)])
+            }
+            Expr::OptionalField(target, name) => {
+                let target = self.expr(target);
+                self.branch(&format!("?.{}", name), vec![("", target)])
+            }
+            Expr::StructLit(def, fields) => {
+                let mut children = Vec::new();
+                for (name, value) in fields.iter() {
+                    children.push((name.as_str(), self.expr(value)));
+                }
+                self.branch(&format!("{} {{ }}", def), children)
+            }
+            Expr::Ternary(condition, then, otherwise) => {
+                let condition = self.expr(condition);
+                let then = self.expr(then);
+                let otherwise = self.expr(otherwise);
+                self.branch(
+                    "? :",
+                    vec![
+                        ("condition", condition),
+                        ("then", then),
+                        ("else", otherwise),
+                    ],
+                )
+            }
+            Expr::Format(inner, spec) => {
+                let inner = self.expr(inner);
+                self.branch(&format!("format :{}", spec), vec![("", inner)])
+            }
+            Expr::Match(subject, arms) => self.match_arms(subject, arms),
+            Expr::If(condition, then, else_stmt) => {
+                let mut children = vec![("condition", self.expr(condition))];
+                children.push(("then", self.stmt(then)));
+                if let Some(else_stmt) = else_stmt {
+                    children.push(("else", self.stmt(else_stmt)));
+                }
+                self.branch("if", children)
+            }
+            Expr::Lambda(params, body) => self.function("", params, body),
+            Expr::Assign(target, value) => {
+                let target = self.expr(target);
+                let value = self.expr(value);
+                self.branch("=", vec![("", target), ("", value)])
+            }
+            Expr::CompoundAssign { target, op, value } => {
+                let target = self.expr(target);
+                let value = self.expr(value);
+                self.branch(&format!("{}=", op), vec![("", target), ("", value)])
+            }
+            Expr::Increment { target, op, prefix } => {
+                let op = if let Op::Add = op { "++" } else { "--" };
+                let label = if *prefix {
+                    format!("{} (prefix)", op)
+                } else {
+                    format!("{} (postfix)", op)
+                };
+                let target = self.expr(target);
+                self.branch(&label, vec![("", target)])
+            }
+            Expr::Capture { names, lambda, .. } => {
+                let names = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+                let lambda = self.expr(lambda);
+                self.branch(
+                    &format!("capture [{}]", names.join(", ")),
+                    vec![("", lambda)],
+                )
+            }
+        }
+    }
+
+    fn jump(&mut self, keyword: &str, label: Option<Symbol>) -> usize {
+        match label {
+            Some(label) => self.node(&format!("{} {}", keyword, label)),
+            None => self.node(keyword),
+        }
+    }
+
+    /// A named function, or a lambda when `name` is empty. Default values
+    /// hang off the function under their parameter's name.
+    fn function(&mut self, name: &str, params: &[Param], body: &Stmt) -> usize {
+        let names = params.iter().map(|p| {
+            let rest = if p.rest { "..." } else { "" };
+            format!("{}{}", rest, p.name)
+        });
+        let label = format!("fn {}({})", name, names.collect::<Vec<_>>().join(", "));
+        let mut children = Vec::new();
+        for param in params {
+            if let Some(default) = &param.default {
+                children.push((param.name.as_str(), self.expr(default)));
+            }
+        }
+        children.push(("body", self.stmt(body)));
+        self.branch(&label, children)
+    }
+
+    fn match_arms(&mut self, subject: &Expr, arms: &[MatchArm]) -> usize {
+        let mut children = vec![("subject", self.expr(subject))];
+        for arm in arms {
+            let patterns = arm.patterns.iter().map(|p| p.to_string());
+            let label = format!("{} =>", patterns.collect::<Vec<_>>().join(" | "));
+            let mut arm_children = Vec::new();
+            if let Some(guard) = &arm.guard {
+                arm_children.push(("if", self.expr(guard)));
+            }
+            arm_children.push(("", self.stmt(&arm.body)));
+            children.push(("", self.branch(&label, arm_children)));
+        }
+        self.branch("match", children)
+    }
+}
+
+/// Escapes text for a double-quoted DOT string.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,82 @@
+use crate::{
+    source::Source,
+    token::{CONTEXTUAL_KEYWORDS, Span, TokenType},
+};
+
+/// What a piece of source text is, for coloring it in an editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenClass {
+    /// Reserved words, and contextual keywords such as `match` even where
+    /// they are used as names.
+    Keyword,
+    /// Numbers, strings (including the pieces around `${...}`), format
+    /// specs, `true`, `false` and `nil`.
+    Literal,
+    Identifier,
+    Operator,
+    /// Brackets, commas and the other separators.
+    Punctuation,
+    Comment,
+}
+
+/// Classifies the tokens and comments of `input`, in source order. Whitespace
+/// and line breaks are left out, so gaps between spans are plain text.
+///
+/// Like `cst::parse`, this runs the lexer, which stops the process on an
+/// invalid token such as an unterminated string.
+pub fn highlight(input: &str) -> Vec<(Span, TokenClass)> {
+    let mut source = Source::new(input.to_string());
+    source.tokenize();
+    let mut spans = Vec::new();
+    let mut prev_end = 0;
+    for token in source.get_tokens() {
+        comments(input, prev_end, token.span.start, &mut spans);
+        prev_end = prev_end.max(token.span.end);
+        if let Some(class) = classify(token.token_type, &token.lexeme) {
+            spans.push((token.span, class));
+        }
+    }
+    comments(input, prev_end, input.len(), &mut spans);
+    spans
+}
+
+fn classify(token_type: TokenType, lexeme: &str) -> Option<TokenClass> {
+    use TokenType::*;
+    let class = match token_type {
+        Number | String | True | False | Nil | InterpStart | InterpMid | InterpEnd | FormatSpec => {
+            TokenClass::Literal
+        }
+        _ if token_type.is_keyword() => TokenClass::Keyword,
+        Ident if CONTEXTUAL_KEYWORDS.contains(&lexeme) => TokenClass::Keyword,
+        Ident => TokenClass::Identifier,
+        LParen | RParen | LBrace | RBrace | LBracket | RBracket | Comma | Colon => {
+            TokenClass::Punctuation
+        }
+        EOL | EOF => return None,
+        _ => TokenClass::Operator,
+    };
+    Some(class)
+}
+
+/// Pushes the `//` comments in `input[start..end]`, which holds only
+/// whitespace and comments.
+fn comments(input: &str, start: usize, end: usize, spans: &mut Vec<(Span, TokenClass)>) {
+    if start >= end {
+        return;
+    }
+    let mut offset = start;
+    while let Some(found) = input[offset..end].find("//") {
+        let comment_start = offset + found;
+        let comment_end = input[comment_start..end]
+            .find('\n')
+            .map_or(end, |i| comment_start + i);
+        spans.push((
+            Span {
+                start: comment_start,
+                end: comment_end,
+            },
+            TokenClass::Comment,
+        ));
+        offset = comment_end;
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    error::{ErrorType, line_fatal},
+    metadata::Metadata,
+    parser::Parser,
+    source::Source,
+    std_fn::{self, Capability},
+    stmt::Stmt,
+    token::TokenType,
+};
+
+/// Describes a script without running it: its header, size, top-level
+/// functions and the capabilities of the builtins it mentions. Syntax errors
+/// stop the report like they would stop the script.
+pub fn report(path: &str, input: &str) -> String {
+    let metadata = Metadata::parse(input)
+        .unwrap_or_else(|(line, message)| line_fatal(ErrorType::SyntaxError, line, message));
+    let mut source = Source::new(input.to_string());
+    source.tokenize();
+    let tokens = source.get_tokens();
+    let mut parser = Parser::new(tokens);
+    parser.parse();
+    let stmts = parser.into_stmts();
+
+    let mut out = format!("script: {}\n", path);
+    for (key, value) in &metadata.fields {
+        if key != "requires" {
+            out += &format!("{}: {}\n", key, value);
+        }
+    }
+
+    let (mut code, mut comment, mut blank) = (0, 0, 0);
+    for line in input.lines().map(str::trim) {
+        if line.is_empty() {
+            blank += 1;
+        } else if line.starts_with("//") {
+            comment += 1;
+        } else {
+            code += 1;
+        }
+    }
+    out += &format!(
+        "lines: {} total, {} code, {} comment, {} blank\n",
+        code + comment + blank,
+        code,
+        comment,
+        blank
+    );
+
+    out += "functions:";
+    let mut functions = 0;
+    for stmt in &stmts {
+        if let Stmt::Function(name, params, returns, _) = stmt {
+            let params = params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            let returns = returns.map_or(String::new(), |ty| format!(" -> {}", ty));
+            out += &format!(
+                "\n  {}({}){} on line {}",
+                name.lexeme,
+                params.join(", "),
+                returns,
+                name.line
+            );
+            functions += 1;
+        }
+    }
+    if functions == 0 {
+        out += " none";
+    }
+    out += "\n";
+
+    // Any mention of a builtin counts, not only direct calls, since a builtin
+    // can be passed around and called later.
+    let mut used = Vec::new();
+    for builtin in std_fn::builtins() {
+        let mentioned = tokens
+            .iter()
+            .any(|t| t.token_type == TokenType::Ident && t.lexeme == builtin.name);
+        if mentioned
+            && builtin.capability != Capability::Pure
+            && !used.contains(&builtin.capability)
+        {
+            used.push(builtin.capability);
+        }
+    }
+    out += &format!("uses: {}\n", capability_list(&used));
+    match &metadata.requires {
+        Some(requires) => {
+            out += &format!("requires: {}\n", capability_list(requires));
+            let missing = used
+                .iter()
+                .filter(|c| !requires.contains(c))
+                .copied()
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                out += &format!("warning: uses undeclared {}\n", capability_list(&missing));
+            }
+        }
+        None => out += "requires: not declared, all capabilities granted\n",
+    }
+    out
+}
+
+fn capability_list(capabilities: &[Capability]) -> String {
+    if capabilities.is_empty() {
+        return "none".to_string();
+    }
+    capabilities
+        .iter()
+        .map(|c| c.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,69 @@
+use std::{rc::Rc, vec};
+
+use crate::{env::Value, expr::call_value, symbol::Symbol};
+
+/// The values a `for` loop, a `...` spread or a builtin such as `list` or
+/// `sum` visits.
+///
+/// Strings, lists, tuples and map keys are copied out up front. Anything
+/// else follows the iterator protocol: an instance of a class with a
+/// `next(self)` method, or a function taking no arguments, is called again
+/// and again until it returns `nil`. A class with an `iter(self)` method is
+/// iterated through whatever that returns, so a collection type can hand out
+/// a fresh iterator each time.
+pub enum Iter {
+    Items(vec::IntoIter<Value>),
+    Next(Value),
+}
+
+impl Iterator for Iter {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        match self {
+            Iter::Items(items) => items.next(),
+            Iter::Next(next) => match call_value(next.clone(), Vec::new()) {
+                Value::Nil => None,
+                value => Some(value),
+            },
+        }
+    }
+}
+
+/// An iterator over `value`, or `None` if it is not iterable.
+pub fn iter(value: &Value) -> Option<Iter> {
+    let items = match value {
+        Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+        Value::List(items) => items.borrow().clone(),
+        Value::Tuple(items) => items.to_vec(),
+        Value::Map(map) => map.borrow().keys().cloned().map(Value::String).collect(),
+        Value::Function { .. }
+        | Value::FuncBuiltIn { .. }
+        | Value::FuncPlugin { .. }
+        | Value::BoundMethod { .. } => {
+            return Some(Iter::Next(value.clone()));
+        }
+        Value::Struct(instance) => {
+            let (next, iter) = (Symbol::from("next"), Symbol::from("iter"));
+            let def = instance.borrow().def.clone();
+            if def.method(next).is_some() {
+                return Some(Iter::Next(Value::BoundMethod {
+                    receiver: instance.clone(),
+                    name: next,
+                }));
+            }
+            def.method(iter)?;
+            let method = Value::BoundMethod {
+                receiver: instance.clone(),
+                name: iter,
+            };
+            return match call_value(method, Vec::new()) {
+                // An `iter` that returns its own instance would recurse.
+                Value::Struct(other) if Rc::ptr_eq(&other, instance) => None,
+                iterator => self::iter(&iterator),
+            };
+        }
+        _ => return None,
+    };
+    Some(Iter::Items(items.into_iter()))
+}
@@ -0,0 +1,386 @@
+use std::{cell::RefCell, collections::BTreeMap, iter::Peekable, rc::Rc, str::Chars};
+
+use crate::env::Value;
+
+/// Writes a value as JSON. Whole numbers stay integers, `nil` is `null` and
+/// numbers JSON cannot hold (NaN, infinities) become `null` too. Functions
+/// have no JSON form.
+pub fn encode(value: &Value) -> Result<String, String> {
+    let mut out = String::new();
+    encode_into(value, &mut out, &mut Vec::new())?;
+    Ok(out)
+}
+
+fn encode_into(value: &Value, out: &mut String, seen: &mut Vec<*const ()>) -> Result<(), String> {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Int(n) => out.push_str(&n.to_string()),
+        Value::Number(n) if n.is_finite() => out.push_str(&format!("{:?}", n)),
+        Value::Number(_) => out.push_str("null"),
+        Value::Decimal(n) => out.push_str(&n.to_string()),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => out.push_str(&n.to_string()),
+        Value::String(s) => encode_string(s, out),
+        Value::List(items) => {
+            let ptr = Rc::as_ptr(items) as *const ();
+            if seen.contains(&ptr) {
+                return Err("cannot encode a list that contains itself".to_string());
+            }
+            seen.push(ptr);
+            out.push('[');
+            for (i, item) in items.borrow().iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                encode_into(item, out, seen)?;
+            }
+            out.push(']');
+            seen.pop();
+        }
+        Value::Tuple(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                encode_into(item, out, seen)?;
+            }
+            out.push(']');
+        }
+        Value::Map(map) => {
+            let ptr = Rc::as_ptr(map) as *const ();
+            if seen.contains(&ptr) {
+                return Err("cannot encode a map that contains itself".to_string());
+            }
+            seen.push(ptr);
+            out.push('{');
+            for (i, (key, item)) in map.borrow().iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                encode_string(key, out);
+                out.push(':');
+                encode_into(item, out, seen)?;
+            }
+            out.push('}');
+            seen.pop();
+        }
+        Value::Struct(instance) => {
+            let ptr = Rc::as_ptr(instance) as *const ();
+            if seen.contains(&ptr) {
+                return Err("cannot encode a struct that contains itself".to_string());
+            }
+            seen.push(ptr);
+            out.push('{');
+            let instance = instance.borrow();
+            for (i, (name, item)) in instance.def.fields.iter().zip(&instance.fields).enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                encode_string(&name.to_string(), out);
+                out.push(':');
+                encode_into(item, out, seen)?;
+            }
+            out.push('}');
+            seen.pop();
+        }
+        _ => return Err(format!("cannot encode {} as JSON", value.type_name())),
+    }
+    Ok(())
+}
+
+fn encode_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Reads a single JSON document. Numbers without a fraction or exponent
+/// become integers when they fit.
+pub fn decode(text: &str) -> Result<Value, String> {
+    let mut chars = text.chars().peekable();
+    let value = decode_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected `{}` after JSON value", c)),
+    }
+}
+
+type Input<'a> = Peekable<Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Input) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn decode_value(chars: &mut Input) -> Result<Value, String> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        Some('n') => keyword(chars, "null", Value::Nil),
+        Some('t') => keyword(chars, "true", Value::Bool(true)),
+        Some('f') => keyword(chars, "false", Value::Bool(false)),
+        Some('"') => decode_string(chars).map(Value::String),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_none() {
+                loop {
+                    items.push(decode_value(chars)?);
+                    skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => continue,
+                        Some(']') => break,
+                        _ => return Err("expected `,` or `]` in JSON array".to_string()),
+                    }
+                }
+            }
+            Ok(Value::List(Rc::new(RefCell::new(items))))
+        }
+        Some('{') => {
+            chars.next();
+            let mut map = BTreeMap::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_none() {
+                loop {
+                    skip_whitespace(chars);
+                    let key = decode_string(chars)?;
+                    skip_whitespace(chars);
+                    if chars.next() != Some(':') {
+                        return Err("expected `:` in JSON object".to_string());
+                    }
+                    map.insert(key, decode_value(chars)?);
+                    skip_whitespace(chars);
+                    match chars.next() {
+                        Some(',') => continue,
+                        Some('}') => break,
+                        _ => return Err("expected `,` or `}` in JSON object".to_string()),
+                    }
+                }
+            }
+            Ok(Value::Map(Rc::new(RefCell::new(map))))
+        }
+        Some(c) if *c == '-' || c.is_ascii_digit() => decode_number(chars),
+        Some(c) => Err(format!("unexpected `{}` in JSON", c)),
+        None => Err("unexpected end of JSON".to_string()),
+    }
+}
+
+fn keyword(chars: &mut Input, word: &str, value: Value) -> Result<Value, String> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            return Err(format!("invalid JSON literal, expected `{}`", word));
+        }
+    }
+    Ok(value)
+}
+
+fn decode_number(chars: &mut Input) -> Result<Value, String> {
+    let mut text = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+        text.push(c);
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Ok(Value::Int(n));
+    }
+    text.parse::<f64>()
+        .map(Value::Number)
+        .map_err(|_| format!("invalid JSON number `{}`", text))
+}
+
+fn decode_string(chars: &mut Input) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("expected a JSON string".to_string());
+    }
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let high = hex4(chars)?;
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return Err("unpaired surrogate in JSON string".to_string());
+                        }
+                        let low = hex4(chars)?;
+                        0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                    } else {
+                        high
+                    };
+                    s.push(char::from_u32(code).ok_or("invalid escape in JSON string")?);
+                }
+                _ => return Err("invalid escape in JSON string".to_string()),
+            },
+            Some(c) => s.push(c),
+            None => return Err("unterminated JSON string".to_string()),
+        }
+    }
+}
+
+fn hex4(chars: &mut Input) -> Result<u32, String> {
+    let digits = chars.take(4).collect::<String>();
+    u32::from_str_radix(&digits, 16).map_err(|_| format!("invalid `\\u{}` escape", digits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        let entries = entries.into_iter().map(|(k, v)| (k.to_string(), v));
+        Value::Map(Rc::new(RefCell::new(entries.collect())))
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    /// Encodes `value`, checks that decoding gives an equal value that
+    /// encodes the same way, and returns the text.
+    fn round_trip(value: &Value) -> String {
+        let text = encode(value).unwrap();
+        let decoded = decode(&text).unwrap();
+        assert!(decoded.deep_eq(value), "{} decoded as {}", text, decoded);
+        assert_eq!(encode(&decoded).unwrap(), text);
+        text
+    }
+
+    #[test]
+    fn scalars_round_trip() {
+        for (value, text) in [
+            (Value::Nil, "null"),
+            (Value::Bool(true), "true"),
+            (Value::Bool(false), "false"),
+            (Value::Int(0), "0"),
+            (Value::Int(i64::MAX), "9223372036854775807"),
+            (Value::Int(i64::MIN), "-9223372036854775808"),
+            (Value::Number(2.0), "2.0"),
+            (Value::Number(-0.1), "-0.1"),
+            (Value::Number(1e300), "1e300"),
+            (Value::Number(2.5e-8), "2.5e-8"),
+        ] {
+            assert_eq!(round_trip(&value), text);
+        }
+    }
+
+    #[test]
+    fn strings_round_trip_with_escapes() {
+        for (s, text) in [
+            ("", r#""""#),
+            ("say \"hi\"", r#""say \"hi\"""#),
+            ("back\\slash", r#""back\\slash""#),
+            ("a\nb\r\tc", r#""a\nb\r\tc""#),
+            ("\u{1}\u{1f}", r#""\u0001\u001f""#),
+            ("héllo 🦀 /", "\"héllo 🦀 /\""),
+        ] {
+            assert_eq!(round_trip(&string(s)), text, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn decode_reads_escapes_that_encode_never_writes() {
+        let decoded = decode(r#""\/\b\fé🦀""#).unwrap();
+        assert!(decoded.deep_eq(&string("/\u{8}\u{c}é🦀")), "{}", decoded);
+        assert!(decode(r#""\ud83e""#).is_err());
+    }
+
+    #[test]
+    fn nested_collections_round_trip() {
+        let value = map(vec![
+            ("empty", list(vec![])),
+            ("none", map(vec![])),
+            (
+                "items",
+                list(vec![
+                    Value::Int(1),
+                    list(vec![Value::Nil, string("x")]),
+                    map(vec![("k", Value::Bool(true))]),
+                ]),
+            ),
+            ("quote\"key", Value::Number(1.5)),
+        ]);
+        assert_eq!(
+            round_trip(&value),
+            r#"{"empty":[],"items":[1,[null,"x"],{"k":true}],"none":{},"quote\"key":1.5}"#
+        );
+    }
+
+    #[test]
+    fn shared_collections_encode_each_time_they_appear() {
+        let shared = list(vec![Value::Int(1)]);
+        let value = list(vec![shared.clone(), map(vec![("again", shared)])]);
+        assert_eq!(round_trip(&value), r#"[[1],{"again":[1]}]"#);
+    }
+
+    #[test]
+    fn values_without_json_form() {
+        assert_eq!(encode(&Value::Number(f64::NAN)).unwrap(), "null");
+        assert_eq!(encode(&Value::Number(f64::INFINITY)).unwrap(), "null");
+        let tuple = Value::Tuple(Rc::new([Value::Int(1), string("a")]));
+        assert_eq!(encode(&tuple).unwrap(), r#"[1,"a"]"#);
+    }
+
+    #[test]
+    fn collections_that_contain_themselves_are_errors() {
+        let items = Rc::new(RefCell::new(Vec::new()));
+        let cyclic = Value::List(items.clone());
+        items.borrow_mut().push(list(vec![cyclic.clone()]));
+        assert_eq!(
+            encode(&cyclic).unwrap_err(),
+            "cannot encode a list that contains itself"
+        );
+        let entries = Rc::new(RefCell::new(BTreeMap::new()));
+        let cyclic = Value::Map(entries.clone());
+        entries
+            .borrow_mut()
+            .insert("me".to_string(), cyclic.clone());
+        assert_eq!(
+            encode(&cyclic).unwrap_err(),
+            "cannot encode a map that contains itself"
+        );
+        // Break the cycles so the test does not leak them.
+        items.borrow_mut().clear();
+        entries.borrow_mut().clear();
+    }
+
+    #[test]
+    fn malformed_documents_are_errors() {
+        for text in [
+            "",
+            "[1,",
+            "[1 2]",
+            "{\"a\" 1}",
+            "{1: 2}",
+            "tru",
+            "\"open",
+            "1 2",
+            "-",
+        ] {
+            assert!(decode(text).is_err(), "{:?}", text);
+        }
+    }
+}
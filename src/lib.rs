@@ -1,59 +1,441 @@
-use std::io::Write;
+use std::{cell::RefCell, collections::HashMap, io::Read, rc::Rc};
 
+use env::{Env, Value};
+use error::{ErrorType, RikuError, exit, line_fatal};
+use metadata::Metadata;
+use output::Output;
 use parser::Parser;
 use source::Source;
-use stmt::ControlFlow;
+use stmt::{ControlFlow, Stmt};
+use symbol::Symbol;
+use transcript::Transcript;
 
+#[cfg(feature = "bigint")]
+pub mod bigint;
+pub mod config;
+mod console;
+pub mod crash;
+pub mod cst;
+pub mod decimal;
 pub mod env;
 pub mod error;
 mod expr;
+pub mod format;
+pub mod formatter;
+mod graph;
+pub mod highlight;
+mod info;
+mod iter;
+mod json;
+pub mod metadata;
+mod methods;
+pub mod output;
 pub mod parser;
+mod pattern;
+pub mod plugin;
+pub mod project;
+mod serve;
 pub mod source;
-mod std_fn;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod stats;
+pub mod std_fn;
 mod stmt;
-mod token;
+pub mod symbol;
+mod timer;
+pub mod token;
+mod transcript;
+mod typeck;
+mod types;
+#[cfg(feature = "self-update")]
+pub mod update;
 
-pub fn run_file(source: &str) {
-    let contents = std::fs::read_to_string(source).expect("Unable to read file");
-    let mut source = Source::new(contents);
+/// A riku session. Variables and functions defined by one call to `run`
+/// stay visible to the next.
+pub struct Interpreter {
+    env: Rc<RefCell<Env>>,
+    /// Definitions from each file run by `load`, by name, as `Stmt::shape`s.
+    loaded: HashMap<String, HashMap<Symbol, String>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            env: Env::new(),
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// Runs `input` and returns the values produced by its top-level
+    /// statements, as echoed by the CLI.
+    pub fn run(&mut self, input: &str, output: &Output) -> Vec<Value> {
+        self.run_collecting(input, output, true)
+    }
+
+    /// Runs `source` and returns the value of each top-level statement, in
+    /// order, with `nil` for statements such as `fn` that have none. A
+    /// fatal error is returned instead of ending the process, for hosts
+    /// such as notebooks that show a result per statement. Whatever ran
+    /// before the error stays defined.
+    pub fn eval_all(&mut self, source: &str) -> Result<Vec<Value>, RikuError> {
+        config::start_run();
+        error::catch(|| {
+            let mut source = Source::new(source.to_string());
+            source.tokenize();
+            let mut parser = Parser::new(source.get_tokens());
+            parser.warn_shadowing(!config::allow_shadow_builtins());
+            parser.parse();
+            parser
+                .into_stmts()
+                .iter()
+                .map(|stmt| match stmt.eval(&mut self.env) {
+                    ControlFlow::Value(value) => value,
+                    _ => Value::Nil,
+                })
+                .collect()
+        })
+    }
+
+    /// Runs `input`, keeping the top-level values only if `collect` is set.
+    /// A script drops them as it goes, so they do not keep shared lists and
+    /// maps alive or show up in `inspect` reference counts.
+    fn run_collecting(&mut self, input: &str, output: &Output, collect: bool) -> Vec<Value> {
+        config::start_run();
+        let mut source = Source::new(input.to_string());
+        source.tokenize();
+        output.trace(|| format!("tokens: {:#?}", source.get_tokens()));
+        let mut parser = Parser::new(source.get_tokens());
+        parser.warn_shadowing(!config::allow_shadow_builtins());
+        parser.parse();
+        let stmts = parser.into_stmts();
+        output.trace(|| format!("statements: {:#?}", stmts));
+        output.info(&format!("parsed {} statements", stmts.len()));
+        let mut values = Vec::new();
+        for stmt in &stmts {
+            if let ControlFlow::Value(value) = stmt.eval(&mut self.env)
+                && collect
+            {
+                values.push(value);
+            }
+        }
+        values
+    }
+
+    /// Runs the script at `path`, for the REPL's `:load`. Loading a file
+    /// again only rebinds the functions, structs and classes whose code
+    /// changed, plus `let`s of names that are not defined yet, so values built
+    /// up in the session survive. Returns what ran, or the error that
+    /// stopped the file, in which case what ran before it stays defined.
+    pub fn load(&mut self, path: &str) -> Result<Loaded, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read `{}`: {}", path, e))?;
+        error::catch(|| self.load_contents(path, contents))
+            .map_err(|e| format!("Unable to load `{}`: {}", path, e))
+    }
+
+    fn load_contents(&mut self, path: &str, contents: String) -> Loaded {
+        config::start_run();
+        let mut source = Source::new(contents.clone());
+        source.tokenize();
+        let mut parser = Parser::new(source.get_tokens());
+        parser.warn_shadowing(!config::allow_shadow_builtins());
+        parser.parse();
+        let stmts = parser.into_stmts();
+        let previous = self.loaded.get(path);
+        let mut definitions = HashMap::new();
+        let mut rebound = Vec::new();
+        let mut ran = Vec::new();
+        for (i, stmt) in stmts.iter().enumerate() {
+            let name = stmt.defined_name();
+            let run = match (previous, name, stmt) {
+                (None, _, _) => true,
+                (Some(_), Some(name), Stmt::Let(..) | Stmt::Const(..)) => {
+                    self.env.borrow().get(name).is_none()
+                }
+                (Some(previous), Some(name), _) => previous.get(&name) != Some(&stmt.shape()),
+                (Some(_), None, _) => false,
+            };
+            if let Some(name) = name {
+                definitions.insert(name, stmt.shape());
+            }
+            if run {
+                ran.push(i);
+                stmt.eval(&mut self.env);
+                if previous.is_some()
+                    && let Some(name) = name
+                {
+                    rebound.push(name.to_string());
+                }
+            }
+        }
+        let summary = match (previous, rebound.is_empty()) {
+            (None, _) => format!("loaded `{}`", path),
+            (Some(_), true) => format!("`{}` has no changes", path),
+            (Some(_), false) => format!("reloaded `{}`: {}", path, rebound.join(", ")),
+        };
+        let source = if previous.is_none() {
+            contents
+        } else {
+            statements_source(&contents, &stmts, &ran)
+        };
+        self.loaded.insert(path.to_string(), definitions);
+        Loaded { summary, source }
+    }
+
+    /// Starts the plugin program at `path` and defines its functions as
+    /// builtins. Returns the names it defined.
+    pub fn load_plugin(&mut self, path: &str) -> Result<Vec<String>, String> {
+        let (plugin, names) = plugin::Plugin::load(path)?;
+        let mut env = self.env.borrow_mut();
+        for name in &names {
+            let symbol = symbol::Symbol::intern(name);
+            if env.map.get(&symbol).is_some_and(|b| b.value.is_builtin()) {
+                return Err(format!("plugin `{}` redefines builtin `{}`", path, name));
+            }
+            let func = Value::FuncPlugin {
+                name: name.clone(),
+                plugin: plugin.clone(),
+            };
+            env.define(symbol, func);
+        }
+        Ok(names)
+    }
+
+    /// The builtin functions available to every script.
+    pub fn builtins(&self) -> Vec<std_fn::BuiltinInfo> {
+        std_fn::builtins()
+    }
+}
+
+/// What [`Interpreter::load`] ran.
+pub struct Loaded {
+    /// A summary for the REPL, such as ``reloaded `shapes.riku`: area``.
+    pub summary: String,
+    /// The source of the statements that ran, in order, which repeats the
+    /// load when run as part of a script.
+    pub source: String,
+}
+
+/// The lines of `contents` holding the top-level statements `ran` of
+/// `stmts`. A statement runs from its own line to the line before the next
+/// statement that has one. Only definitions are picked out this way, and
+/// they always record a line.
+fn statements_source(contents: &str, stmts: &[Stmt], ran: &[usize]) -> String {
+    let lines = contents.lines().collect::<Vec<_>>();
+    let mut source = String::new();
+    for &i in ran {
+        let Some(start) = stmts[i].line() else {
+            continue;
+        };
+        let end = stmts[i + 1..]
+            .iter()
+            .filter_map(Stmt::line)
+            .find(|&line| line > start)
+            .map_or(lines.len(), |line| line - 1);
+        let text = lines[start - 1..end.max(start)].join("\n");
+        source.push_str(text.trim_end());
+        source.push('\n');
+    }
+    source
+}
+
+/// An interpreter with the given plugins loaded. A plugin that fails to start
+/// is reported and ends the process, as nothing can run without it.
+fn interpreter_with(plugins: &[String], output: &Output) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    for path in plugins {
+        match interpreter.load_plugin(path) {
+            Ok(names) => output.info(&format!("plugin `{}`: {}", path, names.join(", "))),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(exit::UNAVAILABLE);
+            }
+        }
+    }
+    interpreter
+}
+
+pub fn run_file(path: &str, plugins: &[String], output: &Output) {
+    let contents = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Unable to read `{}`: {}", path, e);
+        std::process::exit(exit::NOINPUT);
+    });
+    let metadata = Metadata::parse(&contents)
+        .unwrap_or_else(|(line, message)| line_fatal(ErrorType::SyntaxError, line, message));
+    if let Some(requires) = &metadata.requires {
+        config::grant_only(requires);
+    }
+    output.info(&format!("running `{}`", path));
+    crash::set_script(path);
+    interpreter_with(plugins, output).run_collecting(&contents, output, false);
+    std_fn::run_exit_hooks();
+    output::write_stdout(format_args!("\n"));
+}
+
+/// Prints what `riku info` knows about a script, without running it.
+pub fn run_info(path: &str) {
+    let contents = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Unable to read `{}`: {}", path, e);
+        std::process::exit(exit::NOINPUT);
+    });
+    output::write_stdout(format_args!("{}", info::report(path, &contents)));
+}
+
+/// `riku serve --jsonrpc`: runs notebook cells sent over stdin as
+/// JSON-RPC requests, see [`serve::serve`].
+pub fn run_serve(plugins: &[String], output: &Output) {
+    serve::serve(interpreter_with(plugins, output));
+}
+
+/// `riku ast --graph`: prints the syntax tree of a script as Graphviz DOT.
+pub fn run_ast_graph(path: &str) {
+    let contents = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Unable to read `{}`: {}", path, e);
+        std::process::exit(exit::NOINPUT);
+    });
+    output::write_stdout(format_args!("{}", graph::to_dot(&contents)));
+}
+
+pub fn run_fmt_stdin() {
+    let mut input = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut input) {
+        eprintln!("Unable to read stdin: {}", e);
+        std::process::exit(exit::IOERR);
+    }
+    let mut source = Source::new(input.clone());
     source.tokenize();
-    // dbg!(source.get_tokens());
     let mut parser = Parser::new(source.get_tokens());
     parser.parse();
-    // dbg!(parser.get_stmts());
-    let mut env = env::Env::new();
-    for stmt in parser.get_stmts() {
-        stmt.eval(&mut env);
+    let config = fmt_config();
+    output::write_stdout(format_args!("{}", formatter::format_with(&input, &config)));
+}
+
+/// The `[fmt]` settings of the nearest `riku.toml`. Exits with `CONFIG` if
+/// the file is malformed.
+fn fmt_config() -> formatter::FormatConfig {
+    match project::load() {
+        Ok(project) => project.fmt,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(exit::CONFIG);
+        }
     }
-    println!();
-    // dbg!(env);
 }
 
-pub fn run_cli() {
+/// `riku fmt --verify`: checks that formatting each file would not change
+/// what it parses to, and that formatting it twice gives the same text.
+/// Exits with `DATAERR` if any file fails.
+pub fn run_fmt_verify(paths: &[String]) {
+    let config = fmt_config();
+    let mut failed = false;
+    for path in paths {
+        let contents = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Unable to read `{}`: {}", path, e);
+            std::process::exit(exit::NOINPUT);
+        });
+        match formatter::verify_with(&contents, &config) {
+            Ok(()) => output::write_stdout(format_args!("ok {}\n", path)),
+            Err(message) => {
+                eprintln!("{}: {}", path, message);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(exit::DATAERR);
+    }
+}
+
+/// `riku check`: reports the type errors `typeck::check` finds in each
+/// script without running it. Exits with `DATAERR` if there are any.
+pub fn run_check(paths: &[String]) {
+    let mut failed = false;
+    for path in paths {
+        let contents = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Unable to read `{}`: {}", path, e);
+            std::process::exit(exit::NOINPUT);
+        });
+        let mut source = Source::new(contents);
+        source.tokenize();
+        let mut parser = Parser::new(source.get_tokens());
+        parser.parse();
+        let errors = typeck::check(&parser.into_stmts());
+        if errors.is_empty() {
+            output::write_stdout(format_args!("ok {}\n", path));
+        }
+        for error in &errors {
+            eprintln!("{}:{}: TypeError: {}", path, error.line, error.message);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(exit::DATAERR);
+    }
+}
+
+fn io_error(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(exit::IOERR);
+}
+
+/// Runs the interactive REPL. With `record`, every line that runs is also
+/// written to that file along with its results, see [`Transcript`].
+pub fn run_cli(plugins: &[String], output: &Output, record: Option<&str>) {
     let stdin = std::io::stdin();
     let mut input = String::new();
-    let mut stdout = std::io::stdout();
-    let mut env = env::Env::new();
-    println!("Running in cli mode");
+    let mut interpreter = interpreter_with(plugins, output);
+    let mut transcript =
+        record.map(|path| Transcript::create(path).unwrap_or_else(|e| io_error(e)));
+    output.banner("Running in cli mode");
 
     loop {
-        print!("-> ");
-        stdout.flush().unwrap();
+        output::write_stdout(format_args!("-> "));
+        output::flush_stdout();
         input.clear();
-        stdin.read_line(&mut input).unwrap();
+        match stdin.read_line(&mut input) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Unable to read input: {}", e);
+                std::process::exit(exit::IOERR);
+            }
+        }
+        // Windows consoles end lines with `\r\n`.
+        let line = input.trim_end_matches(['\r', '\n']);
 
-        if input.trim() == "exit()" {
+        if line.trim() == "exit()" {
             break;
         }
-
-        let mut source = Source::new(input.clone());
-        source.tokenize();
-        let mut parser = Parser::new(source.get_tokens());
-        parser.parse();
-        for stmt in parser.get_stmts() {
-            if let ControlFlow::Value(res) = stmt.eval(&mut env) {
-                println!("{}", res);
+        if let Some(path) = line.trim().strip_prefix(":load ") {
+            match interpreter.load(path.trim()) {
+                Ok(loaded) => {
+                    output.banner(&loaded.summary);
+                    if let Some(transcript) = &mut transcript {
+                        transcript
+                            .record_load(path.trim(), &loaded.source)
+                            .unwrap_or_else(|e| io_error(e));
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
             }
+            continue;
+        }
+
+        let values = interpreter.run(line, output);
+        for value in &values {
+            output::write_stdout(format_args!("{}\n", value));
+        }
+        if let Some(transcript) = &mut transcript {
+            transcript
+                .record(line, &values)
+                .unwrap_or_else(|e| io_error(e));
         }
     }
+    std_fn::run_exit_hooks();
 }
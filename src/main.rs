@@ -1,15 +1,186 @@
-use riku::{run_cli, run_file};
+use riku::{
+    config, crash,
+    error::exit,
+    output::{Output, Verbosity},
+    run_ast_graph, run_check, run_cli, run_file, run_fmt_stdin, run_fmt_verify, run_info,
+    run_serve, stats,
+};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [-q | -v | -vv] [--strict] [--math] [--allow-shadow-builtins] [--quiet-input] [--stats] [--max-nesting <n>] [--max-recursion <n>] [--plugin <path>]... [<source_file>]",
+        program
+    );
+    eprintln!(
+        "       {} repl [--record <transcript>] [<options>]",
+        program
+    );
+    eprintln!("       {} serve --jsonrpc", program);
+    eprintln!("       {} fmt --stdin", program);
+    eprintln!("       {} fmt --verify <source_file>...", program);
+    eprintln!("       {} check <source_file>...", program);
+    eprintln!("       {} info <source_file>", program);
+    eprintln!("       {} ast --graph <source_file>", program);
+    eprintln!("       {} self update", program);
+    eprintln!("       {} --version", program);
+    std::process::exit(exit::USAGE);
+}
+
+fn version() {
+    println!(
+        "riku {} ({})",
+        env!("CARGO_PKG_VERSION"),
+        env!("RIKU_GIT_HASH")
+    );
+    let features = [
+        ("self-update", cfg!(feature = "self-update")),
+        ("sqlite", cfg!(feature = "sqlite")),
+        ("bigint", cfg!(feature = "bigint")),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| *name)
+    .collect::<Vec<_>>();
+    if features.is_empty() {
+        println!("features: none");
+    } else {
+        println!("features: {}", features.join(", "));
+    }
+}
+
+#[cfg(feature = "self-update")]
+fn self_update(_program: &str) {
+    riku::update::self_update();
+}
+
+#[cfg(not(feature = "self-update"))]
+fn self_update(program: &str) {
+    eprintln!("{} was built without the `self-update` feature", program);
+    std::process::exit(exit::USAGE);
+}
+
+/// The interpreter runs on a thread with a stack this large, so that the
+/// default `--max-recursion` and `--max-nesting` limits are reached well
+/// before the stack overflows, even in a debug build.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
+    let interpreter = std::thread::Builder::new()
+        .name("main".to_string())
+        .stack_size(STACK_SIZE)
+        .spawn(|| crash::guard(run))
+        .unwrap_or_else(|e| {
+            eprintln!("Unable to start the interpreter: {}", e);
+            std::process::exit(exit::SOFTWARE);
+        });
+    if interpreter.join().is_err() {
+        std::process::exit(exit::SOFTWARE);
+    }
+}
+
+fn run() {
     let args = std::env::args().collect::<Vec<_>>();
-    if args.len() > 2 {
-        eprintln!("Usage: {} <source_file>", args[0]);
-        std::process::exit(1);
+    if args.len() == 2 && (args[1] == "--version" || args[1] == "-V") {
+        version();
+        return;
     }
-    if args.len() == 2 {
-        run_file(&args[1]);
-        std::process::exit(1);
-    } else {
-        run_cli();
+    if args.len() > 1 && args[1] == "self" {
+        if args.len() != 3 || args[2] != "update" {
+            usage(&args[0]);
+        }
+        self_update(&args[0]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "info" {
+        if args.len() != 3 {
+            usage(&args[0]);
+        }
+        run_info(&args[2]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "ast" {
+        if args.len() != 4 || args[2] != "--graph" {
+            usage(&args[0]);
+        }
+        run_ast_graph(&args[3]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "serve" {
+        if args.len() != 3 || args[2] != "--jsonrpc" {
+            usage(&args[0]);
+        }
+        run_serve(&[], &Output::new(Verbosity::Quiet));
+        return;
+    }
+    if args.len() > 1 && args[1] == "check" {
+        if args.len() < 3 {
+            usage(&args[0]);
+        }
+        run_check(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "fmt" {
+        match args.get(2).map(String::as_str) {
+            Some("--stdin") if args.len() == 3 => run_fmt_stdin(),
+            Some("--verify") if args.len() > 3 => run_fmt_verify(&args[3..]),
+            _ => usage(&args[0]),
+        }
+        return;
+    }
+
+    let mut verbosity = Verbosity::Normal;
+    let mut file = None;
+    let mut plugins = Vec::new();
+    let mut record = None;
+    // `riku repl` is the same as running without a file, and is where
+    // `--record` belongs.
+    let repl = args.len() > 1 && args[1] == "repl";
+    let mut rest = args[if repl { 2 } else { 1 }..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => {
+                verbosity = if verbosity >= Verbosity::Verbose {
+                    Verbosity::Trace
+                } else {
+                    Verbosity::Verbose
+                }
+            }
+            "-vv" => verbosity = Verbosity::Trace,
+            "--strict" => config::set_strict(true),
+            "--math" => config::set_math(true),
+            "--allow-shadow-builtins" => config::set_allow_shadow_builtins(true),
+            "--quiet-input" => config::set_quiet_input(true),
+            "--stats" => stats::enable(),
+            "--max-recursion" => match rest.next().and_then(|n| n.parse().ok()) {
+                Some(depth) if depth > 0 => config::set_max_recursion(depth),
+                _ => usage(&args[0]),
+            },
+            "--max-nesting" => match rest.next().and_then(|n| n.parse().ok()) {
+                Some(depth) if depth > 0 => config::set_max_nesting(depth),
+                _ => usage(&args[0]),
+            },
+            "--plugin" => match rest.next() {
+                Some(path) => plugins.push(path.clone()),
+                None => usage(&args[0]),
+            },
+            "--record" => match rest.next() {
+                Some(path) => record = Some(path.clone()),
+                None => usage(&args[0]),
+            },
+            _ if arg.starts_with('-') => usage(&args[0]),
+            _ if repl => usage(&args[0]),
+            _ if file.is_none() => file = Some(arg),
+            _ => usage(&args[0]),
+        }
+    }
+
+    let output = Output::new(verbosity);
+    match file {
+        Some(_) if record.is_some() => usage(&args[0]),
+        Some(file) => run_file(file, &plugins, &output),
+        None => run_cli(&plugins, &output, record.as_deref()),
     }
+    stats::report();
+    std::process::exit(exit::OK);
 }
@@ -0,0 +1,73 @@
+use crate::std_fn::Capability;
+
+/// The `//!` header at the top of a script:
+///
+/// ```text
+/// //! name: backup
+/// //! requires: stdout, stdin
+/// ```
+///
+/// Every line is a `key: value` pair. `requires` lists the capabilities the
+/// script needs; once it is declared, builtins needing anything else are
+/// refused. Other keys are kept as written for tools like `riku info`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub fields: Vec<(String, String)>,
+    pub requires: Option<Vec<Capability>>,
+}
+
+impl Metadata {
+    /// Reads the header from the start of `source`. Blank lines may come
+    /// before it and the header ends at the first other line. Errors carry
+    /// the line they were found on.
+    pub fn parse(source: &str) -> Result<Self, (usize, String)> {
+        let mut metadata = Metadata::default();
+        for (i, line) in source.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() && metadata.fields.is_empty() {
+                continue;
+            }
+            let Some(entry) = line.strip_prefix("//!") else {
+                break;
+            };
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = entry.split_once(':') else {
+                return Err((
+                    line_no,
+                    format!("Expected `key: value` in script header, found `{}`", entry),
+                ));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key == "requires" {
+                let requires = metadata.requires.get_or_insert_with(Vec::new);
+                for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                    let Some(capability) = Capability::from_name(name) else {
+                        return Err((
+                            line_no,
+                            format!(
+                                "Unknown capability `{}`, expected one of: {}",
+                                name,
+                                Capability::NAMES.join(", ")
+                            ),
+                        ));
+                    };
+                    requires.push(capability);
+                }
+            }
+            metadata.fields.push((key.to_string(), value.to_string()));
+        }
+        Ok(metadata)
+    }
+
+    /// The value of the first `key` line, if any.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
@@ -0,0 +1,486 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+#[cfg(feature = "bigint")]
+use crate::bigint::BigInt;
+use crate::{
+    env::Value,
+    error::{ErrorType, fatal, line_fatal},
+    expr::{call_value, check_arity, list_index, map_key},
+    stats,
+    std_fn::{Arity, CallLine, argument_type_error},
+    symbol::Symbol,
+};
+
+/// A method of every value whose type is `type_name`. The body gets the
+/// receiver and the already checked arguments.
+struct Method {
+    type_name: &'static str,
+    name: &'static str,
+    arity: Arity,
+    /// Parameter names, used to point at the argument in type errors.
+    params: &'static [&'static str],
+    body: fn(&Value, Vec<Value>) -> Value,
+}
+
+const METHODS: &[Method] = &[
+    // Strings
+    Method {
+        type_name: "string",
+        name: "len",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |s, _| Value::Int(string(s).chars().count() as i64),
+    },
+    Method {
+        type_name: "string",
+        name: "upper",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |s, _| Value::String(string(s).to_uppercase()),
+    },
+    Method {
+        type_name: "string",
+        name: "lower",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |s, _| Value::String(string(s).to_lowercase()),
+    },
+    Method {
+        type_name: "string",
+        name: "trim",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |s, _| Value::String(string(s).trim().to_string()),
+    },
+    Method {
+        type_name: "string",
+        name: "contains",
+        arity: Arity::exact(1),
+        params: &["text"],
+        body: |s, args| Value::Bool(string(s).contains(string_arg("string.contains", 0, &args[0]))),
+    },
+    Method {
+        type_name: "string",
+        name: "starts_with",
+        arity: Arity::exact(1),
+        params: &["prefix"],
+        body: |s, args| {
+            Value::Bool(string(s).starts_with(string_arg("string.starts_with", 0, &args[0])))
+        },
+    },
+    Method {
+        type_name: "string",
+        name: "ends_with",
+        arity: Arity::exact(1),
+        params: &["suffix"],
+        body: |s, args| {
+            Value::Bool(string(s).ends_with(string_arg("string.ends_with", 0, &args[0])))
+        },
+    },
+    Method {
+        type_name: "string",
+        name: "replace",
+        arity: Arity::exact(2),
+        params: &["from", "to"],
+        body: |s, args| {
+            let from = string_arg("string.replace", 0, &args[0]);
+            let to = string_arg("string.replace", 1, &args[1]);
+            Value::String(string(s).replace(from, to))
+        },
+    },
+    Method {
+        type_name: "string",
+        name: "split",
+        arity: Arity::range(0, 1),
+        params: &["separator"],
+        body: string_split,
+    },
+    // Lists
+    Method {
+        type_name: "list",
+        name: "len",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |xs, _| Value::Int(list(xs).borrow().len() as i64),
+    },
+    Method {
+        type_name: "list",
+        name: "push",
+        arity: Arity::variadic(),
+        params: &["values"],
+        body: |xs, args| {
+            list(xs).borrow_mut().extend(args);
+            Value::Nil
+        },
+    },
+    Method {
+        type_name: "list",
+        name: "pop",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |xs, _| {
+            list(xs).borrow_mut().pop().unwrap_or_else(|| {
+                fatal(
+                    ErrorType::RuntimeError,
+                    "Cannot pop from an empty list".to_string(),
+                )
+            })
+        },
+    },
+    Method {
+        type_name: "list",
+        name: "insert",
+        arity: Arity::exact(2),
+        params: &["index", "value"],
+        body: |xs, args| {
+            let mut items = list(xs).borrow_mut();
+            let i = list_index(&args[0], items.len() + 1);
+            items.insert(i, args[1].clone());
+            Value::Nil
+        },
+    },
+    Method {
+        type_name: "list",
+        name: "remove",
+        arity: Arity::exact(1),
+        params: &["index"],
+        body: |xs, args| {
+            let mut items = list(xs).borrow_mut();
+            let i = list_index(&args[0], items.len());
+            items.remove(i)
+        },
+    },
+    Method {
+        type_name: "list",
+        name: "contains",
+        arity: Arity::exact(1),
+        params: &["value"],
+        body: |xs, args| Value::Bool(list(xs).borrow().iter().any(|x| x.deep_eq(&args[0]))),
+    },
+    Method {
+        type_name: "list",
+        name: "reverse",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |xs, _| {
+            list(xs).borrow_mut().reverse();
+            Value::Nil
+        },
+    },
+    Method {
+        type_name: "list",
+        name: "join",
+        arity: Arity::range(0, 1),
+        params: &["separator"],
+        body: |xs, args| {
+            let separator = args.first().map_or("", |s| string_arg("list.join", 0, s));
+            let items = list(xs).borrow();
+            let parts = items.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            Value::String(parts.join(separator))
+        },
+    },
+    Method {
+        type_name: "list",
+        name: "map",
+        arity: Arity::exact(1),
+        params: &["function"],
+        body: |xs, args| {
+            // Copied first, so the callback may change the list.
+            let items = list(xs).borrow().clone();
+            let mapped = items
+                .into_iter()
+                .map(|x| call_value(args[0].clone(), vec![x]))
+                .collect();
+            Value::List(Rc::new(RefCell::new(mapped)))
+        },
+    },
+    Method {
+        type_name: "list",
+        name: "filter",
+        arity: Arity::exact(1),
+        params: &["function"],
+        body: |xs, args| {
+            let items = list(xs).borrow().clone();
+            let kept = items
+                .into_iter()
+                .filter(|x| {
+                    matches!(
+                        call_value(args[0].clone(), vec![x.clone()]),
+                        Value::Bool(true)
+                    )
+                })
+                .collect();
+            Value::List(Rc::new(RefCell::new(kept)))
+        },
+    },
+    // Tuples
+    Method {
+        type_name: "tuple",
+        name: "len",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |t, _| Value::Int(tuple(t).len() as i64),
+    },
+    Method {
+        type_name: "tuple",
+        name: "contains",
+        arity: Arity::exact(1),
+        params: &["value"],
+        body: |t, args| Value::Bool(tuple(t).iter().any(|x| x.deep_eq(&args[0]))),
+    },
+    // Maps
+    Method {
+        type_name: "map",
+        name: "len",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |m, _| Value::Int(map(m).borrow().len() as i64),
+    },
+    Method {
+        type_name: "map",
+        name: "keys",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |m, _| {
+            let keys = map(m).borrow().keys().cloned().map(Value::String).collect();
+            Value::List(Rc::new(RefCell::new(keys)))
+        },
+    },
+    Method {
+        type_name: "map",
+        name: "values",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |m, _| {
+            let values = map(m).borrow().values().cloned().collect();
+            Value::List(Rc::new(RefCell::new(values)))
+        },
+    },
+    Method {
+        type_name: "map",
+        name: "contains",
+        arity: Arity::exact(1),
+        params: &["key"],
+        body: |m, args| Value::Bool(map(m).borrow().contains_key(&map_key(&args[0]))),
+    },
+    Method {
+        type_name: "map",
+        name: "remove",
+        arity: Arity::exact(1),
+        params: &["key"],
+        body: |m, args| {
+            let removed = map(m).borrow_mut().remove(&map_key(&args[0]));
+            removed.unwrap_or(Value::Nil)
+        },
+    },
+    // Numbers
+    Method {
+        type_name: "number",
+        name: "abs",
+        arity: Arity::exact(0),
+        params: &[],
+        body: number_abs,
+    },
+    Method {
+        type_name: "number",
+        name: "floor",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |n, _| round_with(n, f64::floor),
+    },
+    Method {
+        type_name: "number",
+        name: "ceil",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |n, _| round_with(n, f64::ceil),
+    },
+    Method {
+        type_name: "number",
+        name: "round",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |n, _| round_with(n, f64::round),
+    },
+    Method {
+        type_name: "number",
+        name: "to_fixed",
+        arity: Arity::exact(1),
+        params: &["digits"],
+        body: number_to_fixed,
+    },
+    // Every value
+    Method {
+        type_name: ANY,
+        name: "to_str",
+        arity: Arity::exact(0),
+        params: &[],
+        body: |v, _| Value::String(v.to_string()),
+    },
+];
+
+/// The `type_name` of methods that every value has. A struct or class can
+/// declare its own method of the same name, which takes its place.
+const ANY: &str = "any";
+
+/// Whether every value has a method called `name`, so that an instance
+/// without a field or method of that name still answers to it.
+pub fn is_universal(name: Symbol) -> bool {
+    METHODS
+        .iter()
+        .any(|m| m.type_name == ANY && m.name == name.as_str())
+}
+
+/// Calls `receiver.name(args)` on a builtin value, as in `"abc".len()` or
+/// `xs.push(3)`. Struct and class instances have their own fields and
+/// methods instead.
+pub fn call(receiver: Value, name: Symbol, args: Vec<Value>, line: usize) -> Value {
+    stats::call();
+    let type_name = receiver.type_name();
+    let Some(method) = METHODS
+        .iter()
+        .find(|m| (m.type_name == type_name || m.type_name == ANY) && m.name == name.as_str())
+    else {
+        line_fatal(
+            ErrorType::TypeError,
+            line,
+            format!(
+                "`{}` value `{}` has no method `{}`",
+                type_name, receiver, name
+            ),
+        );
+    };
+    check_arity(
+        &format!("{}.{}", type_name, name),
+        method.arity,
+        args.len(),
+        Some(line),
+    );
+    let _line = CallLine::enter(Some(line));
+    stats::alloc((method.body)(&receiver, args))
+}
+
+fn string_split(s: &Value, args: Vec<Value>) -> Value {
+    let s = string(s);
+    let parts: Vec<Value> = match args.first() {
+        Some(separator) => {
+            let separator = string_arg("string.split", 0, separator);
+            if separator.is_empty() {
+                s.chars().map(|c| Value::String(c.to_string())).collect()
+            } else {
+                s.split(separator)
+                    .map(|part| Value::String(part.to_string()))
+                    .collect()
+            }
+        }
+        None => s
+            .split_whitespace()
+            .map(|part| Value::String(part.to_string()))
+            .collect(),
+    };
+    Value::List(Rc::new(RefCell::new(parts)))
+}
+
+fn number_abs(n: &Value, _: Vec<Value>) -> Value {
+    match n {
+        Value::Int(i) => match i.checked_abs() {
+            Some(abs) => Value::Int(abs),
+            None => Value::Number((*i as f64).abs()),
+        },
+        Value::Number(f) => Value::Number(f.abs()),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(b) if **b < BigInt::default() => Value::BigInt(Rc::new(b.neg())),
+        _ => n.clone(),
+    }
+}
+
+/// Rounds a float to an integer with `f`. Integers are already whole.
+fn round_with(n: &Value, f: fn(f64) -> f64) -> Value {
+    match n {
+        Value::Number(x) => {
+            let x = f(*x);
+            if x >= i64::MIN as f64 && x < i64::MAX as f64 {
+                Value::Int(x as i64)
+            } else {
+                Value::Number(x)
+            }
+        }
+        _ => n.clone(),
+    }
+}
+
+/// The most digits `to_fixed` writes after the point.
+const MAX_FIXED_DIGITS: i64 = 100;
+
+/// The number as a string with exactly `digits` digits after the point,
+/// rounded, as in `(2 / 3).to_fixed(2)`, which is `0.67`.
+fn number_to_fixed(n: &Value, args: Vec<Value>) -> Value {
+    let digits = match &args[0] {
+        Value::Int(d) if (0..=MAX_FIXED_DIGITS).contains(d) => *d as usize,
+        Value::Int(d) => fatal(
+            ErrorType::RuntimeError,
+            format!(
+                "number.to_fixed() expected 0 to {} digits, got {}",
+                MAX_FIXED_DIGITS, d
+            ),
+        ),
+        value => argument_type_error("number.to_fixed", 0, Some("digits"), "integer", value),
+    };
+    // Integers are exact, so only their zeros after the point are added.
+    let whole = match n {
+        Value::Number(f) => return Value::String(format!("{:.*}", digits, f)),
+        Value::Int(i) => i.to_string(),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(b) => b.to_string(),
+        _ => unreachable!("number method on {}", n.type_name()),
+    };
+    if digits == 0 {
+        Value::String(whole)
+    } else {
+        Value::String(format!("{}.{}", whole, "0".repeat(digits)))
+    }
+}
+
+// The receiver of a method always has the type the method was found for.
+
+fn string(value: &Value) -> &str {
+    match value {
+        Value::String(s) => s,
+        _ => unreachable!("string method on {}", value.type_name()),
+    }
+}
+
+fn list(value: &Value) -> &Rc<RefCell<Vec<Value>>> {
+    match value {
+        Value::List(items) => items,
+        _ => unreachable!("list method on {}", value.type_name()),
+    }
+}
+
+fn tuple(value: &Value) -> &[Value] {
+    match value {
+        Value::Tuple(items) => items,
+        _ => unreachable!("tuple method on {}", value.type_name()),
+    }
+}
+
+fn map(value: &Value) -> &Rc<RefCell<BTreeMap<String, Value>>> {
+    match value {
+        Value::Map(map) => map,
+        _ => unreachable!("map method on {}", value.type_name()),
+    }
+}
+
+/// Argument `index` of `method`, written `type.name`, as a string.
+fn string_arg<'a>(method: &str, index: usize, value: &'a Value) -> &'a str {
+    match value {
+        Value::String(s) => s,
+        _ => {
+            let param = METHODS
+                .iter()
+                .find(|m| format!("{}.{}", m.type_name, m.name) == method)
+                .and_then(|m| m.params.get(index).copied());
+            argument_type_error(method, index, param, "string", value)
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, Write},
+    process,
+};
+
+use crate::error::{ErrorType, exit, fatal};
+
+/// How much the interpreter reports besides the program's own output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+    Trace,
+}
+
+/// Output policy shared by file mode and the REPL. Program output always
+/// goes through; this only decides the interpreter's own messages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Output {
+    pub verbosity: Verbosity,
+}
+
+impl Output {
+    pub fn new(verbosity: Verbosity) -> Self {
+        Output { verbosity }
+    }
+
+    /// Informational text such as the REPL banner, hidden by `-q`.
+    pub fn banner(&self, message: &str) {
+        if self.verbosity >= Verbosity::Normal {
+            write_stdout(format_args!("{}\n", message));
+        }
+    }
+
+    /// Progress messages enabled by `-v`.
+    pub fn info(&self, message: &str) {
+        if self.verbosity >= Verbosity::Verbose {
+            eprintln!("info: {}", message);
+        }
+    }
+
+    /// Internal tracing enabled by `-vv`. The message is only built when it
+    /// will be printed.
+    pub fn trace<F: FnOnce() -> String>(&self, message: F) {
+        if self.verbosity >= Verbosity::Trace {
+            eprintln!("trace: {}", message());
+        }
+    }
+}
+
+/// A stream that program output is written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+thread_local! {
+    /// Replacements for the process streams, indexed by `Stream`.
+    static SINKS: RefCell<[Option<Box<dyn Write>>; 2]> = const { RefCell::new([None, None]) };
+}
+
+/// Sends what scripts on this thread write to `stream` into `sink`, or
+/// back to the process stream for `None`, and returns the sink it
+/// replaces. Embedders and tests use it to capture a script's output. The
+/// interpreter's own error messages still go to the process stderr.
+pub fn set_sink(stream: Stream, sink: Option<Box<dyn Write>>) -> Option<Box<dyn Write>> {
+    SINKS.with_borrow_mut(|sinks| std::mem::replace(&mut sinks[stream as usize], sink))
+}
+
+/// Writes program output to `stream`. When the reader has gone away, as in
+/// `riku script | head`, the program ends quietly; any other failure is a
+/// runtime error.
+pub fn write_to(stream: Stream, args: fmt::Arguments) {
+    // Formatting a value can run a class's `to_str`, which may print too, so
+    // the text is built before the sinks are borrowed.
+    let text = args.to_string();
+    let result = SINKS.with_borrow_mut(|sinks| match (&mut sinks[stream as usize], stream) {
+        (Some(sink), _) => sink.write_all(text.as_bytes()),
+        (None, Stream::Stdout) => io::stdout().write_all(text.as_bytes()),
+        (None, Stream::Stderr) => io::stderr().write_all(text.as_bytes()),
+    });
+    check_write(result);
+}
+
+pub fn write_stdout(args: fmt::Arguments) {
+    write_to(Stream::Stdout, args);
+}
+
+pub fn write_stderr(args: fmt::Arguments) {
+    write_to(Stream::Stderr, args);
+}
+
+/// Flushes stdout, so a prompt shows before input is read.
+pub fn flush_stdout() {
+    let result = SINKS.with_borrow_mut(|sinks| match &mut sinks[Stream::Stdout as usize] {
+        Some(sink) => sink.flush(),
+        None => io::stdout().flush(),
+    });
+    check_write(result);
+}
+
+fn check_write(result: io::Result<()>) {
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => process::exit(exit::OK),
+        Err(e) => fatal(
+            ErrorType::RuntimeError,
+            format!("Unable to write output: {}", e),
+        ),
+    }
+}
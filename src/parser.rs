@@ -1,28 +1,57 @@
-use std::process;
-
 use crate::{
-    error::{ErrorType, line_error},
-    expr::Expr,
-    stmt::Stmt,
-    token::{Token, TokenType},
+    config,
+    env::Value,
+    error::{ErrorType, excerpt, line_fatal, warning},
+    expr::{Expr, Op},
+    format::FormatSpec,
+    pattern::{MatchArm, Pattern, TYPE_NAMES},
+    std_fn,
+    stmt::{Method, Param, Stmt, StructDecl},
+    symbol::Symbol,
+    token::{Token, TokenType, match_subject_follows, starts_operand},
+    types::Type,
 };
 
 #[derive(Debug)]
-pub struct Parser {
-    tokens: Vec<Token>,
+pub struct Parser<'a> {
+    tokens: &'a [Token],
     current: usize,
     stmts: Vec<Stmt>,
+    /// Labels of the loops around the statement being parsed, innermost
+    /// last. A function body starts with none.
+    labels: Vec<Symbol>,
+    /// How many `|x|` bars of math mode are open around the expression
+    /// being parsed, outside any brackets. While some are, `|` closes one
+    /// instead of meaning `or`.
+    abs_depth: usize,
+    /// How many expressions and statements enclose the one being parsed,
+    /// checked against `config::max_nesting` so that a pathological
+    /// program is an error instead of a stack overflow.
+    depth: usize,
+    /// Whether to warn about bindings that hide a builtin. Only on when the
+    /// program is about to run, so tools such as `fmt` stay quiet.
+    warn_shadowing: bool,
 }
 
-impl Parser {
-    pub fn new(tokens: &[Token]) -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
         Parser {
-            tokens: tokens.to_vec(),
+            tokens,
             current: 0,
             stmts: Vec::new(),
+            labels: Vec::new(),
+            abs_depth: 0,
+            depth: 0,
+            warn_shadowing: false,
         }
     }
 
+    /// Turns on the warning for a variable, parameter or nested function
+    /// named after a builtin.
+    pub fn warn_shadowing(&mut self, warn: bool) {
+        self.warn_shadowing = warn;
+    }
+
     fn peek(&self) -> Option<&Token> {
         if self.current < self.tokens.len() {
             Some(&self.tokens[self.current])
@@ -39,6 +68,17 @@ impl Parser {
         }
     }
 
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.current + offset)
+    }
+
+    /// A `{` at the start of a statement opens a block, unless it is
+    /// followed by `"key":`, which can only be a map literal.
+    fn is_map_literal(&self) -> bool {
+        matches!(self.peek_at(1), Some(t) if t.token_type == TokenType::String)
+            && matches!(self.peek_at(2), Some(t) if t.token_type == TokenType::Colon)
+    }
+
     fn peek_back(&self, offset: usize) -> Option<&Token> {
         if self.current >= offset {
             Some(&self.tokens[self.current - offset])
@@ -57,10 +97,8 @@ impl Parser {
     }
 
     fn check(&mut self, s: &str) -> bool {
-        if self.current < self.tokens.len() {
-            if self.peek().unwrap().lexeme == s {
-                return true;
-            }
+        if self.current < self.tokens.len() && self.peek().unwrap().lexeme == s {
+            return true;
         }
         false
     }
@@ -79,8 +117,8 @@ impl Parser {
         }
     }
 
-    pub fn get_stmts(&self) -> &Vec<Stmt> {
-        &self.stmts
+    pub fn into_stmts(self) -> Vec<Stmt> {
+        self.stmts
     }
 
     pub fn parse(&mut self) {
@@ -100,141 +138,493 @@ impl Parser {
                 found = true;
                 break;
             }
-            match t.token_type {
-                TokenType::EOL => {
+            if t.token_type == TokenType::EOL {
+                self.next();
+                continue;
+            }
+            let Some(stmt) = self.parse_stmt() else {
+                return (stmts, found);
+            };
+            stmts.push(stmt);
+        }
+        (stmts, found)
+    }
+
+    fn parse_stmt(&mut self) -> Option<Stmt> {
+        self.nested(Self::parse_stmt_inner)
+    }
+
+    fn parse_stmt_inner(&mut self) -> Option<Stmt> {
+        let stmt = match self.peek()?.token_type {
+            TokenType::Let | TokenType::Const => self.parse_let(),
+            TokenType::Ident if self.is_loop_label() => self.parse_labeled(),
+            TokenType::Ident if self.at_block_keyword("loop") => self.parse_loop(),
+            TokenType::Ident if self.at_block_keyword("do") => self.parse_do(),
+            TokenType::Ident if self.is_match_keyword() => {
+                let (subject, arms) = self.parse_match();
+                Stmt::Match(subject, arms)
+            }
+            TokenType::Ident if self.is_assert_keyword() => self.parse_assert(),
+            TokenType::Ident => self.parse_ident(),
+            TokenType::LBrace if !self.is_map_literal() => self.parse_brace(),
+            TokenType::If => self.parse_if(),
+            TokenType::Break => {
+                self.next();
+                Stmt::Break(self.parse_jump_label())
+            }
+            TokenType::Continue => {
+                self.next();
+                Stmt::Continue(self.parse_jump_label())
+            }
+            TokenType::While => self.parse_while(),
+            TokenType::For => self.parse_for(),
+            TokenType::Fn
+                if !matches!(
+                    self.peek_next()?.token_type,
+                    TokenType::LParen | TokenType::LBracket
+                ) =>
+            {
+                self.parse_fn()
+            }
+            TokenType::Struct | TokenType::Class => self.parse_struct(),
+            TokenType::Return => {
+                self.next();
+                let expr = self.parse_expr();
+                Stmt::Return(expr)
+            }
+            _ => match self.parse_expr()? {
+                expr @ (Expr::Increment { .. } | Expr::CompoundAssign { .. }) => Stmt::Semi(expr),
+                expr => self.expr_stmt(expr),
+            },
+        };
+        Some(stmt)
+    }
+
+    /// A parameter list, `(a, b = 1)` after `fn` or `|a, b|` for a lambda.
+    /// Default values are only allowed between parentheses, since `|` is
+    /// also an operator.
+    fn parse_params(&mut self, line: usize, open: &str, close: &str) -> Vec<Param> {
+        let mut args = Vec::new();
+        if self.peek().is_none() || self.peek_next().is_none() {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!("Expected `{}{}`, found EOF", open, close),
+            );
+        }
+        if !self.check(open) {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected `{}`, found `{}`",
+                    open,
+                    excerpt(&self.peek().unwrap().lexeme)
+                ),
+            );
+        }
+        self.next();
+        if !self.check(close) {
+            loop {
+                if self.check("...") {
                     self.next();
-                    continue;
-                }
-                TokenType::Let => {
-                    let stmt = self.parse_let();
-                    stmts.push(stmt);
-                }
-                TokenType::Ident => {
-                    let stmt = self.parse_ident();
-                    stmts.push(stmt);
-                }
-                TokenType::LBrace => {
-                    let stmt = self.parse_brace();
-                    stmts.push(stmt);
-                }
-                TokenType::If => {
-                    let stmt = self.parse_if();
-                    stmts.push(stmt);
-                }
-                TokenType::Break => {
-                    stmts.push(Stmt::Break);
-                }
-                TokenType::Continue => {
-                    stmts.push(Stmt::Continue);
-                }
-                TokenType::While => {
-                    let stmt = self.parse_while();
-                    stmts.push(stmt);
-                }
-                TokenType::Fn => {
-                    let stmt = self.parse_fn();
-                    stmts.push(stmt);
+                    let name = self.expect_name(line);
+                    self.warn_if_shadows_builtin(&name);
+                    args.push(Param {
+                        name: Symbol::intern(&name.lexeme),
+                        default: None,
+                        rest: true,
+                        ty: None,
+                    });
+                    if !self.check(close) {
+                        line_fatal(
+                            ErrorType::SyntaxError,
+                            line,
+                            format!(
+                                "Rest parameter `...{}` must be the last parameter",
+                                name.lexeme
+                            ),
+                        );
+                    }
+                    break;
                 }
-                TokenType::Return => {
+                let name = self.expect_name(line);
+                self.warn_if_shadows_builtin(&name);
+                let ty = self.parse_annotation(line);
+                let default = if close == ")" && self.check("=") {
                     self.next();
-                    let expr = self.parse_expr();
-                    stmts.push(Stmt::Return(expr));
-                }
-                _ => {
                     let Some(expr) = self.parse_expr() else {
-                        return (stmts, found);
+                        line_fatal(
+                            ErrorType::SyntaxError,
+                            line,
+                            format!("Expected default value for `{}`", name.lexeme),
+                        );
                     };
-                    stmts.push(Stmt::Expr(expr));
+                    Some(expr)
+                } else {
+                    if args.iter().any(|p: &Param| p.default.is_some()) {
+                        line_fatal(
+                            ErrorType::SyntaxError,
+                            line,
+                            format!(
+                                "Parameter `{}` without a default cannot follow one with a default",
+                                name.lexeme
+                            ),
+                        );
+                    }
+                    None
+                };
+                args.push(Param {
+                    name: Symbol::intern(&name.lexeme),
+                    default,
+                    rest: false,
+                    ty,
+                });
+                if self.check(",") {
+                    self.next();
+                } else {
+                    break;
                 }
             }
-            self.next()
         }
-        (stmts, found)
+        if !self.check(close) {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected `{}`, found `{}`",
+                    close,
+                    excerpt(&self.peek().unwrap().lexeme)
+                ),
+            );
+        }
+        self.next();
+        args
     }
 
-    fn parse_fn(&mut self) -> Stmt {
-        let line = self.peek().unwrap().line;
+    /// `fn(a, b) { ... }` or `|a, b| expr` in expression position. An
+    /// expression body is returned from the function. `fn[x, y](a) { ... }`
+    /// also copies `x` and `y` into the lambda's closure.
+    fn expr_lambda(&mut self) -> Option<Expr> {
+        let line = self.peek()?.line;
+        let mut captures = None;
+        let (params, body) = if self.peek()?.token_type == TokenType::Fn {
+            self.next();
+            if self.check("[") {
+                captures = Some(self.parse_captures(line));
+            }
+            let params = self.parse_params(line, "(", ")");
+            (params, self.parse_fn_body(line))
+        } else {
+            let params = self.parse_params(line, "|", "|");
+            let labels = std::mem::take(&mut self.labels);
+            let body = if self.check("{") {
+                self.parse_brace()
+            } else {
+                let Some(expr) = self.parse_expr() else {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        line,
+                        "Expected expression, after lambda parameters".to_string(),
+                    );
+                };
+                Stmt::Return(Some(expr))
+            };
+            self.labels = labels;
+            (params, body)
+        };
+        let lambda = Expr::Lambda(params.into(), Box::new(body));
+        Some(match captures {
+            Some(names) => Expr::Capture {
+                names,
+                lambda: Box::new(lambda),
+                line: line as u32,
+            },
+            None => lambda,
+        })
+    }
+
+    /// The `[x, y]` of `fn[x, y](...)`: the names a lambda captures by value.
+    fn parse_captures(&mut self, line: usize) -> Box<[Symbol]> {
         self.next();
-        let name = match self.peek() {
-            Some(t) if t.token_type == TokenType::Ident => {
-                let t = t.clone();
+        let mut names = Vec::new();
+        while let Some(token) = self.peek()
+            && token.token_type == TokenType::Ident
+        {
+            names.push(Symbol::intern(&token.lexeme));
+            self.next();
+            if self.check(",") {
                 self.next();
-                t
+            } else {
+                break;
+            }
+        }
+        if !self.check("]") {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected a variable name or `]` in the captures of `fn[...]`, found `{}`",
+                    excerpt(&self.peek().map_or(String::new(), |t| t.lexeme.clone()))
+                ),
+            );
+        }
+        self.next();
+        names.into()
+    }
+
+    fn at_word(&self, word: &str) -> bool {
+        self.peek().is_some_and(|t| t.is_word(word))
+    }
+
+    /// `loop` or `do` is a keyword when a block follows it; otherwise it is
+    /// a name.
+    fn at_block_keyword(&self, word: &str) -> bool {
+        self.at_word(word)
+            && self
+                .peek_next()
+                .is_some_and(|t| t.token_type == TokenType::LBrace)
+    }
+
+    /// `elif` is a keyword after the block of an `if` when a condition
+    /// follows it; the caller checks the block.
+    fn is_elif_keyword(&self) -> bool {
+        self.at_word("elif")
+            && self
+                .peek_next()
+                .is_some_and(|t| starts_operand(t.token_type))
+    }
+
+    /// `match` is a keyword when a subject follows it.
+    fn is_match_keyword(&self) -> bool {
+        self.at_word("match")
+            && match_subject_follows(self.tokens[self.current + 1..].iter().map(|t| t.token_type))
+    }
+
+    /// `assert` is a keyword at the start of a statement when a condition
+    /// follows it.
+    fn is_assert_keyword(&self) -> bool {
+        self.at_word("assert")
+            && self
+                .peek_next()
+                .is_some_and(|t| starts_operand(t.token_type))
+    }
+
+    /// `outer:` before a loop.
+    fn is_loop_label(&self) -> bool {
+        self.peek_next()
+            .is_some_and(|t| t.token_type == TokenType::Colon)
+            && self.peek_at(2).is_some_and(|t| {
+                matches!(t.token_type, TokenType::While | TokenType::For)
+                    || ((t.is_word("loop") || t.is_word("do"))
+                        && self
+                            .peek_at(3)
+                            .is_some_and(|t| t.token_type == TokenType::LBrace))
+            })
+    }
+
+    /// `outer: while ... { ... }`, whose body may `break outer` or
+    /// `continue outer` from an inner loop.
+    fn parse_labeled(&mut self) -> Stmt {
+        let token = self.advance().unwrap().clone();
+        let label = Symbol::intern(&token.lexeme);
+        if self.labels.contains(&label) {
+            line_fatal(
+                ErrorType::SyntaxError,
+                token.line,
+                format!("Label `{}` is already used by an enclosing loop", label),
+            );
+        }
+        self.next();
+        self.labels.push(label);
+        let stmt = self.parse_stmt().unwrap();
+        self.labels.pop();
+        Stmt::Labeled(label, Box::new(stmt))
+    }
+
+    /// The label after `break` or `continue`, if any. It must name an
+    /// enclosing loop.
+    fn parse_jump_label(&mut self) -> Option<Symbol> {
+        let token = self.peek()?;
+        if token.token_type != TokenType::Ident {
+            return None;
+        }
+        let label = Symbol::intern(&token.lexeme);
+        if !self.labels.contains(&label) {
+            line_fatal(
+                ErrorType::SyntaxError,
+                token.line,
+                format!("No enclosing loop is labelled `{}`", label),
+            );
+        }
+        self.next();
+        Some(label)
+    }
+
+    fn parse_fn_body(&mut self, line: usize) -> Stmt {
+        self.skip_line_breaks_before(TokenType::LBrace);
+        match self.peek() {
+            Some(t) if t.token_type == TokenType::LBrace => {
+                // Loops outside the function cannot be left from inside it.
+                let labels = std::mem::take(&mut self.labels);
+                let body = self.parse_brace();
+                self.labels = labels;
+                body
             }
             _ => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     line,
-                    format!(
-                        "Expected identifier, found `{}`",
-                        self.peek().unwrap().lexeme
-                    ),
+                    "Expected { and }, after `fn`".to_string(),
                 );
-                process::exit(1);
             }
-        };
-        let mut args = Vec::new();
-        if None == self.peek() || None == self.peek_next() {
-            line_error(
+        }
+    }
+
+    fn parse_fn(&mut self) -> Stmt {
+        let line = self.peek().unwrap().line;
+        self.next();
+        let name = self.expect_name(line);
+        if self.is_nested() {
+            self.warn_if_shadows_builtin(&name);
+        }
+        let args = self.parse_params(line, "(", ")");
+        let returns = self.parse_return_type(line);
+        let body = self.parse_fn_body(line);
+        Stmt::Function(Box::new(name), args, returns, Box::new(body))
+    }
+
+    /// `struct Point { x, y }` or `class Counter { count, fn inc(self) { ... } }`.
+    /// Fields are separated by commas, newlines or both; only a class may
+    /// declare methods.
+    fn parse_struct(&mut self) -> Stmt {
+        let keyword = self.advance().unwrap().clone();
+        let class = keyword.token_type == TokenType::Class;
+        let line = keyword.line;
+        let name = self.expect_name(line);
+        if !name.lexeme.starts_with(char::is_uppercase) {
+            line_fatal(
                 ErrorType::SyntaxError,
                 line,
-                format!("Expected `()`, found EOF"),
+                format!(
+                    "{} name `{}` must start with a capital letter",
+                    if class { "Class" } else { "Struct" },
+                    name.lexeme
+                ),
             );
-            process::exit(1);
         }
-        if self.peek().unwrap().token_type == TokenType::LParen {
-            self.next();
-            if self.peek().unwrap().token_type != TokenType::RParen {
-                loop {
-                    if let Some(token) = self.peek() {
-                        if token.token_type == TokenType::Ident {
-                            args.push(token.clone());
-                            self.next();
-                        } else {
-                            line_error(
-                                ErrorType::SyntaxError,
-                                line,
-                                format!("Expected identifier, found `{}`", token.lexeme),
-                            );
-                            process::exit(1);
-                        }
-                    }
-                    if self.check(",") {
-                        self.next();
-                    } else {
-                        break;
-                    }
+        self.skip_line_breaks_before(TokenType::LBrace);
+        if !self.check("{") {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected `{{`, found `{}`",
+                    excerpt(&self.peek().unwrap().lexeme)
+                ),
+            );
+        }
+        self.next();
+        let mut fields: Vec<Symbol> = Vec::new();
+        let mut methods: Vec<Method> = Vec::new();
+        loop {
+            self.skip_eol();
+            if self.check("}") {
+                break;
+            }
+            let member_line = self.peek().unwrap().line;
+            if self.peek().unwrap().token_type == TokenType::Fn {
+                if !class {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        member_line,
+                        format!(
+                            "Struct `{}` cannot have methods; declare it with `class`",
+                            name.lexeme
+                        ),
+                    );
                 }
+                let method = self.parse_method(member_line);
+                if methods.iter().any(|m| m.name == method.name) {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        member_line,
+                        format!(
+                            "Duplicate method `{}` in class `{}`",
+                            method.name, name.lexeme
+                        ),
+                    );
+                }
+                methods.push(method);
+                continue;
             }
-            if self.peek() == None || self.peek().unwrap().token_type != TokenType::RParen {
-                line_error(
+            let field = Symbol::intern(&self.expect_name(member_line).lexeme);
+            if fields.contains(&field) {
+                line_fatal(
                     ErrorType::SyntaxError,
-                    line,
-                    format!("Expected `)`, found `{}`", self.peek().unwrap().lexeme),
+                    member_line,
+                    format!("Duplicate field `{}` in `{}`", field, name.lexeme),
                 );
-                process::exit(1);
             }
-            self.next();
-        } else {
-            line_error(
+            fields.push(field);
+            let separator = self.peek().unwrap().token_type;
+            if matches!(separator, TokenType::Comma | TokenType::EOL) {
+                self.next();
+            } else if separator != TokenType::RBrace {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    member_line,
+                    format!(
+                        "Expected `,` or `}}`, found `{}`",
+                        excerpt(&self.peek().unwrap().lexeme)
+                    ),
+                );
+            }
+        }
+        self.next();
+        if let Some(method) = methods.iter().find(|m| fields.contains(&m.name)) {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "`{}` is both a field and a method of `{}`",
+                    method.name, name.lexeme
+                ),
+            );
+        }
+        Stmt::Struct(Box::new(StructDecl {
+            name,
+            class,
+            fields,
+            methods,
+        }))
+    }
+
+    /// `fn name(self, ...) { ... }` inside a class body.
+    fn parse_method(&mut self, line: usize) -> Method {
+        self.next();
+        let name = self.expect_name(line);
+        let mut params = self.parse_params(line, "(", ")");
+        if params
+            .first()
+            .is_none_or(|p| p.name != Symbol::intern("self"))
+        {
+            line_fatal(
                 ErrorType::SyntaxError,
                 line,
-                format!("Expected `(`, found `{}`", self.peek().unwrap().lexeme),
+                format!(
+                    "Method `{}` must take `self` as its first parameter",
+                    name.lexeme
+                ),
             );
-            process::exit(1);
         }
-        let body = match self.peek() {
-            Some(t) if t.token_type == TokenType::LBrace => self.parse_brace(),
-            _ => {
-                line_error(
-                    ErrorType::SyntaxError,
-                    line,
-                    format!("Expected {{ and }}, after `fn`"),
-                );
-                process::exit(1);
-            }
-        };
-        Stmt::Function(name, args, Box::new(body))
+        params.remove(0);
+        Method {
+            name: Symbol::intern(&name.lexeme),
+            params,
+            returns: self.parse_return_type(line),
+            body: self.parse_fn_body(line),
+        }
     }
 
     fn parse_while(&mut self) -> Stmt {
@@ -243,312 +633,1513 @@ impl Parser {
         let condition = match self.parse_expr() {
             Some(e) => e,
             None => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     line,
-                    format!("Expected expression, after `While`"),
+                    "Expected expression, after `While`".to_string(),
                 );
-                process::exit(1);
             }
         };
+        check_condition(&condition, line);
+        self.skip_line_breaks_before(TokenType::LBrace);
         let then = match self.peek() {
             Some(t) if t.token_type == TokenType::LBrace => self.parse_brace(),
             _ => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     line,
-                    format!("Expected {{ and }}, after `loop`"),
+                    "Expected { and }, after `while`".to_string(),
                 );
-                process::exit(1);
             }
         };
         Stmt::While(condition, Box::new(then))
     }
 
-    fn parse_if(&mut self) -> Stmt {
+    /// `loop { ... }`, which is `while true { ... }`.
+    fn parse_loop(&mut self) -> Stmt {
         let line = self.peek().unwrap().line;
         self.next();
-        let condition = match self.parse_expr() {
-            Some(e) => e,
-            None => {
-                line_error(
+        let body = match self.peek() {
+            Some(t) if t.token_type == TokenType::LBrace => self.parse_brace(),
+            _ => {
+                line_fatal(
                     ErrorType::SyntaxError,
                     line,
-                    format!("Expected expression, after `if`"),
+                    "Expected { and }, after `loop`".to_string(),
                 );
-                process::exit(1);
             }
         };
-        let then = match self.peek() {
+        Stmt::While(Expr::Bool(true), Box::new(body))
+    }
+
+    /// `do { ... } while condition`, with `while` on the line of the `}`.
+    fn parse_do(&mut self) -> Stmt {
+        let line = self.peek().unwrap().line;
+        self.next();
+        let body = match self.peek() {
             Some(t) if t.token_type == TokenType::LBrace => self.parse_brace(),
             _ => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     line,
-                    format!("Expected {{ and }}, after `if`"),
+                    "Expected { and }, after `do`".to_string(),
                 );
-                process::exit(1);
             }
         };
-        self.next();
-        let else_stmt = match self.peek() {
-            Some(t) if t.token_type == TokenType::Else => {
+        match self.peek() {
+            Some(t) if t.token_type == TokenType::While => {
                 self.next();
-                match self.peek() {
-                    Some(t) if t.token_type == TokenType::LBrace => Some(self.parse_brace()),
-                    _ => {
-                        line_error(
-                            ErrorType::SyntaxError,
-                            line,
-                            format!("Expected {{ and }}, after `else`"),
-                        );
-                        process::exit(1);
-                    }
-                }
             }
-            _ => None,
+            _ => {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    "Expected `while` and a condition after the `do` block".to_string(),
+                );
+            }
+        }
+        let Some(condition) = self.parse_expr() else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                "Expected expression, after `while`".to_string(),
+            );
         };
-        Stmt::If(condition, Box::new(then), else_stmt.map(Box::new))
+        check_condition(&condition, line);
+        Stmt::DoWhile(Box::new(body), condition)
     }
 
-    fn parse_brace(&mut self) -> Stmt {
+    fn parse_for(&mut self) -> Stmt {
         let line = self.peek().unwrap().line;
         self.next();
-        let (stmts, found) = self.parse_till(TokenType::RBrace);
-        if !found {
-            line_error(
+        let var = self.expect_name(line);
+        self.warn_if_shadows_builtin(&var);
+        let second = if self.check(",") {
+            self.next();
+            let second = self.expect_name(line);
+            self.warn_if_shadows_builtin(&second);
+            Some(Box::new(second))
+        } else {
+            None
+        };
+        if !self.at_word("in") {
+            line_fatal(
                 ErrorType::SyntaxError,
                 line,
-                format!("Missing closing for the starting brace"),
-            );
-            process::exit(1);
-        }
-        Stmt::Group(stmts)
-    }
-
-    fn parse_ident(&mut self) -> Stmt {
-        if self.peek_next().is_some() {
-            if self.peek_next().unwrap().token_type == TokenType::Equal {
-                let token = self.peek().unwrap().clone();
-                return self.parse_assign(token);
-            }
-        }
-        Stmt::Expr(self.parse_expr().unwrap())
-    }
-
-    fn parse_assign(&mut self, name: Token) -> Stmt {
-        self.next(); // consume the identifier
-        self.next(); // consume the equal sign
-        let expr = self.parse_expr();
-        if expr.is_none() {
-            line_error(
-                ErrorType::SyntaxError,
-                name.line,
                 format!(
-                    "Expected expression, found `{}`",
-                    self.peek().unwrap().lexeme
+                    "Expected `in`, found `{}`",
+                    excerpt(&self.peek().unwrap().lexeme)
                 ),
             );
-            process::exit(1);
-        }
-        let expr = expr.unwrap();
-        Stmt::Assign(name, expr)
-    }
-
-    fn parse_let(&mut self) -> Stmt {
-        self.next();
-        let name = self.advance().unwrap();
-        let name = name.clone();
-        if name.token_type != TokenType::Ident {
-            line_error(
-                ErrorType::SyntaxError,
-                name.line,
-                format!("Expected identifier, found `{}`", name.lexeme),
-            );
-            process::exit(1);
-        }
-        if self.check1("=").is_err() {
-            line_error(
-                ErrorType::SyntaxError,
-                name.line,
-                format!("Expected `=`, found `{}`", self.peek().unwrap().lexeme),
-            );
-            process::exit(1);
         }
         self.next();
-        let expr = self.parse_expr();
-        if expr.is_none() {
-            line_error(
-                ErrorType::SyntaxError,
-                name.line,
+        let start = match self.parse_expr() {
+            Some(e) => e,
+            None => {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    "Expected expression, after `in`".to_string(),
+                );
+            }
+        };
+        let end = if self.peek().unwrap().token_type == TokenType::DotDot {
+            if second.is_some() {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    "A range binds a single name; use `for i in a..b`".to_string(),
+                );
+            }
+            self.next();
+            match self.parse_expr() {
+                Some(e) => Some(e),
+                None => {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        line,
+                        "Expected expression, after `..`".to_string(),
+                    );
+                }
+            }
+        } else {
+            None
+        };
+        // `0..10 step 2`
+        let step = if end.is_some() && self.at_word("step") {
+            self.next();
+            match self.parse_expr() {
+                Some(e) => Some(Box::new(e)),
+                None => {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        line,
+                        "Expected expression, after `step`".to_string(),
+                    );
+                }
+            }
+        } else {
+            None
+        };
+        self.skip_line_breaks_before(TokenType::LBrace);
+        let then = match self.peek() {
+            Some(t) if t.token_type == TokenType::LBrace => self.parse_brace(),
+            _ => {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    "Expected { and }, after `for`".to_string(),
+                );
+            }
+        };
+        match end {
+            Some(end) => Stmt::For(
+                Box::new(var),
+                Box::new(start),
+                Box::new(end),
+                step,
+                Box::new(then),
+            ),
+            None => Stmt::ForIn(Box::new(var), second, start, Box::new(then)),
+        }
+    }
+
+    /// `assert condition` or `assert condition, message`. The message is
+    /// only evaluated when the condition is false.
+    fn parse_assert(&mut self) -> Stmt {
+        let line = self.peek().unwrap().line;
+        self.next();
+        let Some(condition) = self.parse_expr() else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                "Expected a condition after `assert`".to_string(),
+            );
+        };
+        let message = if self
+            .peek()
+            .is_some_and(|t| t.token_type == TokenType::Comma)
+        {
+            self.next();
+            let Some(message) = self.parse_expr() else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    "Expected a message after `,` in `assert`".to_string(),
+                );
+            };
+            Some(Box::new(message))
+        } else {
+            None
+        };
+        Stmt::Assert(condition, message, line as u32)
+    }
+
+    fn parse_if(&mut self) -> Stmt {
+        let (condition, then, else_stmt) = self.parse_if_parts();
+        Stmt::If(condition, Box::new(then), else_stmt.map(Box::new))
+    }
+
+    /// The condition and branches of an `if` or `elif`, shared by the
+    /// statement and the expression form. An `elif` chain becomes nested
+    /// `if`s in the `else` branches.
+    fn parse_if_parts(&mut self) -> (Expr, Stmt, Option<Stmt>) {
+        let token = self.peek().unwrap();
+        let (line, keyword) = (token.line, token.lexeme.clone());
+        self.next();
+        let condition = match self.parse_expr() {
+            Some(e) => e,
+            None => {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Expected expression, after `{}`", keyword),
+                );
+            }
+        };
+        check_condition(&condition, line);
+        self.skip_line_breaks_before(TokenType::LBrace);
+        let then = match self.peek() {
+            Some(t) if t.token_type == TokenType::LBrace => self.parse_brace(),
+            _ => {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Expected {{ and }}, after `{}`", keyword),
+                );
+            }
+        };
+        self.skip_line_breaks_before(TokenType::Else);
+        self.skip_line_breaks_if(Self::is_elif_keyword);
+        let else_stmt = match self.peek() {
+            _ if self.is_elif_keyword() => Some(self.nested(Self::parse_if)),
+            Some(t) if t.token_type == TokenType::Else => {
+                self.next();
+                self.skip_line_breaks_before(TokenType::LBrace);
+                match self.peek() {
+                    Some(t) if t.token_type == TokenType::LBrace => Some(self.parse_brace()),
+                    _ => {
+                        line_fatal(
+                            ErrorType::SyntaxError,
+                            line,
+                            "Expected { and }, after `else`".to_string(),
+                        );
+                    }
+                }
+            }
+            _ => None,
+        };
+        (condition, then, else_stmt)
+    }
+
+    fn parse_brace(&mut self) -> Stmt {
+        let line = self.peek().unwrap().line;
+        self.next();
+        let (stmts, found) = self.parse_till(TokenType::RBrace);
+        if !found {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                "Missing closing for the starting brace".to_string(),
+            );
+        }
+        self.next(); // consume the closing brace
+        Stmt::Group(stmts)
+    }
+
+    /// `match subject { pattern | pattern => body, ... }`, shared by the
+    /// statement and the expression form. A body is a block, an expression
+    /// or `return`, `break` or `continue`.
+    fn parse_match(&mut self) -> (Expr, Vec<MatchArm>) {
+        let line = self.peek().unwrap().line;
+        self.next();
+        let Some(subject) = self.parse_expr() else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                "Expected expression, after `match`".to_string(),
+            );
+        };
+        if !self.check("{") {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                "Expected { and }, after `match`".to_string(),
+            );
+        }
+        self.next();
+        self.skip_eol();
+        let mut arms = Vec::new();
+        while !self.check("}") {
+            let mut patterns = vec![self.parse_pattern()];
+            while self.check("|") {
+                self.next();
+                patterns.push(self.parse_pattern());
+            }
+            let guard = if self.peek().unwrap().token_type == TokenType::If {
+                self.next();
+                let Some(guard) = self.parse_expr() else {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        self.peek_back(1).unwrap().line,
+                        "Expected a condition after `if` in a match arm".to_string(),
+                    );
+                };
+                Some(guard)
+            } else {
+                None
+            };
+            if self.peek().unwrap().token_type != TokenType::FatArrow {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    self.peek().unwrap().line,
+                    format!(
+                        "Expected `=>`, found `{}`",
+                        excerpt(&self.peek().unwrap().lexeme)
+                    ),
+                );
+            }
+            self.next();
+            let body = match self.peek().unwrap().token_type {
+                TokenType::Return | TokenType::Break | TokenType::Continue => {
+                    self.parse_stmt().unwrap()
+                }
+                TokenType::LBrace => self.parse_brace(),
+                _ => match self.parse_expr() {
+                    Some(expr) => Stmt::Expr(expr),
+                    None => line_fatal(
+                        ErrorType::SyntaxError,
+                        self.peek_back(1).unwrap().line,
+                        "Expected expression, after `=>`".to_string(),
+                    ),
+                },
+            };
+            arms.push(MatchArm {
+                patterns,
+                guard,
+                body,
+            });
+            if self.check(",") {
+                self.next();
+            }
+            self.skip_eol();
+        }
+        self.next(); // consume the closing brace
+        (subject, arms)
+    }
+
+    /// A pattern of a `match` arm: a literal, `_`, a type pattern, a name
+    /// that binds the value, or a tuple, list or struct of patterns.
+    fn parse_pattern(&mut self) -> Pattern {
+        let line = self.peek().unwrap().line;
+        if self.check("(") || self.check("[") {
+            return self.parse_sequence_pattern(line, |parser, _| parser.parse_pattern());
+        }
+        let token = self.advance().unwrap().clone();
+        match token.token_type {
+            TokenType::Ident if token.lexeme == "_" => Pattern::Wildcard,
+            TokenType::Ident if self.check("{") => {
+                self.parse_struct_pattern(&token, |parser, _| parser.parse_pattern())
+            }
+            TokenType::Ident if !TYPE_NAMES.contains(&token.lexeme.as_str()) => {
+                Pattern::Bind(Symbol::intern(&token.lexeme))
+            }
+            TokenType::Ident => {
+                let name = TYPE_NAMES
+                    .iter()
+                    .find(|&&name| name == token.lexeme)
+                    .unwrap();
+                let binding = match self.peek() {
+                    Some(t) if t.token_type == TokenType::Ident => {
+                        let binding = self.advance().unwrap();
+                        (binding.lexeme != "_").then(|| Symbol::intern(&binding.lexeme))
+                    }
+                    _ => None,
+                };
+                Pattern::Type(name, binding)
+            }
+            TokenType::Number
+            | TokenType::String
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil => Pattern::Literal(Expr::new(token).literal_value()),
+            TokenType::Minus if self.peek().unwrap().token_type == TokenType::Number => {
+                let number = self.advance().unwrap().clone();
+                match Expr::new(number).literal_value() {
+                    Value::Int(n) => Pattern::Literal(Value::Int(-n)),
+                    Value::Number(n) => Pattern::Literal(Value::Number(-n)),
+                    _ => unreachable!(),
+                }
+            }
+            _ => line_fatal(
+                ErrorType::SyntaxError,
+                token.line,
+                format!("Expected a pattern, found `{}`", excerpt(&token.lexeme)),
+            ),
+        }
+    }
+
+    fn parse_ident(&mut self) -> Stmt {
+        if self
+            .peek_next()
+            .is_some_and(|t| t.token_type == TokenType::Equal || is_compound(t.token_type))
+        {
+            let token = self.peek().unwrap().clone();
+            return self.parse_assign(token);
+        }
+        // An assignment to an index or field, which gives no value as a
+        // statement.
+        match self.parse_expr().unwrap() {
+            Expr::Assign(target, value) => match *target {
+                Expr::Field(target, name) => Stmt::FieldAssign(target, name, *value),
+                Expr::Index(target, index) => Stmt::IndexAssign(target, index, *value),
+                target => self.expr_stmt(Expr::Assign(Box::new(target), value)),
+            },
+            // `x++` and `x += 1` on their own only update `x`.
+            expr @ (Expr::Increment { .. } | Expr::CompoundAssign { .. }) => Stmt::Semi(expr),
+            expr => self.expr_stmt(expr),
+        }
+    }
+
+    /// `expr;` throws its value away, a bare `expr` yields it.
+    fn expr_stmt(&mut self, expr: Expr) -> Stmt {
+        if self.check(";") {
+            Stmt::Semi(expr)
+        } else {
+            Stmt::Expr(expr)
+        }
+    }
+
+    fn parse_assign(&mut self, name: Token) -> Stmt {
+        self.next(); // consume the identifier
+        let op = self.advance().unwrap().clone(); // consume the assignment operator
+        let expr = self.parse_expr();
+        if expr.is_none() {
+            line_fatal(
+                ErrorType::SyntaxError,
+                name.line,
+                format!(
+                    "Expected expression, found `{}`",
+                    excerpt(&self.peek().unwrap().lexeme)
+                ),
+            );
+        }
+        let expr = expr.unwrap();
+        if is_compound(op.token_type) {
+            // `x += e` is sugar for `x = x + e`.
+            let current = Expr::new(name.clone());
+            return Stmt::Assign(Box::new(name), Expr::new_binary(current, &op, expr));
+        }
+        Stmt::Assign(Box::new(name), expr)
+    }
+
+    /// Consumes the identifier naming a variable, function or parameter.
+    fn expect_name(&mut self, line: usize) -> Token {
+        let token = self.peek().unwrap().clone();
+        if token.token_type == TokenType::Ident {
+            self.next();
+            return token;
+        }
+        let message = if token.token_type.is_keyword() {
+            format!(
+                "`{}` is a reserved word and cannot be used as a name",
+                token.lexeme
+            )
+        } else {
+            format!("Expected identifier, found `{}`", excerpt(&token.lexeme))
+        };
+        line_fatal(ErrorType::SyntaxError, line, message);
+    }
+
+    /// The `: type` after a variable or parameter name, if there is one.
+    fn parse_annotation(&mut self, line: usize) -> Option<Type> {
+        if !self.check(":") {
+            return None;
+        }
+        self.next();
+        Some(self.parse_type(line))
+    }
+
+    /// The `-> type` after a function's parameters, if there is one.
+    fn parse_return_type(&mut self, line: usize) -> Option<Type> {
+        if !self.check("->") {
+            return None;
+        }
+        self.next();
+        Some(self.parse_type(line))
+    }
+
+    /// A type name such as `number`, `nil` or `Point`, optionally followed
+    /// by `?` to also allow `nil`. Struct names are not resolved until the
+    /// annotation is checked, so a type may be declared after its use.
+    fn parse_type(&mut self, line: usize) -> Type {
+        let token = self.peek().unwrap().clone();
+        if !matches!(token.token_type, TokenType::Ident | TokenType::Nil) {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected a type such as `number` or `string`, found `{}`",
+                    excerpt(&token.lexeme)
+                ),
+            );
+        }
+        self.next();
+        let optional = self.check("?");
+        if optional {
+            self.next();
+        }
+        Type {
+            name: Symbol::intern(&token.lexeme),
+            optional,
+        }
+    }
+
+    /// Warns that the binding `name` hides the builtin of the same name,
+    /// which makes later calls to it fail in confusing ways. Top-level
+    /// `let`s and functions are not checked here, since redefining a
+    /// builtin there is already an error.
+    fn warn_if_shadows_builtin(&self, name: &Token) {
+        if self.warn_shadowing && std_fn::is_builtin(&name.lexeme) {
+            warning(format!(
+                "`{}` on line {} hides the builtin function `{}`; pick another name, \
+                 or pass --allow-shadow-builtins",
+                name.lexeme, name.line, name.lexeme
+            ));
+        }
+    }
+
+    /// Whether a `let` or `fn` being parsed is inside a block or function
+    /// rather than at the top level of the script.
+    fn is_nested(&self) -> bool {
+        self.depth > 1
+    }
+
+    /// The left side of a destructuring `let`: names, `_`, `Point { x, y }`
+    /// structs, and nested `(a, b)` tuples or `[a, b, rest...]` lists.
+    fn parse_binding(&mut self, line: usize) -> Pattern {
+        if self.check("(") || self.check("[") {
+            return self.parse_sequence_pattern(line, Self::parse_binding);
+        }
+        let name = self.expect_name(line);
+        if self.check("{") {
+            return self.parse_struct_pattern(&name, Self::parse_binding);
+        }
+        if name.lexeme == "_" {
+            return Pattern::Wildcard;
+        }
+        if self.is_nested() {
+            self.warn_if_shadows_builtin(&name);
+        }
+        Pattern::Bind(Symbol::intern(&name.lexeme))
+    }
+
+    /// A `(a, b)` tuple or `[a, b, rest...]` list pattern, whose elements
+    /// are parsed by `element`; `let` and `match` allow different ones.
+    fn parse_sequence_pattern(
+        &mut self,
+        line: usize,
+        element: fn(&mut Self, usize) -> Pattern,
+    ) -> Pattern {
+        let close = if self.check("(") { ")" } else { "]" };
+        self.next();
+        let mut patterns = Vec::new();
+        let mut rest = None;
+        while !self.check(close) {
+            if close == "]" && self.check("...") {
+                self.next();
+                rest = Some(self.expect_name(line));
+            } else if close == "]"
+                && self
+                    .peek_next()
+                    .is_some_and(|t| t.token_type == TokenType::Ellipsis)
+            {
+                rest = Some(self.expect_name(line));
+                self.next();
+            } else {
+                patterns.push(element(self, line));
+            }
+            if rest.is_some() && !self.check(close) {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    "The rest binding must come last".to_string(),
+                );
+            }
+            if self.check(",") {
+                self.next();
+            } else if !self.check(close) {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!(
+                        "Expected `,` or `{}`, found `{}`",
+                        close,
+                        excerpt(&self.peek().unwrap().lexeme)
+                    ),
+                );
+            }
+        }
+        self.next();
+        if close == ")" {
+            Pattern::Tuple(patterns)
+        } else {
+            let rest = rest.map(|name| Symbol::intern(&name.lexeme));
+            Pattern::List(patterns, rest)
+        }
+    }
+
+    /// `Point { x, y: pattern }` after the struct's name. A field without a
+    /// pattern binds a variable of the same name; fields left out are not
+    /// checked.
+    fn parse_struct_pattern(
+        &mut self,
+        name: &Token,
+        element: fn(&mut Self, usize) -> Pattern,
+    ) -> Pattern {
+        let line = name.line;
+        self.next();
+        let mut fields: Vec<(Symbol, Pattern)> = Vec::new();
+        while !self.check("}") {
+            let field = Symbol::intern(&self.expect_name(line).lexeme);
+            if fields.iter().any(|(f, _)| *f == field) {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Field `{}` appears twice in the pattern", field),
+                );
+            }
+            let pattern = if self.check(":") {
+                self.next();
+                element(self, line)
+            } else {
+                Pattern::Bind(field)
+            };
+            fields.push((field, pattern));
+            if self.check(",") {
+                self.next();
+            } else if !self.check("}") {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!(
+                        "Expected `,` or `}}`, found `{}`",
+                        excerpt(&self.peek().unwrap().lexeme)
+                    ),
+                );
+            }
+        }
+        self.next();
+        Pattern::Struct(Symbol::intern(&name.lexeme), fields)
+    }
+
+    /// `let name = value`, a destructuring `let`, or `const NAME = value`.
+    fn parse_let(&mut self) -> Stmt {
+        let keyword = self.advance().unwrap().clone();
+        let line = keyword.line;
+        let constant = keyword.token_type == TokenType::Const;
+        // `let mut = 1` names a variable `mut`.
+        let mutable = self.at_word("mut")
+            && self.peek_next().is_some_and(|t| {
+                matches!(
+                    t.token_type,
+                    TokenType::Ident | TokenType::LParen | TokenType::LBracket
+                )
+            });
+        if mutable {
+            if constant {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    "A `const` cannot be `mut`; use `let mut` for a variable".to_string(),
+                );
+            }
+            self.next();
+        }
+        let destructure = self.check("(")
+            || self.check("[")
+            || (self
+                .peek()
+                .is_some_and(|t| t.token_type == TokenType::Ident)
+                && self
+                    .peek_next()
+                    .is_some_and(|t| t.token_type == TokenType::LBrace));
+        if constant && destructure {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                "`const` binds a single name; use `let` to destructure".to_string(),
+            );
+        }
+        if destructure {
+            let pattern = self.parse_binding(line);
+            if self.check1("=").is_err() {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!(
+                        "Expected `=`, found `{}`",
+                        excerpt(&self.peek().unwrap().lexeme)
+                    ),
+                );
+            }
+            self.next();
+            let Some(expr) = self.parse_expr() else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!(
+                        "Expected expression, found `{}`",
+                        excerpt(&self.peek().unwrap().lexeme)
+                    ),
+                );
+            };
+            return Stmt::Destructure(Box::new(pattern), expr, mutable, line as u32);
+        }
+        let name = self.expect_name(line);
+        if self.is_nested() {
+            self.warn_if_shadows_builtin(&name);
+        }
+        let ty = self.parse_annotation(name.line);
+        if self.check1("=").is_err() {
+            line_fatal(
+                ErrorType::SyntaxError,
+                name.line,
+                format!(
+                    "Expected `=`, found `{}`",
+                    excerpt(&self.peek().unwrap().lexeme)
+                ),
+            );
+        }
+        self.next();
+        let expr = self.parse_expr();
+        if expr.is_none() {
+            line_fatal(
+                ErrorType::SyntaxError,
+                name.line,
                 format!(
                     "Expected expression, found `{}`",
-                    self.peek().unwrap().lexeme
+                    excerpt(&self.peek().unwrap().lexeme)
                 ),
             );
-            process::exit(1);
         }
         let expr = expr.unwrap();
-        Stmt::Let(name, expr)
+        if constant {
+            Stmt::Const(Box::new(name), expr, ty)
+        } else {
+            Stmt::Let(Box::new(name), expr, mutable, ty)
+        }
     }
 
     fn parse_call(&mut self) -> Option<Expr> {
         let name = self.peek().unwrap().clone();
         self.next();
-        let line = self.peek().unwrap().line;
-        if self.peek().is_some() {
-            if self.peek().unwrap().token_type == TokenType::LParen {
+        if self.peek().is_some() && self.peek().unwrap().token_type == TokenType::LParen {
+            let arguments = self.in_brackets(Self::parse_args)?;
+            let line = name.line;
+            return Some(Expr::new_call(Expr::new(name), arguments, line));
+        }
+        None
+    }
+
+    /// The parenthesized argument list of a call, starting at `(`.
+    fn parse_args(&mut self) -> Option<Vec<Expr>> {
+        let line = self.peek()?.line;
+        self.next();
+        let mut arguments = Vec::new();
+        if self.peek()?.token_type != TokenType::RParen {
+            loop {
+                if let Some(expr) = self.parse_item() {
+                    arguments.push(expr);
+                } else {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        line,
+                        format!(
+                            "Expected expression, found `{}`",
+                            excerpt(&self.peek().unwrap().lexeme)
+                        ),
+                    );
+                }
+
+                if !self.check(",") {
+                    break;
+                }
                 self.next();
-                let mut arguments = Vec::new();
-                if self.peek()?.token_type != TokenType::RParen {
-                    loop {
-                        if let Some(expr) = self.parse_expr() {
-                            arguments.push(expr);
-                        } else {
-                            line_error(
-                                ErrorType::SyntaxError,
-                                line,
-                                format!(
-                                    "Expected expression, found `{}`",
-                                    self.peek().unwrap().lexeme
-                                ),
-                            );
-                            process::exit(1);
-                        }
+            }
+        }
+        self.next();
+        Some(arguments)
+    }
 
-                        if !self.check(",") {
-                            break;
-                        }
-                        self.next();
-                    }
+    fn parse_expr(&mut self) -> Option<Expr> {
+        self.nested(Self::expr_assign)
+    }
+
+    /// `target = value` or `target += value`, which is the loosest operator
+    /// and groups to the right, so `a = b = 0` sets `b` and then `a`.
+    fn expr_assign(&mut self) -> Option<Expr> {
+        let line = self.peek()?.line;
+        let target = self.expr_ternary()?;
+        let op = match self.peek() {
+            Some(t) if t.token_type == TokenType::Equal || is_compound(t.token_type) => t.clone(),
+            _ => return Some(target),
+        };
+        if !is_assignable(&target) {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!("Invalid assignment target `{}`", target),
+            );
+        }
+        self.next();
+        let Some(value) = self.nested(Self::expr_assign) else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected expression, found `{}`",
+                    excerpt(&self.peek()?.lexeme)
+                ),
+            );
+        };
+        if is_compound(op.token_type) {
+            return Some(Expr::new_compound(target, &op, value));
+        }
+        Some(Expr::Assign(Box::new(target), Box::new(value)))
+    }
+
+    /// Runs `parse` one level deeper, failing once the program nests more
+    /// than `config::max_nesting` levels.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> T) -> T {
+        if self.depth >= config::max_nesting() {
+            let line = self.peek().map_or(0, |t| t.line);
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Program too deeply nested: more than {} levels of nested expressions \
+                     or blocks (raise the limit with `--max-nesting`)",
+                    config::max_nesting()
+                ),
+            );
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// A call argument or list element, where `...xs` spreads a list.
+    fn parse_item(&mut self) -> Option<Expr> {
+        if self.peek()?.token_type == TokenType::Ellipsis {
+            self.next();
+            return Some(Expr::Spread(Box::new(self.parse_expr()?)));
+        }
+        self.parse_expr()
+    }
+
+    /// `cond ? a : b`, right associative so `a ? b : c ? d : e` nests in
+    /// the else branch.
+    fn expr_ternary(&mut self) -> Option<Expr> {
+        let cond = self.expr_logic()?;
+        if self.peek()?.token_type != TokenType::Question {
+            return Some(cond);
+        }
+        let line = self.peek()?.line;
+        self.next();
+        let Some(then) = self.parse_expr() else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected expression, found `{}`",
+                    excerpt(&self.peek()?.lexeme)
+                ),
+            );
+        };
+        if self.peek()?.token_type != TokenType::Colon {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!("Expected `:`, found `{}`", excerpt(&self.peek()?.lexeme)),
+            );
+        }
+        self.next();
+        let Some(otherwise) = self.expr_ternary() else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected expression, found `{}`",
+                    excerpt(&self.peek()?.lexeme)
+                ),
+            );
+        };
+        Some(Expr::new_ternary(cond, then, otherwise))
+    }
+
+    fn expr_logic(&mut self) -> Option<Expr> {
+        let mut left = self.expr_equality()?;
+        while self.peek()?.token_type == TokenType::Ampersand
+            || (self.peek()?.token_type == TokenType::Pipe && self.abs_depth == 0)
+        {
+            let op = self.peek()?;
+            let op = op.clone();
+            self.next();
+            let right = self.expr_equality()?;
+            let expr = Expr::new_logic(left, &op, right);
+            left = expr;
+        }
+        Some(left)
+    }
+
+    fn expr_equality(&mut self) -> Option<Expr> {
+        let mut left = self.expr_relation()?;
+        while self.peek()?.token_type == TokenType::EqualEqual
+            || self.peek()?.token_type == TokenType::BangEqual
+        {
+            let op = self.peek()?;
+            let op = op.clone();
+            self.next();
+            let right = self.expr_relation()?;
+            let expr = Expr::new_logic(left, &op, right);
+            left = expr;
+        }
+        Some(left)
+    }
+
+    /// `a < b`, or a chain like `a < b <= c` that holds when every
+    /// comparison in it does.
+    fn expr_relation(&mut self) -> Option<Expr> {
+        let left = self.expr_term()?;
+        let mut rest = Vec::new();
+        while self.peek()?.token_type == TokenType::Less
+            || self.peek()?.token_type == TokenType::LessEqual
+            || self.peek()?.token_type == TokenType::Greater
+            || self.peek()?.token_type == TokenType::GreaterEqual
+        {
+            let op = self.peek()?;
+            let op = op.clone();
+            self.next();
+            rest.push((op, self.expr_term()?));
+        }
+        Some(match rest.len() {
+            0 => left,
+            1 => {
+                let (op, right) = rest.pop().unwrap();
+                Expr::new_logic(left, &op, right)
+            }
+            _ => Expr::new_chain(left, rest),
+        })
+    }
+
+    fn expr_term(&mut self) -> Option<Expr> {
+        let mut left = self.expr_factor()?;
+        while self.peek()?.token_type == TokenType::Plus
+            || self.peek()?.token_type == TokenType::Minus
+        {
+            let op = self.peek()?;
+            let op = op.clone();
+            self.next();
+            let right = self.expr_factor()?;
+            let expr = Expr::new_binary(left, &op, right);
+            left = expr;
+        }
+        Some(left)
+    }
+
+    fn expr_factor(&mut self) -> Option<Expr> {
+        let mut left = self.expr_unary()?;
+        while let Some(op) = self.factor_op() {
+            self.next();
+            let right = self.expr_unary()?;
+            left = Expr::Binary(Box::new(left), op, Box::new(right));
+        }
+        Some(left)
+    }
+
+    /// `*`, `/`, `%`, or the keyword operators `mod`, another spelling of
+    /// `%`, and `div`, division rounded toward negative infinity. `//`
+    /// starts a comment, so it cannot be the floor division operator.
+    fn factor_op(&self) -> Option<Op> {
+        let token = self.peek()?;
+        match token.token_type {
+            TokenType::Star => Some(Op::Mul),
+            TokenType::Slash => Some(Op::Div),
+            TokenType::Modulo => Some(Op::Mod),
+            TokenType::Ident
+                if self
+                    .peek_next()
+                    .is_some_and(|t| starts_operand(t.token_type)) =>
+            {
+                match token.lexeme.as_str() {
+                    "mod" => Some(Op::Mod),
+                    "div" => Some(Op::FloorDiv),
+                    _ => None,
                 }
+            }
+            _ => None,
+        }
+    }
+
+    fn expr_unary(&mut self) -> Option<Expr> {
+        if matches!(
+            self.peek()?.token_type,
+            TokenType::PlusPlus | TokenType::MinusMinus
+        ) {
+            let op = self.advance()?.clone();
+            let Some(target) = self.nested(Self::expr_unary) else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    op.line,
+                    format!("Expected a variable after `{}`", op.lexeme),
+                );
+            };
+            return Some(increment(&op, target, true));
+        }
+        if self.peek()?.token_type == TokenType::Minus || self.peek()?.token_type == TokenType::Bang
+        {
+            let op = self.peek()?;
+            let op = op.clone();
+            self.next();
+            let right = self.nested(Self::expr_unary)?;
+            return Some(Expr::new_unary(&op, right));
+        }
+        self.expr_power()
+    }
+
+    /// `base ^ exponent`, in math mode. It binds tighter than a leading
+    /// `-` and groups to the right, so `-2 ^ 2` is `-4` and `2 ^ 3 ^ 2` is
+    /// `512`.
+    fn expr_power(&mut self) -> Option<Expr> {
+        let base = self.expr_postfix()?;
+        if self.peek()?.token_type != TokenType::Caret {
+            return Some(base);
+        }
+        let line = self.peek()?.line;
+        self.next();
+        let Some(exponent) = self.nested(Self::expr_unary) else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                "Expected an exponent after `^`".to_string(),
+            );
+        };
+        Some(Expr::Binary(Box::new(base), Op::Pow, Box::new(exponent)))
+    }
+
+    fn expr_postfix(&mut self) -> Option<Expr> {
+        let mut expr = self.expr_group()?;
+        loop {
+            if config::math() && self.peek()?.token_type == TokenType::Bang {
                 self.next();
-                return Some(Expr::new_call(Expr::new(name), arguments));
+                expr = Expr::Unary(Op::Factorial, Box::new(expr));
+                continue;
+            }
+            let dot = self.peek()?.token_type;
+            if dot == TokenType::Dot
+                || dot == TokenType::QuestionDot
+                    && !matches!(self.peek_next(), Some(t) if t.token_type == TokenType::LBracket)
+            {
+                let line = self.peek()?.line;
+                self.next();
+                let name = Symbol::intern(&self.expect_name(line).lexeme);
+                expr = if dot == TokenType::Dot {
+                    Expr::Field(Box::new(expr), name)
+                } else {
+                    Expr::OptionalField(Box::new(expr), name)
+                };
+                if self.peek()?.token_type == TokenType::LParen {
+                    let args = self.in_brackets(Self::parse_args)?;
+                    expr = Expr::new_call(expr, args, line);
+                }
+                continue;
+            }
+            // Names and fields take their arguments above; any other
+            // function value must come from a call, an index or a group, as
+            // in `make()(2)`, `handlers[0](e)` or `(|x| x)(1)`.
+            if self.peek()?.token_type == TokenType::LParen
+                && matches!(expr, Expr::Call { .. } | Expr::Index(..) | Expr::Group(_))
+            {
+                let line = self.peek()?.line;
+                let args = self.in_brackets(Self::parse_args)?;
+                expr = Expr::new_call(expr, args, line);
+                continue;
+            }
+            if matches!(
+                self.peek()?.token_type,
+                TokenType::PlusPlus | TokenType::MinusMinus
+            ) {
+                let op = self.advance()?.clone();
+                expr = increment(&op, expr, false);
+                break;
+            }
+            let optional = match self.peek()?.token_type {
+                TokenType::LBracket => false,
+                TokenType::QuestionDot if matches!(self.peek_next(), Some(t) if t.token_type == TokenType::LBracket) =>
+                {
+                    self.next();
+                    true
+                }
+                _ => break,
+            };
+            let line = self.peek()?.line;
+            self.next();
+            let Some(index) = self.in_brackets(Self::parse_expr) else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Expected index, found `{}`", excerpt(&self.peek()?.lexeme)),
+                );
+            };
+            if self.peek()?.token_type != TokenType::RBracket {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Expected `]`, found `{}`", excerpt(&self.peek()?.lexeme)),
+                );
             }
+            self.next();
+            expr = if optional {
+                Expr::OptionalIndex(Box::new(expr), Box::new(index))
+            } else {
+                Expr::new_index(expr, index)
+            };
         }
-        None
+        Some(expr)
     }
 
-    fn parse_expr(&mut self) -> Option<Expr> {
-        self.expr_logic()
+    fn skip_eol(&mut self) {
+        while matches!(self.peek(), Some(t) if t.token_type == TokenType::EOL) {
+            self.next();
+        }
     }
 
-    fn expr_logic(&mut self) -> Option<Expr> {
-        let mut left = self.expr_equality()?;
-        while self.peek()?.token_type == TokenType::Ampersand
-            || self.peek()?.token_type == TokenType::Pipe
+    /// Skips line breaks if a token of `token_type` follows them, so the
+    /// `{` of a block, or an `else`, may start the next line as in the
+    /// `next-line` brace style of `riku fmt`. A `;` still ends the
+    /// statement.
+    fn skip_line_breaks_before(&mut self, token_type: TokenType) {
+        self.skip_line_breaks_if(|parser| {
+            parser.peek().is_some_and(|t| t.token_type == token_type)
+        });
+    }
+
+    /// Skips line breaks if `at` holds after them, as for an `elif`.
+    fn skip_line_breaks_if(&mut self, at: impl Fn(&Self) -> bool) {
+        let start = self.current;
+        while self
+            .tokens
+            .get(self.current)
+            .is_some_and(|t| t.token_type == TokenType::EOL && t.lexeme.is_empty())
         {
-            let op = self.peek()?;
-            let op = op.clone();
-            self.next();
-            let right = self.expr_equality()?;
-            let expr = Expr::new_logic(left, &op, right);
-            left = expr;
+            self.current += 1;
+        }
+        if !at(self) {
+            self.current = start;
         }
-        Some(left)
     }
 
-    fn expr_equality(&mut self) -> Option<Expr> {
-        let mut left = self.expr_relation()?;
-        while self.peek()?.token_type == TokenType::EqualEqual
-            || self.peek()?.token_type == TokenType::BangEqual
-        {
-            let op = self.peek()?;
-            let op = op.clone();
+    /// Desugars `"a ${x} b"` into `"a " + x + " b"`. The leading string
+    /// segment makes every `+` a string concatenation.
+    fn expr_interpolation(&mut self) -> Option<Expr> {
+        let start = self.peek()?.clone();
+        self.next();
+        let mut expr = Expr::String(start.lexeme);
+        loop {
+            // In a `"""` string the expression may start and end on lines
+            // of its own.
+            self.skip_eol();
+            let Some(part) = self.parse_expr() else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    start.line,
+                    "Expected expression inside `${}`".to_string(),
+                );
+            };
+            let part = if self.peek()?.token_type == TokenType::FormatSpec {
+                let spec = self.advance()?.clone();
+                match FormatSpec::parse(&spec.lexeme) {
+                    Ok(parsed) => Expr::Format(Box::new(part), Box::new(parsed)),
+                    Err(message) => line_fatal(ErrorType::SyntaxError, spec.line, message),
+                }
+            } else {
+                part
+            };
+            expr = Expr::Binary(Box::new(expr), Op::Add, Box::new(part));
+            self.skip_eol();
+            let segment = self.peek()?.clone();
+            if !matches!(
+                segment.token_type,
+                TokenType::InterpMid | TokenType::InterpEnd
+            ) {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    segment.line,
+                    format!(
+                        "Expected `}}` in string, found `{}`",
+                        excerpt(&segment.lexeme)
+                    ),
+                );
+            }
             self.next();
-            let right = self.expr_relation()?;
-            let expr = Expr::new_logic(left, &op, right);
-            left = expr;
+            expr = Expr::Binary(
+                Box::new(expr),
+                Op::Add,
+                Box::new(Expr::String(segment.lexeme)),
+            );
+            if segment.token_type == TokenType::InterpEnd {
+                return Some(expr);
+            }
         }
-        Some(left)
     }
 
-    fn expr_relation(&mut self) -> Option<Expr> {
-        let mut left = self.expr_term()?;
-        while self.peek()?.token_type == TokenType::Less
-            || self.peek()?.token_type == TokenType::LessEqual
-            || self.peek()?.token_type == TokenType::Greater
-            || self.peek()?.token_type == TokenType::GreaterEqual
-        {
-            let op = self.peek()?;
-            let op = op.clone();
+    fn expr_map(&mut self) -> Option<Expr> {
+        let line = self.peek()?.line;
+        self.next();
+        let mut entries = Vec::new();
+        self.skip_eol();
+        while self.peek()?.token_type != TokenType::RBrace {
+            let Some(key) = self.parse_expr() else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!(
+                        "Expected map key, found `{}`",
+                        excerpt(&self.peek()?.lexeme)
+                    ),
+                );
+            };
+            if self.peek()?.token_type != TokenType::Colon {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Expected `:`, found `{}`", excerpt(&self.peek()?.lexeme)),
+                );
+            }
             self.next();
-            let right = self.expr_term()?;
-            let expr = Expr::new_logic(left, &op, right);
-            left = expr;
+            self.skip_eol();
+            let Some(value) = self.parse_expr() else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!(
+                        "Expected expression, found `{}`",
+                        excerpt(&self.peek()?.lexeme)
+                    ),
+                );
+            };
+            entries.push((key, value));
+            self.skip_eol();
+            if !self.check(",") {
+                break;
+            }
+            self.next();
+            self.skip_eol();
         }
-        Some(left)
+        if self.peek()?.token_type != TokenType::RBrace {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!("Expected `}}`, found `{}`", excerpt(&self.peek()?.lexeme)),
+            );
+        }
+        self.next();
+        Some(Expr::Map(entries))
     }
 
-    fn expr_term(&mut self) -> Option<Expr> {
-        let mut left = self.expr_factor()?;
-        while self.peek()?.token_type == TokenType::Plus
-            || self.peek()?.token_type == TokenType::Minus
+    /// `Name {` starts a struct literal when the name is capitalized and the
+    /// brace holds `field:` entries or nothing, so `while Ready {` still
+    /// reads as a loop over a block.
+    fn is_struct_literal(&self) -> bool {
+        let capitalized = self
+            .peek()
+            .is_some_and(|t| t.lexeme.starts_with(char::is_uppercase));
+        if !capitalized || !matches!(self.peek_next(), Some(t) if t.token_type == TokenType::LBrace)
         {
-            let op = self.peek()?;
-            let op = op.clone();
-            self.next();
-            let right = self.expr_factor()?;
-            let expr = Expr::new_binary(left, &op, right);
-            left = expr;
+            return false;
+        }
+        let mut offset = 2;
+        while matches!(self.peek_at(offset), Some(t) if t.token_type == TokenType::EOL) {
+            offset += 1;
+        }
+        match self.peek_at(offset).map(|t| t.token_type) {
+            Some(TokenType::RBrace) => true,
+            Some(TokenType::Ident) => {
+                matches!(self.peek_at(offset + 1), Some(t) if t.token_type == TokenType::Colon)
+            }
+            _ => false,
         }
-        Some(left)
     }
 
-    fn expr_factor(&mut self) -> Option<Expr> {
-        let mut left = self.expr_unary()?;
-        while self.peek()?.token_type == TokenType::Star
-            || self.peek()?.token_type == TokenType::Slash
-            || self.peek()?.token_type == TokenType::Modulo
-        {
-            let op = self.peek()?;
-            let op = op.clone();
+    fn expr_struct(&mut self) -> Option<Expr> {
+        let name = self.advance()?.clone();
+        let line = name.line;
+        self.next();
+        let mut fields: Vec<(Symbol, Expr)> = Vec::new();
+        self.skip_eol();
+        while self.peek()?.token_type != TokenType::RBrace {
+            let field = Symbol::intern(&self.expect_name(line).lexeme);
+            if fields.iter().any(|(f, _)| *f == field) {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Field `{}` is given twice", field),
+                );
+            }
+            if self.peek()?.token_type != TokenType::Colon {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!("Expected `:`, found `{}`", excerpt(&self.peek()?.lexeme)),
+                );
+            }
             self.next();
-            let right = self.expr_unary()?;
-            let expr = Expr::new_binary(left, &op, right);
-            left = expr;
+            self.skip_eol();
+            let Some(value) = self.parse_expr() else {
+                line_fatal(
+                    ErrorType::SyntaxError,
+                    line,
+                    format!(
+                        "Expected expression, found `{}`",
+                        excerpt(&self.peek()?.lexeme)
+                    ),
+                );
+            };
+            fields.push((field, value));
+            self.skip_eol();
+            if !self.check(",") {
+                break;
+            }
+            self.next();
+            self.skip_eol();
         }
-        Some(left)
+        if self.peek()?.token_type != TokenType::RBrace {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!("Expected `}}`, found `{}`", excerpt(&self.peek()?.lexeme)),
+            );
+        }
+        self.next();
+        Some(Expr::StructLit(
+            Box::new(Expr::new(name)),
+            fields.into_boxed_slice(),
+        ))
     }
 
-    fn expr_unary(&mut self) -> Option<Expr> {
-        if self.peek()?.token_type == TokenType::Minus || self.peek()?.token_type == TokenType::Bang
-        {
-            let op = self.peek()?;
-            let op = op.clone();
+    fn expr_list(&mut self) -> Option<Expr> {
+        let line = self.peek()?.line;
+        self.next();
+        let mut items = Vec::new();
+        while self.peek()?.token_type == TokenType::EOL {
             self.next();
-            let right = self.expr_unary()?;
-            return Some(Expr::new_unary(&op, right));
         }
-        self.expr_group()
+        if self.peek()?.token_type != TokenType::RBracket {
+            loop {
+                if let Some(expr) = self.parse_item() {
+                    items.push(expr);
+                } else {
+                    line_fatal(
+                        ErrorType::SyntaxError,
+                        line,
+                        format!(
+                            "Expected expression, found `{}`",
+                            excerpt(&self.peek()?.lexeme)
+                        ),
+                    );
+                }
+                while self.peek()?.token_type == TokenType::EOL {
+                    self.next();
+                }
+                if !self.check(",") {
+                    break;
+                }
+                self.next();
+                while self.peek()?.token_type == TokenType::EOL {
+                    self.next();
+                }
+                if self.peek()?.token_type == TokenType::RBracket {
+                    break;
+                }
+            }
+        }
+        if self.peek()?.token_type != TokenType::RBracket {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!("Expected `]`, found `{}`", excerpt(&self.peek()?.lexeme)),
+            );
+        }
+        self.next();
+        Some(Expr::List(items))
     }
 
     fn expr_group(&mut self) -> Option<Expr> {
         if self.peek()?.token_type == TokenType::LParen {
+            return self.in_brackets(Self::expr_paren);
+        }
+        self.expr_primary()
+    }
+
+    /// Runs `parse` on the inside of a pair of brackets, where `|` means
+    /// `or` again even within the bars of `|x|`.
+    fn in_brackets<T>(&mut self, parse: fn(&mut Self) -> T) -> T {
+        let abs_depth = std::mem::take(&mut self.abs_depth);
+        let result = parse(self);
+        self.abs_depth = abs_depth;
+        result
+    }
+
+    /// `|x|`, the absolute value of `x`, in math mode.
+    fn expr_abs(&mut self) -> Option<Expr> {
+        let line = self.peek()?.line;
+        self.next();
+        self.abs_depth += 1;
+        let inner = self.parse_expr();
+        self.abs_depth -= 1;
+        let Some(inner) = inner else {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected expression after `|`, found `{}`",
+                    excerpt(&self.peek()?.lexeme)
+                ),
+            );
+        };
+        if self.peek()?.token_type != TokenType::Pipe {
+            line_fatal(
+                ErrorType::SyntaxError,
+                line,
+                format!(
+                    "Expected `|` after an absolute value, found `{}`",
+                    excerpt(&self.peek()?.lexeme)
+                ),
+            );
+        }
+        self.next();
+        Some(Expr::Unary(Op::Abs, Box::new(inner)))
+    }
+
+    /// `(expr)` groups. A comma makes it a tuple: `()`, `(a,)` and `(a, b)`.
+    fn expr_paren(&mut self) -> Option<Expr> {
+        self.next();
+        if self.peek()?.token_type == TokenType::RParen {
             self.next();
-            let expr = self.parse_expr()?;
-            if self.peek()?.token_type == TokenType::RParen {
+            return Some(Expr::Tuple(Box::new([])));
+        }
+        let expr = self.parse_expr()?;
+        if self.peek()?.token_type == TokenType::Comma {
+            let mut items = vec![expr];
+            while self.peek()?.token_type == TokenType::Comma {
                 self.next();
-                return Some(Expr::new_group(expr));
-            } else {
-                line_error(
+                if self.peek()?.token_type == TokenType::RParen {
+                    break;
+                }
+                items.push(self.parse_expr()?);
+            }
+            if self.peek()?.token_type != TokenType::RParen {
+                line_fatal(
                     ErrorType::SyntaxError,
                     self.peek_back(1)?.line,
                     "Missing closing parenthesis".to_string(),
                 );
-                process::exit(1);
             }
+            self.next();
+            return Some(Expr::Tuple(items.into_boxed_slice()));
         }
-        self.expr_primary()
+        if self.peek()?.token_type != TokenType::RParen {
+            line_fatal(
+                ErrorType::SyntaxError,
+                self.peek_back(1)?.line,
+                "Missing closing parenthesis".to_string(),
+            );
+        }
+        self.next();
+        Some(Expr::new_group(expr))
     }
 
     fn expr_primary(&mut self) -> Option<Expr> {
         match self.peek()?.token_type {
+            TokenType::Ident if self.is_match_keyword() => {
+                let (subject, arms) = self.parse_match();
+                Some(Expr::Match(Box::new(subject), arms.into_boxed_slice()))
+            }
+            TokenType::If => {
+                let (condition, then, else_stmt) = self.parse_if_parts();
+                Some(Expr::If(
+                    Box::new(condition),
+                    Box::new(then),
+                    else_stmt.map(Box::new),
+                ))
+            }
             TokenType::Number => {
                 self.next();
                 Some(Expr::new(self.peek_back(1)?.clone()))
             }
-            TokenType::True | TokenType::False => {
+            TokenType::True | TokenType::False | TokenType::Nil => {
                 self.next();
                 Some(Expr::new(self.peek_back(1)?.clone()))
             }
@@ -560,6 +2151,7 @@ impl Parser {
                             return Some(exp);
                         }
                     }
+                    _ if self.is_struct_literal() => return self.expr_struct(),
                     _ => {}
                 }
                 self.next();
@@ -569,15 +2161,331 @@ impl Parser {
                 self.next();
                 Some(Expr::new(self.peek_back(1)?.clone()))
             }
+            TokenType::InterpStart => self.expr_interpolation(),
+            TokenType::Pipe if config::math() => self.expr_abs(),
+            TokenType::Fn | TokenType::Pipe => self.expr_lambda(),
+            TokenType::LBracket => self.in_brackets(Self::expr_list),
+            TokenType::LBrace => self.in_brackets(Self::expr_map),
             TokenType::EOF => None,
             _ => {
-                line_error(
+                line_fatal(
                     ErrorType::SyntaxError,
                     self.peek_back(1)?.line,
-                    format!("Unexpected token `{}`", self.peek()?.lexeme),
+                    format!("Unexpected token `{}`", excerpt(&self.peek()?.lexeme)),
                 );
-                process::exit(1);
             }
         }
     }
 }
+
+/// Refuses `if x = 1`, almost always a mistyped `==`. An assignment that
+/// is meant as a condition goes in parentheses: `while (line = next()) != nil`.
+fn check_condition(condition: &Expr, line: usize) {
+    if let Expr::Assign(target, _) | Expr::CompoundAssign { target, .. } = condition {
+        line_fatal(
+            ErrorType::SyntaxError,
+            line,
+            format!(
+                "Assignment to `{}` used as a condition; compare with `==`, or put the assignment in parentheses",
+                target
+            ),
+        );
+    }
+}
+
+/// Whether `expr` names a place that can be assigned to or incremented.
+fn is_assignable(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Variable { .. } | Expr::Index(..) | Expr::Field(..)
+    )
+}
+
+/// `++` or `--` around `target`, which must be assignable.
+fn increment(token: &Token, target: Expr, prefix: bool) -> Expr {
+    if !is_assignable(&target) {
+        line_fatal(
+            ErrorType::SyntaxError,
+            token.line,
+            format!("Cannot apply `{}` to `{}`", token.lexeme, target),
+        );
+    }
+    let op = if token.token_type == TokenType::PlusPlus {
+        Op::Add
+    } else {
+        Op::Sub
+    };
+    Expr::Increment {
+        target: Box::new(target),
+        op,
+        prefix,
+    }
+}
+
+fn is_compound(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::PlusEqual
+            | TokenType::MinusEqual
+            | TokenType::StarEqual
+            | TokenType::SlashEqual
+            | TokenType::ModuloEqual
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{source::Source, token::CONTEXTUAL_KEYWORDS};
+
+    fn parse(input: &str) -> Vec<Stmt> {
+        let mut source = Source::new(input.to_string());
+        source.tokenize();
+        let mut parser = Parser::new(source.get_tokens());
+        parser.parse();
+        parser.into_stmts()
+    }
+
+    /// The name a `let` binds, and whether it is `let mut`.
+    fn let_name(stmt: &Stmt) -> (&str, bool) {
+        match stmt {
+            Stmt::Let(name, _, mutable, _) => (&name.lexeme, *mutable),
+            stmt => panic!("expected a let, got {:?}", stmt),
+        }
+    }
+
+    fn is_variable(expr: &Expr, expected: &str) -> bool {
+        matches!(expr, Expr::Variable { name, .. } if name.as_str() == expected)
+    }
+
+    #[test]
+    fn contextual_keywords_are_names_in_let_and_expressions() {
+        for word in CONTEXTUAL_KEYWORDS {
+            let stmts = parse(&format!("let {word} = 1\n{word} + 1\n"));
+            assert_eq!(let_name(&stmts[0]), (*word, false), "{}", word);
+            match &stmts[1] {
+                Stmt::Expr(Expr::Binary(left, Op::Add, _)) => {
+                    assert!(is_variable(left, word), "{}: {:?}", word, left)
+                }
+                stmt => panic!("{}: expected `{} + 1`, got {:?}", word, word, stmt),
+            }
+        }
+    }
+
+    #[test]
+    fn contextual_keywords_are_names_as_parameters_and_loop_variables() {
+        for word in CONTEXTUAL_KEYWORDS {
+            let stmts = parse(&format!(
+                "fn {word}({word}) {{\n    return {word}\n}}\nfor {word} in [1] {{\n}}\n"
+            ));
+            match &stmts[0] {
+                Stmt::Function(name, params, ..) => {
+                    assert_eq!(name.lexeme, *word);
+                    assert_eq!(params[0].name.as_str(), *word);
+                }
+                stmt => panic!("{}: expected a function, got {:?}", word, stmt),
+            }
+            match &stmts[1] {
+                Stmt::ForIn(name, None, ..) => assert_eq!(name.lexeme, *word),
+                stmt => panic!("{}: expected a for loop, got {:?}", word, stmt),
+            }
+        }
+    }
+
+    #[test]
+    fn contextual_keywords_index_and_call_as_names() {
+        let stmts = parse("in[0]\nmatch(1)\nstep - 1\nloop.x\n");
+        assert!(
+            matches!(&stmts[0], Stmt::Expr(Expr::Index(target, _)) if is_variable(target, "in"))
+        );
+        assert!(
+            matches!(&stmts[1], Stmt::Expr(Expr::Call { callee, .. }) if is_variable(callee, "match"))
+        );
+        assert!(
+            matches!(&stmts[2], Stmt::Expr(Expr::Binary(left, Op::Sub, _)) if is_variable(left, "step"))
+        );
+        assert!(
+            matches!(&stmts[3], Stmt::Expr(Expr::Field(target, _)) if is_variable(target, "loop"))
+        );
+    }
+
+    #[test]
+    fn contextual_keywords_are_keywords_where_the_grammar_expects_them() {
+        let stmts = parse(
+            "let mut x = 1
+for i in 0..10 step 2 {
+}
+loop {
+    break
+}
+do {
+} while false
+match x {
+    _ => 1
+}
+assert x > 0
+x mod 2
+x div 2
+",
+        );
+        assert_eq!(let_name(&stmts[0]), ("x", true));
+        assert!(matches!(&stmts[1], Stmt::For(name, _, _, Some(_), _) if name.lexeme == "i"));
+        assert!(matches!(&stmts[2], Stmt::While(Expr::Bool(true), _)));
+        assert!(matches!(&stmts[3], Stmt::DoWhile(..)));
+        assert!(matches!(&stmts[4], Stmt::Match(subject, _) if is_variable(subject, "x")));
+        assert!(matches!(&stmts[5], Stmt::Assert(..)));
+        assert!(matches!(&stmts[6], Stmt::Expr(Expr::Binary(_, Op::Mod, _))));
+        assert!(matches!(
+            &stmts[7],
+            Stmt::Expr(Expr::Binary(_, Op::FloorDiv, _))
+        ));
+    }
+
+    #[test]
+    fn keywords_and_names_mix_in_one_statement() {
+        let stmts = parse("let mut mut = 1\nfor in in [1] {\n}\nassert(assert)\nmod mod mod\n");
+        assert_eq!(let_name(&stmts[0]), ("mut", true));
+        assert!(matches!(&stmts[1], Stmt::ForIn(name, None, items, _)
+            if name.lexeme == "in" && matches!(items, Expr::List(_))));
+        assert!(matches!(&stmts[2], Stmt::Assert(condition, None, _)
+            if matches!(condition, Expr::Group(inner) if is_variable(inner, "assert"))));
+        assert!(
+            matches!(&stmts[3], Stmt::Expr(Expr::Binary(left, Op::Mod, right))
+            if is_variable(left, "mod") && is_variable(right, "mod"))
+        );
+    }
+
+    #[test]
+    fn elif_continues_an_if_after_its_block() {
+        for input in [
+            "if a {\n} elif b {\n} else {\n}\n",
+            "if a\n{\n}\nelif (b)\n{\n}\nelse\n{\n}\n",
+        ] {
+            let stmts = parse(input);
+            assert_eq!(stmts.len(), 1, "{}", input);
+            match &stmts[0] {
+                Stmt::If(condition, _, Some(other)) => {
+                    assert!(is_variable(condition, "a"));
+                    assert!(
+                        matches!(&**other, Stmt::If(_, _, Some(_))),
+                        "{}: {:?}",
+                        input,
+                        other
+                    );
+                }
+                stmt => panic!("{}: expected an if with an elif, got {:?}", input, stmt),
+            }
+        }
+    }
+
+    #[test]
+    fn elif_after_an_if_block_is_a_name_without_a_condition() {
+        let stmts = parse("if a {\n}\nelif = 2\n");
+        assert!(matches!(&stmts[0], Stmt::If(_, _, None)));
+        assert!(matches!(&stmts[1], Stmt::Assign(name, _) if name.lexeme == "elif"));
+    }
+
+    /// The value of the `let` at the start of `input`.
+    fn let_value(input: &str) -> Expr {
+        match parse(input).remove(0) {
+            Stmt::Let(_, value, ..) => value,
+            stmt => panic!("expected a let, got {:?}", stmt),
+        }
+    }
+
+    /// Splits `"before ${part} after"`, desugared into
+    /// `"before " + part + " after"`, into its three pieces.
+    fn interpolation(expr: &Expr) -> (&str, &Expr, &str) {
+        match expr {
+            Expr::Binary(left, Op::Add, after) => match (&**left, &**after) {
+                (Expr::Binary(before, Op::Add, part), Expr::String(after)) => match &**before {
+                    Expr::String(before) => (before, part, after),
+                    before => panic!("expected a string, got {:?}", before),
+                },
+                pair => panic!("expected a part and a string, got {:?}", pair),
+            },
+            expr => panic!("expected an interpolation, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn interpolation_nests_three_levels_deep() {
+        let expr = let_value(r#"let s = "a ${"b ${"c ${x} C"} B"} A""#);
+        let (before, part, after) = interpolation(&expr);
+        assert_eq!((before, after), ("a ", " A"));
+        let (before, part, after) = interpolation(part);
+        assert_eq!((before, after), ("b ", " B"));
+        let (before, part, after) = interpolation(part);
+        assert_eq!((before, after), ("c ", " C"));
+        assert!(is_variable(part, "x"), "{:?}", part);
+    }
+
+    #[test]
+    fn interpolation_holds_quotes_braces_and_lines() {
+        let expr = let_value("let s = \"<${f(\"}\", {\"k\": 1}[\"k\"], [\n    2,\n])}>\"\n");
+        let (before, part, after) = interpolation(&expr);
+        assert_eq!((before, after), ("<", ">"));
+        match part {
+            Expr::Call { callee, args, .. } => {
+                assert!(is_variable(callee, "f"));
+                assert!(matches!(&args[0], Expr::String(s) if s == "}"));
+                assert!(matches!(&args[1], Expr::Index(..)));
+                assert!(matches!(&args[2], Expr::List(items) if items.len() == 1));
+            }
+            part => panic!("expected a call, got {:?}", part),
+        }
+    }
+
+    #[test]
+    fn calls_and_indexes_nest_three_levels_deep() {
+        let mut expr = let_value("let x = f(g(h(xs[ys[zs[0]]])))\n");
+        for name in ["f", "g", "h"] {
+            expr = match expr {
+                Expr::Call { callee, args, .. }
+                    if is_variable(&callee, name) && args.len() == 1 =>
+                {
+                    args.into_vec().remove(0)
+                }
+                expr => panic!("expected a call of {}, got {:?}", name, expr),
+            };
+        }
+        for name in ["xs", "ys", "zs"] {
+            expr = match expr {
+                Expr::Index(target, index) if is_variable(&target, name) => *index,
+                expr => panic!("expected an index of {}, got {:?}", name, expr),
+            };
+        }
+        assert!(matches!(expr, Expr::Int(0)), "{:?}", expr);
+    }
+
+    #[test]
+    fn calls_apply_to_call_index_and_group_results() {
+        let mut expr = let_value("let x = make(1)(2)(3)\n");
+        for arg in [3, 2] {
+            expr = match expr {
+                Expr::Call { callee, args, .. } if matches!(args[..], [Expr::Int(n)] if n == arg) => {
+                    *callee
+                }
+                expr => panic!("expected a call with {}, got {:?}", arg, expr),
+            };
+        }
+        assert!(matches!(&expr, Expr::Call { callee, .. } if is_variable(callee, "make")));
+        assert!(matches!(
+            let_value("let x = fs[0](1)\n"),
+            Expr::Call { callee, .. } if matches!(*callee, Expr::Index(..))
+        ));
+        assert!(matches!(
+            let_value("let x = (f)(1)\n"),
+            Expr::Call { callee, .. } if matches!(*callee, Expr::Group(_))
+        ));
+    }
+
+    #[test]
+    fn a_call_on_the_next_line_is_a_new_statement() {
+        let stmts = parse("let x = f()\n(1)\n");
+        assert_eq!(stmts.len(), 2);
+        assert!(
+            matches!(&stmts[0], Stmt::Let(_, Expr::Call { callee, .. }, ..) if is_variable(callee, "f"))
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    env::{Env, Value},
+    error::{ErrorType, fatal},
+    expr::Expr,
+    stmt::{ControlFlow, Stmt},
+    symbol::Symbol,
+};
+
+/// Type names usable in a type pattern, as returned by `Value::type_name`.
+pub const TYPE_NAMES: &[&str] = &[
+    "number", "decimal", "bool", "string", "list", "tuple", "map", "function",
+];
+
+/// The left side of a `match` arm or of a destructuring `let`.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// A number, string, boolean or `nil` literal, compared with `==`.
+    Literal(Value),
+    /// `number n`, which matches values of that type and binds them to `n`
+    /// inside the arm. The binding is optional.
+    Type(&'static str, Option<Symbol>),
+    /// `_`, which matches anything.
+    Wildcard,
+    /// A name, which matches anything and binds it.
+    Bind(Symbol),
+    /// `(a, b)`, which matches a tuple of exactly that length.
+    Tuple(Vec<Pattern>),
+    /// `[a, b, rest...]`, which matches a list with at least as many
+    /// elements as patterns. Without a rest name the lengths must be equal.
+    List(Vec<Pattern>, Option<Symbol>),
+    /// `Point { x, y: 0 }`, which matches an instance of the struct or
+    /// class with that name whose listed fields match.
+    Struct(Symbol, Vec<(Symbol, Pattern)>),
+}
+
+impl Pattern {
+    pub fn matches(&self, value: &Value) -> bool {
+        self.bind(value, &mut Vec::new())
+    }
+
+    /// Matches `value`, pushing the names it binds onto `bindings`. On a
+    /// mismatch `bindings` may hold some of the names and should be dropped.
+    pub fn bind(&self, value: &Value, bindings: &mut Vec<(Symbol, Value)>) -> bool {
+        match self {
+            Pattern::Literal(literal) => literal.deep_eq(value),
+            Pattern::Type(name, binding) => {
+                if value.type_name() != *name {
+                    return false;
+                }
+                if let Some(binding) = binding {
+                    bindings.push((*binding, value.clone()));
+                }
+                true
+            }
+            Pattern::Wildcard => true,
+            Pattern::Bind(name) => {
+                bindings.push((*name, value.clone()));
+                true
+            }
+            Pattern::Tuple(patterns) => {
+                let Value::Tuple(items) = value else {
+                    return false;
+                };
+                items.len() == patterns.len()
+                    && patterns
+                        .iter()
+                        .zip(items.iter())
+                        .all(|(pattern, item)| pattern.bind(item, bindings))
+            }
+            Pattern::List(patterns, rest) => {
+                let Value::List(items) = value else {
+                    return false;
+                };
+                let items = items.borrow();
+                let fits = match rest {
+                    Some(_) => items.len() >= patterns.len(),
+                    None => items.len() == patterns.len(),
+                };
+                if !fits
+                    || !patterns
+                        .iter()
+                        .zip(items.iter())
+                        .all(|(pattern, item)| pattern.bind(item, bindings))
+                {
+                    return false;
+                }
+                if let Some(rest) = rest {
+                    let tail = items[patterns.len()..].to_vec();
+                    bindings.push((*rest, Value::List(Rc::new(RefCell::new(tail)))));
+                }
+                true
+            }
+            Pattern::Struct(name, fields) => {
+                let Value::Struct(instance) = value else {
+                    return false;
+                };
+                let instance = instance.borrow();
+                if instance.def.name != *name {
+                    return false;
+                }
+                fields.iter().all(|(field, pattern)| {
+                    let Some(i) = instance.def.field_index(*field) else {
+                        fatal(
+                            ErrorType::RuntimeError,
+                            format!("Struct `{}` has no field `{}`", name, field),
+                        );
+                    };
+                    pattern.bind(&instance.fields[i], bindings)
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Literal(Value::String(s)) => write!(f, "{:?}", s),
+            Pattern::Literal(value) => write!(f, "{}", value),
+            Pattern::Type(name, Some(binding)) => write!(f, "{} {}", name, binding),
+            Pattern::Type(name, None) => write!(f, "{}", name),
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Bind(name) => write!(f, "{}", name),
+            Pattern::Tuple(patterns) => {
+                write!(f, "(")?;
+                for (i, pattern) in patterns.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", pattern)?;
+                }
+                if patterns.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Pattern::List(patterns, rest) => {
+                write!(f, "[")?;
+                for (i, pattern) in patterns.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", pattern)?;
+                }
+                if let Some(rest) = rest {
+                    if !patterns.is_empty() {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}...", rest)?;
+                }
+                write!(f, "]")
+            }
+            Pattern::Struct(name, fields) => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, pattern)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match pattern {
+                        Pattern::Bind(binding) if binding == field => write!(f, "{}", field)?,
+                        _ => write!(f, "{}: {}", field, pattern)?,
+                    }
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+/// `pattern | pattern if guard => body`. The body is a block or a single
+/// expression.
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub patterns: Vec<Pattern>,
+    /// Checked after a pattern matches, with its names bound; when false
+    /// the next arm is tried.
+    pub guard: Option<Expr>,
+    pub body: Stmt,
+}
+
+impl MatchArm {
+    /// Runs the first arm with a pattern matching `value`, or returns `None`
+    /// if no arm matches. Arms never fall through to the next one.
+    pub fn eval(
+        arms: &[MatchArm],
+        value: Value,
+        env: &mut Rc<RefCell<Env>>,
+    ) -> Option<ControlFlow> {
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !arm.patterns.iter().any(|p| {
+                bindings.clear();
+                p.bind(&value, &mut bindings)
+            }) {
+                continue;
+            }
+            let mut arm_env = if bindings.is_empty() {
+                env.clone()
+            } else {
+                let arm_env = Env::child_env(env.clone());
+                for (name, value) in bindings {
+                    arm_env.borrow_mut().define(name, value);
+                }
+                arm_env
+            };
+            if let Some(guard) = &arm.guard
+                && !guard.condition_eval(&mut arm_env)
+            {
+                continue;
+            }
+            return Some(arm.body.eval(&mut arm_env));
+        }
+        None
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    rc::Rc,
+};
+
+use crate::{
+    env::Value,
+    error::{ErrorType, fatal},
+    json,
+};
+
+/// Version of the protocol above. A plugin must announce the same number.
+pub const PROTOCOL: i64 = 1;
+
+/// Native builtins provided by a separate program, so third parties can
+/// ship extensions without forking riku or linking against it.
+///
+/// riku starts the plugin and talks to it over its standard input and
+/// output, one JSON document per line:
+///
+/// 1. The plugin announces itself: `{"riku_plugin": 1, "functions": ["sql"]}`.
+/// 2. For each call riku writes `{"call": "sql", "args": [...]}` and the
+///    plugin answers `{"ok": value}` or `{"error": "message"}`.
+///
+/// The plugin should exit when its standard input is closed.
+#[derive(Debug)]
+pub struct Plugin {
+    path: String,
+    process: RefCell<Process>,
+}
+
+#[derive(Debug)]
+struct Process {
+    // Kept so the child is not detached while riku runs.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Plugin {
+    /// Starts the plugin at `path` and returns it with the names of the
+    /// functions it provides.
+    pub fn load(path: &str) -> Result<(Rc<Plugin>, Vec<String>), String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("unable to start plugin `{}`: {}", path, e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let plugin = Plugin {
+            path: path.to_string(),
+            process: RefCell::new(Process {
+                _child: child,
+                stdin,
+                stdout,
+            }),
+        };
+
+        let hello = plugin.receive()?;
+        let field = |key: &str| match &hello {
+            Value::Map(map) => map.borrow().get(key).cloned(),
+            _ => None,
+        };
+        match field("riku_plugin") {
+            Some(Value::Int(PROTOCOL)) => {}
+            Some(version) => {
+                return Err(format!(
+                    "plugin `{}` speaks protocol {}, riku speaks {}",
+                    path, version, PROTOCOL
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "`{}` did not announce itself as a riku plugin",
+                    path
+                ));
+            }
+        }
+        let Some(Value::List(names)) = field("functions") else {
+            return Err(format!("plugin `{}` did not list its functions", path));
+        };
+        let names = names
+            .borrow()
+            .iter()
+            .map(|name| match name {
+                Value::String(name) => Ok(name.clone()),
+                _ => Err(format!(
+                    "plugin `{}` listed a function name that is not a string",
+                    path
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((Rc::new(plugin), names))
+    }
+
+    /// Calls a plugin function. Errors reported by the plugin, and plugins
+    /// that crash or answer nonsense, stop the script.
+    pub fn call(&self, name: &str, args: Vec<Value>) -> Value {
+        let fail = |message: String| -> ! {
+            fatal(
+                ErrorType::RuntimeError,
+                format!("plugin `{}`: {}", self.path, message),
+            )
+        };
+        let mut request = BTreeMap::new();
+        request.insert("call".to_string(), Value::String(name.to_string()));
+        request.insert("args".to_string(), Value::List(Rc::new(RefCell::new(args))));
+        let request = Value::Map(Rc::new(RefCell::new(request)));
+        self.send(&request).unwrap_or_else(|e| fail(e));
+        let Value::Map(response) = self.receive().unwrap_or_else(|e| fail(e)) else {
+            fail("expected an object in response".to_string());
+        };
+        let response = response.borrow();
+        if let Some(message) = response.get("error") {
+            fail(format!("{}: {}", name, message));
+        }
+        response
+            .get("ok")
+            .cloned()
+            .unwrap_or_else(|| fail("response has neither `ok` nor `error`".to_string()))
+    }
+
+    fn send(&self, message: &Value) -> Result<(), String> {
+        let line = json::encode(message)?;
+        let mut process = self.process.borrow_mut();
+        writeln!(process.stdin, "{}", line)
+            .and_then(|_| process.stdin.flush())
+            .map_err(|e| format!("unable to write to plugin: {}", e))
+    }
+
+    fn receive(&self) -> Result<Value, String> {
+        let mut line = String::new();
+        let read = self
+            .process
+            .borrow_mut()
+            .stdout
+            .read_line(&mut line)
+            .map_err(|e| format!("unable to read from plugin: {}", e))?;
+        if read == 0 {
+            return Err(format!("plugin `{}` exited", self.path));
+        }
+        json::decode(&line).map_err(|e| format!("invalid message from plugin: {}", e))
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use crate::formatter::{BraceStyle, FormatConfig};
+
+/// The name of the per-project settings file.
+pub const FILE_NAME: &str = "riku.toml";
+
+/// Settings read from `riku.toml`. Only the `[fmt]` table is understood so
+/// far:
+///
+/// ```toml
+/// [fmt]
+/// indent = 4
+/// max_width = 100
+/// brace_style = "same-line"  # or "next-line"
+/// ```
+#[derive(Debug, Default)]
+pub struct Project {
+    pub fmt: FormatConfig,
+}
+
+/// Finds `riku.toml` in the current directory or the nearest parent that
+/// has one, and reads it. Gives the defaults if there is none.
+pub fn load() -> Result<Project, String> {
+    let Some(path) = std::env::current_dir().ok().and_then(|dir| find(&dir)) else {
+        return Ok(Project::default());
+    };
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Unable to read `{}`: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{}:{}", path.display(), e))
+}
+
+fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Reads the small part of TOML that the settings need: `[table]` headers,
+/// `key = value` lines with integer or double-quoted string values, and
+/// `#` comments. Errors start with the line number.
+pub fn parse(text: &str) -> Result<Project, String> {
+    let mut project = Project::default();
+    let mut table = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{}: {}", i + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            table = name.trim().to_string();
+            if table != "fmt" {
+                return Err(error(format!("unknown table `[{}]`", table)));
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error(format!("expected `key = value`, found `{}`", line)));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if table.is_empty() {
+            return Err(error(format!(
+                "`{}` must be inside a table such as `[fmt]`",
+                key
+            )));
+        }
+        let fmt = &mut project.fmt;
+        match key {
+            "indent" => fmt.indent = integer(value, 1..=16).map_err(error)?,
+            "max_width" => fmt.max_width = integer(value, 20..=1000).map_err(error)?,
+            "brace_style" => {
+                fmt.brace_style = match string(value).map_err(error)? {
+                    "same-line" => BraceStyle::SameLine,
+                    "next-line" => BraceStyle::NextLine,
+                    other => {
+                        return Err(error(format!(
+                            "`brace_style` is \"same-line\" or \"next-line\", not \"{}\"",
+                            other
+                        )));
+                    }
+                }
+            }
+            _ => return Err(error(format!("unknown key `{}` in `[{}]`", key, table))),
+        }
+    }
+    Ok(project)
+}
+
+/// The line without a `#` comment, leaving any `#` inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn integer(value: &str, range: std::ops::RangeInclusive<usize>) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if range.contains(&n) => Ok(n),
+        _ => Err(format!(
+            "expected a whole number from {} to {}, found `{}`",
+            range.start(),
+            range.end(),
+            value
+        )),
+    }
+}
+
+fn string(value: &str) -> Result<&str, String> {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .filter(|v| !v.contains('"'))
+        .ok_or_else(|| format!("expected a quoted string, found `{}`", value))
+}
@@ -0,0 +1,221 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    Interpreter, config,
+    env::Value,
+    error::{self, RikuError, exit},
+    json,
+    output::{self, Stream},
+    std_fn::Capability,
+};
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// `riku serve --jsonrpc`: runs notebook cells sent as JSON-RPC 2.0
+/// requests, one per line on stdin, and answers each on its own line of
+/// stdout. Every cell runs in the same session.
+///
+/// `{"jsonrpc": "2.0", "id": 1, "method": "execute", "params": {"code": "1 + 2"}}`
+/// is answered with the text the cell printed, the value of each of its
+/// top-level statements and the error that stopped it, if any:
+///
+/// `{"id": 1, "jsonrpc": "2.0", "result": {"diagnostics": [], "ok": true,
+/// "results": ["3"], "stderr": "", "stdout": ""}}`
+///
+/// `shutdown` ends the server. Scripts cannot read stdin, which carries the
+/// requests, and `exit()` only stops the cell it is in.
+pub fn serve(mut interpreter: Interpreter) {
+    config::grant_only(&[
+        Capability::Stdout,
+        Capability::Stderr,
+        Capability::Files,
+        Capability::Plugins,
+    ]);
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Unable to read input: {}", e);
+                std::process::exit(exit::IOERR);
+            }
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, reply, done) = match json::decode(&line) {
+            Ok(Value::Map(request)) => {
+                let request = request.borrow();
+                let id = request.get("id").cloned();
+                let method = request.get("method");
+                let (reply, done) = match method {
+                    Some(Value::String(m)) if m == "execute" => {
+                        (execute(&mut interpreter, request.get("params")), false)
+                    }
+                    Some(Value::String(m)) if m == "shutdown" => (Ok(Value::Nil), true),
+                    Some(Value::String(m)) => (
+                        Err((METHOD_NOT_FOUND, format!("Unknown method `{}`", m))),
+                        false,
+                    ),
+                    _ => (
+                        Err((INVALID_REQUEST, "Expected a `method` string".to_string())),
+                        false,
+                    ),
+                };
+                (id, reply, done)
+            }
+            Ok(_) => (
+                None,
+                Err((INVALID_REQUEST, "Expected a request object".to_string())),
+                false,
+            ),
+            Err(e) => (None, Err((PARSE_ERROR, e)), false),
+        };
+        // Requests without an id are notifications, which get no answer
+        // unless they could not be read at all.
+        if id.is_some() || reply.is_err() {
+            respond(id, reply);
+        }
+        if done {
+            break;
+        }
+    }
+}
+
+/// Runs the `code` of an `execute` request with the script's output
+/// captured.
+fn execute(interpreter: &mut Interpreter, params: Option<&Value>) -> Result<Value, (i64, String)> {
+    let code = match params {
+        Some(Value::Map(params)) => params.borrow().get("code").cloned(),
+        _ => None,
+    };
+    let Some(Value::String(code)) = code else {
+        return Err((
+            INVALID_PARAMS,
+            "`execute` expects `params` with a `code` string".to_string(),
+        ));
+    };
+    let stdout = Capture::default();
+    let stderr = Capture::default();
+    let outer_stdout = output::set_sink(Stream::Stdout, Some(Box::new(stdout.clone())));
+    let outer_stderr = output::set_sink(Stream::Stderr, Some(Box::new(stderr.clone())));
+    let outcome = error::catch_exit(|| interpreter.eval_all(&code));
+    output::set_sink(Stream::Stdout, outer_stdout);
+    output::set_sink(Stream::Stderr, outer_stderr);
+
+    let (results, diagnostics) = match outcome {
+        Ok(Ok(values)) => (values, Vec::new()),
+        Ok(Err(error)) => (Vec::new(), vec![diagnostic(&error)]),
+        Err(status) => (Vec::new(), vec![exit_diagnostic(status)]),
+    };
+    let results = results
+        .into_iter()
+        .map(|value| match value {
+            Value::Nil => Value::Nil,
+            value => Value::String(value.to_string()),
+        })
+        .collect();
+    Ok(object([
+        ("ok", Value::Bool(diagnostics.is_empty())),
+        ("stdout", Value::String(stdout.text())),
+        ("stderr", Value::String(stderr.text())),
+        ("results", list(results)),
+        ("diagnostics", list(diagnostics)),
+    ]))
+}
+
+fn diagnostic(error: &RikuError) -> Value {
+    object([
+        ("kind", Value::String(format!("{:?}", error.kind))),
+        (
+            "line",
+            error.line.map_or(Value::Nil, |l| Value::Int(l as i64)),
+        ),
+        ("message", Value::String(error.message.clone())),
+    ])
+}
+
+/// `exit(status)` in a cell, which stops the cell but not the server.
+fn exit_diagnostic(status: i32) -> Value {
+    object([
+        ("kind", Value::String("Exit".to_string())),
+        ("line", Value::Nil),
+        (
+            "message",
+            Value::String(format!("exit({}) ended the cell", status)),
+        ),
+        ("status", Value::Int(status as i64)),
+    ])
+}
+
+fn respond(id: Option<Value>, reply: Result<Value, (i64, String)>) {
+    let (key, body) = match reply {
+        Ok(result) => ("result", result),
+        Err((code, message)) => (
+            "error",
+            object([
+                ("code", Value::Int(code)),
+                ("message", Value::String(message)),
+            ]),
+        ),
+    };
+    let response = object([
+        ("jsonrpc", Value::String("2.0".to_string())),
+        ("id", id.unwrap_or(Value::Nil)),
+        (key, body),
+    ]);
+    let line = json::encode(&response).unwrap_or_else(|e| {
+        eprintln!("Unable to encode a response: {}", e);
+        std::process::exit(exit::SOFTWARE);
+    });
+    let mut stdout = io::stdout().lock();
+    if let Err(e) = writeln!(stdout, "{}", line).and_then(|_| stdout.flush()) {
+        eprintln!("Unable to write a response: {}", e);
+        std::process::exit(exit::IOERR);
+    }
+}
+
+fn object<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    let map = entries
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect::<BTreeMap<_, _>>();
+    Value::Map(Rc::new(RefCell::new(map)))
+}
+
+fn list(items: Vec<Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(items)))
+}
+
+/// A sink that keeps what a cell writes, for its response.
+#[derive(Clone, Default)]
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,8 +1,7 @@
-use std::process;
-
 use crate::{
-    error::{ErrorType, line_error},
-    token::{Token, TokenType},
+    config,
+    error::{ErrorType, excerpt, snippet_fatal},
+    token::{Span, Token, TokenType},
 };
 
 #[derive(Debug)]
@@ -11,15 +10,24 @@ pub struct Source {
     position: usize,
     tokens: Vec<Token>,
     line: usize,
+    start: usize,
+    /// Byte offset where each line of `input` starts, so errors can find
+    /// their column without rescanning a long line.
+    line_starts: Vec<usize>,
 }
 
 impl Source {
     pub fn new(input: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
         Source {
             input,
+            line_starts,
             position: 0,
             tokens: Vec::new(),
             line: 1,
+            start: 0,
         }
     }
 
@@ -27,22 +35,121 @@ impl Source {
         &self.tokens
     }
 
+    /// The source text, which token spans index into.
+    pub fn text(&self) -> &str {
+        &self.input
+    }
+
+    /// The line and column of byte `offset`, both counting from 1. Columns
+    /// count characters, so a tab or an `é` is one column. An offset past
+    /// the end is the position after the last character.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let mut offset = offset.min(self.input.len());
+        while !self.input.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let index = self.line_index(offset);
+        let column = self.input[self.line_starts[index]..offset].chars().count() + 1;
+        (index + 1, column)
+    }
+
+    /// The text of `line`, counting from 1, without its line break, or
+    /// `None` if there is no such line.
+    pub fn line_text(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.input.len(), |next| next - 1);
+        Some(self.input[start..end].trim_end_matches('\r'))
+    }
+
     pub fn tokenize(&mut self) {
         while let Some(c) = self.peek() {
             // println!("{}", c);
+            self.start = self.position;
             match c {
-                '+' => self.add_token("+", TokenType::Plus),
-                '-' => self.add_token("-", TokenType::Minus),
-                '*' => self.add_token("*", TokenType::Star),
-                '/' => self.add_token("/", TokenType::Slash),
-                '%' => self.add_token("%", TokenType::Modulo),
+                '+' => {
+                    if self.peek_next() == Some('=') {
+                        self.advance();
+                        self.add_token("+=", TokenType::PlusEqual);
+                    } else if self.peek_next() == Some('+') {
+                        self.advance();
+                        self.add_token("++", TokenType::PlusPlus);
+                    } else {
+                        self.add_token("+", TokenType::Plus);
+                    }
+                }
+                '-' => {
+                    if self.peek_next() == Some('=') {
+                        self.advance();
+                        self.add_token("-=", TokenType::MinusEqual);
+                    } else if self.peek_next() == Some('-') {
+                        self.advance();
+                        self.add_token("--", TokenType::MinusMinus);
+                    } else if self.peek_next() == Some('>') {
+                        self.advance();
+                        self.add_token("->", TokenType::Arrow);
+                    } else {
+                        self.add_token("-", TokenType::Minus);
+                    }
+                }
+                '*' => {
+                    if self.peek_next() == Some('=') {
+                        self.advance();
+                        self.add_token("*=", TokenType::StarEqual);
+                    } else {
+                        self.add_token("*", TokenType::Star);
+                    }
+                }
+                '/' => {
+                    if self.peek_next() == Some('/') {
+                        self.comment();
+                    } else if self.peek_next() == Some('=') {
+                        self.advance();
+                        self.add_token("/=", TokenType::SlashEqual);
+                    } else {
+                        self.add_token("/", TokenType::Slash);
+                    }
+                }
+                '%' => {
+                    if self.peek_next() == Some('=') {
+                        self.advance();
+                        self.add_token("%=", TokenType::ModuloEqual);
+                    } else {
+                        self.add_token("%", TokenType::Modulo);
+                    }
+                }
                 ';' => self.add_token(";", TokenType::EOL),
                 ',' => self.add_token(",", TokenType::Comma),
+                ':' => self.add_token(":", TokenType::Colon),
+                '?' => {
+                    if self.peek_next() == Some('.') {
+                        self.advance();
+                        self.add_token("?.", TokenType::QuestionDot);
+                    } else {
+                        self.add_token("?", TokenType::Question);
+                    }
+                }
+                '.' => {
+                    if self.input[self.position..].starts_with("...") {
+                        self.advance();
+                        self.advance();
+                        self.add_token("...", TokenType::Ellipsis);
+                    } else if self.peek_next() == Some('.') {
+                        self.advance();
+                        self.add_token("..", TokenType::DotDot);
+                    } else {
+                        self.add_token(".", TokenType::Dot);
+                    }
+                }
                 ' ' => self.eat_char(&[' ']),
                 '(' => self.add_token("(", TokenType::LParen),
                 ')' => self.add_token(")", TokenType::RParen),
                 '{' => self.add_token("{", TokenType::LBrace),
                 '}' => self.add_token("}", TokenType::RBrace),
+                '[' => self.add_token("[", TokenType::LBracket),
+                ']' => self.add_token("]", TokenType::RBracket),
                 '&' => self.add_token("&", TokenType::Ampersand),
                 '|' => self.add_token("|", TokenType::Pipe),
                 '\n' => {
@@ -72,6 +179,9 @@ impl Source {
                     if self.peek_next() == Some('=') {
                         self.advance();
                         self.add_token("==", TokenType::EqualEqual);
+                    } else if self.peek_next() == Some('>') {
+                        self.advance();
+                        self.add_token("=>", TokenType::FatArrow);
                     } else {
                         self.add_token("=", TokenType::Equal);
                     }
@@ -85,37 +195,195 @@ impl Source {
                     }
                 }
                 '0'..='9' => self.numbers(),
-                '"' => self.string(),
-                _ if c.is_alphabetic() => self.identifier(),
+                '"' => self.string(false),
+                'r' if self.peek_next() == Some('"') => {
+                    self.advance();
+                    self.string(true);
+                }
+                '^' if config::math() => self.add_token("^", TokenType::Caret),
+                _ if c.is_alphabetic() || c == '_' => self.identifier(),
                 _ => self.syntaxerror(),
             }
         }
-        self.add_token("", TokenType::EOF);
+        self.start = self.position;
+        self.push_token("", TokenType::EOF);
     }
 
-    fn string(&mut self) {
-        self.advance();
-        let start = self.position;
+    fn comment(&mut self) {
         while let Some(c) = self.peek() {
-            if c == '"' {
+            if c == '\n' {
                 break;
-            } else if c == '\n' {
-                line_error(
-                    ErrorType::SyntaxError,
-                    self.line,
-                    "Unterminated string".to_string(),
-                );
-                process::exit(1);
             }
             self.advance();
         }
-        let lexeme = &self.input[start..self.position];
-        let token = Token::new(lexeme, self.line, TokenType::String);
+    }
+
+    fn push_token(&mut self, lexeme: &str, token_type: TokenType) {
+        let span = Span {
+            start: self.start,
+            end: self.position,
+        };
+        let token = Token::new(lexeme, self.line, token_type, span);
         self.tokens.push(token);
-        self.advance();
+    }
+
+    /// A string with `${...}` parts is emitted as `InterpStart`, the tokens
+    /// of each embedded expression separated by `InterpMid`, and `InterpEnd`.
+    /// `"""` strings may span lines; raw strings keep `\` and `${` as
+    /// written.
+    fn string(&mut self, raw: bool) {
+        let heredoc = self.input[self.position..].starts_with("\"\"\"");
+        let quote = if heredoc { "\"\"\"" } else { "\"" };
+        self.position += quote.len();
+        if heredoc {
+            // The line break right after the opening quotes is not part of
+            // the text.
+            if self.input[self.position..].starts_with("\r\n") {
+                self.position += 1;
+            }
+            if self.peek() == Some('\n') {
+                self.advance();
+                self.line += 1;
+            }
+        }
+        let mut text = String::new();
+        let mut interpolated = false;
+        loop {
+            let Some(c) = self.peek() else {
+                self.fatal_at(self.start, "Unterminated string".to_string());
+            };
+            if self.input[self.position..].starts_with(quote) {
+                break;
+            }
+            match c {
+                '\n' if !heredoc => {
+                    self.fatal_at(self.start, "Unterminated string".to_string());
+                }
+                '\n' => {
+                    self.advance();
+                    self.line += 1;
+                    text.push(c);
+                }
+                // Line breaks are `\n` whatever the file uses.
+                '\r' if heredoc && self.peek_next() == Some('\n') => {
+                    self.advance();
+                }
+                '\\' if !raw => {
+                    self.advance();
+                    text.push(self.escape());
+                }
+                '$' if !raw && self.peek_next() == Some('{') => {
+                    self.advance();
+                    self.advance();
+                    let token_type = if interpolated {
+                        TokenType::InterpMid
+                    } else {
+                        TokenType::InterpStart
+                    };
+                    self.push_token(&text, token_type);
+                    text.clear();
+                    interpolated = true;
+                    self.interpolation();
+                }
+                _ => {
+                    self.advance();
+                    text.push(c);
+                }
+            }
+        }
+        self.position += quote.len();
+        let token_type = if interpolated {
+            TokenType::InterpEnd
+        } else {
+            TokenType::String
+        };
+        self.push_token(&text, token_type);
         self.eat_char(&[' ']);
     }
 
+    /// Reads the character after a `\` in a string.
+    fn escape(&mut self) -> char {
+        match self.advance() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"' | '$')) => c,
+            Some('u') => self.unicode_escape(),
+            Some(c) => self.fatal_at(
+                self.position - c.len_utf8() - 1,
+                format!("Unknown escape `\\{}` in string", c),
+            ),
+            None => self.fatal_at(self.start, "Unterminated string".to_string()),
+        }
+    }
+
+    /// Reads the `{1F600}` after `\u`: one to six hex digits naming a
+    /// Unicode scalar value.
+    fn unicode_escape(&mut self) -> char {
+        let start = self.position - 2;
+        if self.peek() != Some('{') {
+            self.fatal_at(
+                start,
+                "Expected `{` after `\\u`, as in `\\u{1F600}`".to_string(),
+            );
+        }
+        self.advance();
+        let digits_start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.advance();
+        }
+        let digits = self.input[digits_start..self.position].to_string();
+        if self.peek() != Some('}') || digits.is_empty() || digits.len() > 6 {
+            self.fatal_at(
+                start,
+                "Expected one to six hex digits and `}` in `\\u{...}`".to_string(),
+            );
+        }
+        self.advance();
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .unwrap_or_else(|| {
+                self.fatal_at(
+                    start,
+                    format!("`\\u{{{}}}` is not a Unicode character", digits),
+                )
+            })
+    }
+
+    /// Lexes the expression inside `${...}` up to the matching `}`, which is
+    /// consumed and becomes the start of the next string segment.
+    fn interpolation(&mut self) {
+        let inner_start = self.position;
+        let Some(close) = interpolation_end(&self.input, inner_start) else {
+            self.fatal_at(inner_start - 2, "Unterminated `${` in string".to_string());
+        };
+        self.position = close;
+        let inner_end = inner_start
+            + spec_colon(&self.input[inner_start..self.position])
+                .unwrap_or(self.position - inner_start);
+        let mut inner = Source::new(self.input[inner_start..inner_end].to_string());
+        inner.line = self.line;
+        inner.tokenize();
+        for mut token in inner.tokens {
+            if token.token_type == TokenType::EOF {
+                continue;
+            }
+            token.span.start += inner_start;
+            token.span.end += inner_start;
+            self.tokens.push(token);
+        }
+        self.line += self.input[inner_start..close].matches('\n').count();
+        if inner_end < self.position {
+            self.start = inner_end;
+            let spec = self.input[inner_end + 1..self.position].to_string();
+            self.push_token(&spec, TokenType::FormatSpec);
+        }
+        self.start = self.position;
+        self.advance();
+    }
+
     fn identifier(&mut self) {
         let start = self.position;
         while let Some(c) = self.peek() {
@@ -125,27 +393,46 @@ impl Source {
                 break;
             }
         }
-        let lexeme = &self.input[start..self.position];
-        let token_type = match lexeme {
+        let lexeme = self.input[start..self.position].to_string();
+        let token_type = match lexeme.as_str() {
             "true" => TokenType::True,
             "false" => TokenType::False,
+            "nil" => TokenType::Nil,
             "let" => TokenType::Let,
+            "const" => TokenType::Const,
             "if" => TokenType::If,
             "else" => TokenType::Else,
             "while" => TokenType::While,
+            "for" => TokenType::For,
             "break" => TokenType::Break,
             "continue" => TokenType::Continue,
             "fn" => TokenType::Fn,
             "return" => TokenType::Return,
+            "struct" => TokenType::Struct,
+            "class" => TokenType::Class,
             _ => TokenType::Ident,
         };
-        let token = Token::new(lexeme.trim(), self.line, token_type);
-        self.tokens.push(token);
+        self.push_token(lexeme.trim(), token_type);
         self.eat_char(&[' ']);
     }
 
+    /// Stops with a syntax error, showing the source around byte `offset`.
+    fn fatal_at(&self, offset: usize, message: String) -> ! {
+        let index = self.line_index(offset);
+        // `self.line` is the line of the current position, which may be
+        // past the offset in a multi-line string.
+        let line = self.line - (self.line_index(self.position) - index);
+        let text = self.line_text(index + 1).unwrap_or_default();
+        let column = offset - self.line_starts[index];
+        snippet_fatal(ErrorType::SyntaxError, line, text, column, message)
+    }
+
+    /// Which line of `input`, counting from 0, holds byte `offset`.
+    fn line_index(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
     fn syntaxerror(&self) {
-        let error = ErrorType::SyntaxError;
         let mut syntax = String::new();
         let mut pos = self.position;
         while let Some(c) = self.input[pos..].chars().next() {
@@ -155,9 +442,10 @@ impl Source {
             syntax.push(c);
             pos += c.len_utf8();
         }
-        println!("{:?}", syntax);
-        line_error(error, self.line, format!("Unexpected Syntax `{}`", syntax));
-        process::exit(1);
+        self.fatal_at(
+            self.position,
+            format!("Unexpected Syntax `{}`", excerpt(&syntax)),
+        );
     }
 
     pub fn peek(&self) -> Option<char> {
@@ -170,7 +458,7 @@ impl Source {
 
     pub fn peek_next(&self) -> Option<char> {
         if self.position < self.input.len() {
-            self.input[self.position..].chars().skip(1).next()
+            self.input[self.position..].chars().nth(1)
         } else {
             None
         }
@@ -200,25 +488,309 @@ impl Source {
     }
 
     pub fn add_token(&mut self, s: &str, token_type: TokenType) {
-        let lexeme = s.trim();
-        let token = Token::new(lexeme, self.line, token_type);
-        self.tokens.push(token);
         self.advance();
+        self.push_token(s.trim(), token_type);
         self.eat_char(&[' ']);
     }
 
     pub fn numbers(&mut self) {
         let start = self.position;
+        if let Some(radix) = self.input[start..].strip_prefix('0').and_then(radix_prefix) {
+            self.radix_number(radix);
+            return;
+        }
         while let Some(c) = self.peek() {
-            if c.is_digit(10) || c == '.' {
+            // A dot followed by anything but a digit is `..` or a method
+            // call, as in `2.5.floor()`.
+            if c == '.' && !self.peek_next().is_some_and(|n| n.is_ascii_digit()) {
+                break;
+            }
+            if c.is_ascii_digit() || c == '.' {
                 self.advance();
+            } else if c == '_' {
+                self.separator(10);
+            } else if c == 'e' || c == 'E' {
+                self.exponent(start);
+                break;
             } else {
                 break;
             }
         }
-        let lexeme = &self.input[start..self.position];
-        let token = Token::new(lexeme.trim(), self.line, TokenType::Number);
-        self.tokens.push(token);
+        let lexeme = self.input[start..self.position].replace('_', "");
+        self.push_token(lexeme.trim(), TokenType::Number);
+        self.eat_char(&[' ']);
+    }
+
+    /// A `_` between digits, as in `1_000_000`, which only makes the
+    /// number easier to read.
+    fn separator(&mut self, radix: u32) {
+        if !self.peek_next().is_some_and(|n| n.is_digit(radix)) {
+            self.fatal_at(
+                self.position,
+                "A `_` in a number must be followed by a digit".to_string(),
+            );
+        }
+        self.advance();
+    }
+
+    /// The `e9` or `E-3` that ends `1.5e9` or `2E-3`, for the number that
+    /// starts at byte `start`.
+    fn exponent(&mut self, start: usize) {
+        let e = self.position;
+        self.advance();
+        if matches!(self.peek(), Some('+' | '-')) {
+            self.advance();
+        }
+        if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.fatal_at(
+                e,
+                format!(
+                    "Expected digits in the exponent of `{}`",
+                    &self.input[start..self.position]
+                ),
+            );
+        }
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() {
+                self.advance();
+            } else if c == '_' {
+                self.separator(10);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// `0xFF`, `0b1010` or `0o755`. The token holds the value in decimal,
+    /// so the parser reads it like any other integer.
+    fn radix_number(&mut self, (radix, name): (u32, &str)) {
+        let start = self.position;
+        self.position += 2;
+        let digits_start = self.position;
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            if c == '_' {
+                self.separator(radix);
+                continue;
+            }
+            if !c.is_digit(radix) {
+                self.fatal_at(
+                    self.position,
+                    format!("Invalid digit `{}` in {} literal", c, name),
+                );
+            }
+            self.advance();
+        }
+        let digits = self.input[digits_start..self.position].replace('_', "");
+        if digits.is_empty() {
+            self.fatal_at(
+                start,
+                format!(
+                    "Expected digits after `{}`",
+                    &self.input[start..digits_start]
+                ),
+            );
+        }
+        let Ok(value) = i64::from_str_radix(&digits, radix) else {
+            self.fatal_at(
+                start,
+                format!(
+                    "`{}` does not fit in a 64-bit integer",
+                    &self.input[start..self.position]
+                ),
+            );
+        };
+        self.push_token(&value.to_string(), TokenType::Number);
         self.eat_char(&[' ']);
     }
 }
+
+/// The radix and name of a literal whose text after the leading `0` is
+/// `rest`, if it has a prefix such as `x`.
+fn radix_prefix(rest: &str) -> Option<(u32, &'static str)> {
+    match rest.chars().next()? {
+        'x' | 'X' => Some((16, "hex")),
+        'b' | 'B' => Some((2, "binary")),
+        'o' | 'O' => Some((8, "octal")),
+        _ => None,
+    }
+}
+
+/// Finds the `:` that starts a format spec in the text of `${...}`. Colons
+/// inside brackets, strings and ternaries belong to the expression.
+fn spec_colon(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut ternaries = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = string_end(text, i)?;
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b'?' if bytes.get(i + 1) != Some(&b'.') => ternaries += 1,
+            b':' if depth == 0 && ternaries == 0 => return Some(i),
+            b':' if depth == 0 => ternaries -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Finds the `}` that closes a `${` whose expression starts at `start`.
+/// Braces and strings inside the expression, including strings with their
+/// own `${...}`, are skipped, and the expression may span lines.
+fn interpolation_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = string_end(text, i)?;
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The index just past the string literal whose opening quote is at
+/// `start`, or `None` if it is unterminated on its line.
+fn string_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' => return Some(i + 1),
+            b'$' if bytes.get(i + 1) == Some(&b'{') => i = interpolation_end(text, i + 2)?,
+            b'\n' => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+
+    /// The tokens of `input` without the `EOL` and `EOF` markers, with the
+    /// line each ends on.
+    fn tokens(input: &str) -> Vec<(TokenType, String, usize)> {
+        let mut source = Source::new(input.to_string());
+        source.tokenize();
+        source
+            .get_tokens()
+            .iter()
+            .filter(|t| !matches!(t.token_type, TokenType::EOL | TokenType::EOF))
+            .map(|t| (t.token_type, t.lexeme.clone(), t.line))
+            .collect()
+    }
+
+    fn string(lexeme: &str, line: usize) -> (TokenType, String, usize) {
+        (TokenType::String, lexeme.to_string(), line)
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_the_string() {
+        assert_eq!(
+            tokens(r#""say \"hi\"" "\\" "a\$b""#),
+            [string("say \"hi\"", 1), string("\\", 1), string("a$b", 1)]
+        );
+    }
+
+    #[test]
+    fn triple_quotes_hold_unescaped_quotes() {
+        assert_eq!(
+            tokens(r#""""a "b" ""c"" d""""#),
+            [string(r#"a "b" ""c"" d"#, 1)]
+        );
+        assert_eq!(tokens(r#"r"""x "y" \n""""#), [string(r#"x "y" \n"#, 1)]);
+    }
+
+    #[test]
+    fn quotes_nest_inside_interpolation() {
+        let tokens = tokens(r#""a ${f("}", "${"b"}")} c""#);
+        let types: Vec<_> = tokens.iter().map(|t| t.0).collect();
+        assert_eq!(
+            types,
+            [
+                TokenType::InterpStart,
+                TokenType::Ident,
+                TokenType::LParen,
+                TokenType::String,
+                TokenType::Comma,
+                TokenType::InterpStart,
+                TokenType::String,
+                TokenType::InterpEnd,
+                TokenType::RParen,
+                TokenType::InterpEnd,
+            ]
+        );
+        assert_eq!((tokens[0].1.as_str(), tokens[9].1.as_str()), ("a ", " c"));
+        assert_eq!(tokens[3].1, "}");
+        assert_eq!(tokens[6].1, "b");
+    }
+
+    #[test]
+    fn raw_strings_keep_backslashes_and_interpolation() {
+        assert_eq!(
+            tokens(r#"r"C:\Users\riku ${x}""#),
+            [string(r"C:\Users\riku ${x}", 1)]
+        );
+    }
+
+    #[test]
+    fn escaped_newlines_do_not_count_as_lines() {
+        assert_eq!(
+            tokens("\"a\\nb\\tc\" x"),
+            [string("a\nb\tc", 1), (TokenType::Ident, "x".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn triple_quoted_strings_span_lines() {
+        let tokens = tokens("let s = \"\"\"\none\ntwo\n\"\"\"\nx\n");
+        assert_eq!(tokens[3], string("one\ntwo\n", 4));
+        assert_eq!(tokens[4], (TokenType::Ident, "x".to_string(), 5));
+    }
+
+    #[test]
+    fn only_the_line_break_right_after_the_opening_quotes_is_dropped() {
+        assert_eq!(tokens("\"\"\"\n\none\"\"\""), [string("\none", 3)]);
+        assert_eq!(tokens("\"\"\"one\n\"\"\""), [string("one\n", 2)]);
+    }
+
+    #[test]
+    fn crlf_line_breaks_in_strings_become_newlines() {
+        assert_eq!(
+            tokens("\"\"\"\r\none\r\ntwo\"\"\"\r\nx"),
+            [
+                string("one\ntwo", 3),
+                (TokenType::Ident, "x".to_string(), 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_line_break_ends_a_single_quoted_string() {
+        let error = error::catch(|| tokens("let s = \"a\nb\"\n")).unwrap_err();
+        assert_eq!(error.line, Some(1));
+        assert_eq!(error.message, "Unterminated string");
+    }
+}
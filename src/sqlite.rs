@@ -0,0 +1,313 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    ffi::{CStr, CString, c_char, c_int},
+    ptr,
+    rc::Rc,
+};
+
+use crate::{
+    env::Value,
+    error::{ErrorType, fatal},
+    std_fn::wrong_type,
+};
+
+#[repr(C)]
+struct Sqlite3 {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct Sqlite3Stmt {
+    _private: [u8; 0],
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+const SQLITE_INTEGER: c_int = 1;
+const SQLITE_FLOAT: c_int = 2;
+const SQLITE_NULL: c_int = 5;
+/// Destructor value asking SQLite to copy bound text before returning.
+const SQLITE_TRANSIENT: isize = -1;
+
+#[link(name = "sqlite3")]
+unsafe extern "C" {
+    fn sqlite3_open_v2(
+        filename: *const c_char,
+        db: *mut *mut Sqlite3,
+        flags: c_int,
+        vfs: *const c_char,
+    ) -> c_int;
+    fn sqlite3_close(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+    fn sqlite3_changes(db: *mut Sqlite3) -> c_int;
+    fn sqlite3_prepare_v2(
+        db: *mut Sqlite3,
+        sql: *const c_char,
+        bytes: c_int,
+        stmt: *mut *mut Sqlite3Stmt,
+        tail: *mut *const c_char,
+    ) -> c_int;
+    fn sqlite3_bind_parameter_count(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_bind_null(stmt: *mut Sqlite3Stmt, index: c_int) -> c_int;
+    fn sqlite3_bind_int64(stmt: *mut Sqlite3Stmt, index: c_int, value: i64) -> c_int;
+    fn sqlite3_bind_double(stmt: *mut Sqlite3Stmt, index: c_int, value: f64) -> c_int;
+    fn sqlite3_bind_text(
+        stmt: *mut Sqlite3Stmt,
+        index: c_int,
+        text: *const c_char,
+        bytes: c_int,
+        destructor: isize,
+    ) -> c_int;
+    fn sqlite3_step(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_column_count(stmt: *mut Sqlite3Stmt) -> c_int;
+    fn sqlite3_column_name(stmt: *mut Sqlite3Stmt, column: c_int) -> *const c_char;
+    fn sqlite3_column_type(stmt: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    fn sqlite3_column_int64(stmt: *mut Sqlite3Stmt, column: c_int) -> i64;
+    fn sqlite3_column_double(stmt: *mut Sqlite3Stmt, column: c_int) -> f64;
+    fn sqlite3_column_text(stmt: *mut Sqlite3Stmt, column: c_int) -> *const u8;
+    fn sqlite3_column_bytes(stmt: *mut Sqlite3Stmt, column: c_int) -> c_int;
+    fn sqlite3_finalize(stmt: *mut Sqlite3Stmt) -> c_int;
+}
+
+struct Connection(*mut Sqlite3);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe {
+            sqlite3_close(self.0);
+        }
+    }
+}
+
+thread_local! {
+    /// Open databases. Scripts hold the index into this list.
+    static DATABASES: RefCell<Vec<Option<Connection>>> = const { RefCell::new(Vec::new()) };
+}
+
+fn fail(function: &str, message: String) -> ! {
+    fatal(
+        ErrorType::RuntimeError,
+        format!("{}(): {}", function, message),
+    )
+}
+
+fn errmsg(db: *mut Sqlite3) -> String {
+    unsafe { CStr::from_ptr(sqlite3_errmsg(db)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Opens or creates the database file at `path` and returns its handle.
+/// `":memory:"` opens a private in-memory database.
+pub fn db_open(args: Vec<Value>) -> Value {
+    let [path] = args.as_slice() else {
+        fail("db_open", "takes a path string".to_string());
+    };
+    let Value::String(path) = path else {
+        wrong_type("db_open", 0, "string", path);
+    };
+    let Ok(c_path) = CString::new(path.as_str()) else {
+        fail("db_open", "path contains a NUL character".to_string());
+    };
+    let mut db = ptr::null_mut();
+    let flags = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE;
+    let rc = unsafe { sqlite3_open_v2(c_path.as_ptr(), &mut db, flags, ptr::null()) };
+    // SQLite allocates a handle even when opening fails; dropping the
+    // connection closes it.
+    let connection = Connection(db);
+    if rc != SQLITE_OK {
+        let message = if db.is_null() {
+            "out of memory".to_string()
+        } else {
+            errmsg(db)
+        };
+        fail("db_open", format!("cannot open `{}`: {}", path, message));
+    }
+    DATABASES.with(|databases| {
+        let mut databases = databases.borrow_mut();
+        databases.push(Some(connection));
+        Value::Int(databases.len() as i64 - 1)
+    })
+}
+
+/// Closes a database. Using the handle afterwards is an error.
+pub fn db_close(args: Vec<Value>) -> Value {
+    let [db] = args.as_slice() else {
+        fail("db_close", "takes a database handle".to_string());
+    };
+    let index = handle_index("db_close", db);
+    DATABASES.with(|databases| databases.borrow_mut()[index] = None);
+    Value::Nil
+}
+
+/// Runs a query and returns its rows as a list of maps from column name to
+/// value.
+pub fn db_query(args: Vec<Value>) -> Value {
+    let (db, sql, params) = sql_args("db_query", &args);
+    let mut rows = Vec::new();
+    run(db, sql, &params, |stmt| rows.push(row(stmt))).unwrap_or_else(|e| fail("db_query", e));
+    Value::List(Rc::new(RefCell::new(rows)))
+}
+
+/// Runs one or more statements and returns the number of rows changed by
+/// the last one. Parameters need a single statement.
+pub fn db_exec(args: Vec<Value>) -> Value {
+    let (db, sql, params) = sql_args("db_exec", &args);
+    run(db, sql, &params, |_| {}).unwrap_or_else(|e| fail("db_exec", e));
+    Value::Int(unsafe { sqlite3_changes(db) } as i64)
+}
+
+fn handle_index(function: &str, db: &Value) -> usize {
+    let open = |i: usize| DATABASES.with(|d| d.borrow().get(i).is_some_and(Option::is_some));
+    match db {
+        Value::Int(i) if *i >= 0 && open(*i as usize) => *i as usize,
+        Value::Int(_) => fail(function, format!("`{}` is not an open database", db)),
+        _ => wrong_type(function, 0, "database handle", db),
+    }
+}
+
+/// `(db, sql)` or `(db, sql, params)`.
+fn sql_args<'a>(function: &str, args: &'a [Value]) -> (*mut Sqlite3, &'a str, Vec<Value>) {
+    let (db, sql, params) = match args {
+        [db, sql] => (db, sql, Vec::new()),
+        [db, sql, Value::List(params)] => (db, sql, params.borrow().clone()),
+        [_, _, params] => wrong_type(function, 2, "list", params),
+        _ => fail(
+            function,
+            "takes a database, an SQL string and an optional list of parameters".to_string(),
+        ),
+    };
+    let Value::String(sql) = sql else {
+        wrong_type(function, 1, "string", sql);
+    };
+    let index = handle_index(function, db);
+    let db = DATABASES.with(|d| d.borrow()[index].as_ref().unwrap().0);
+    (db, sql, params)
+}
+
+/// Prepares and steps every statement in `sql`, calling `on_row` for every
+/// result row. `params` are bound to the `?` placeholders, so they need SQL
+/// of a single statement, which is checked before anything runs.
+fn run(
+    db: *mut Sqlite3,
+    sql: &str,
+    params: &[Value],
+    mut on_row: impl FnMut(*mut Sqlite3Stmt),
+) -> Result<(), String> {
+    let sql = CString::new(sql).map_err(|_| "SQL contains a NUL character".to_string())?;
+    let mut tail = sql.as_ptr();
+    loop {
+        let mut stmt = ptr::null_mut();
+        if unsafe { sqlite3_prepare_v2(db, tail, -1, &mut stmt, &mut tail) } != SQLITE_OK {
+            return Err(errmsg(db));
+        }
+        // Only whitespace or comments were left.
+        if stmt.is_null() {
+            return Ok(());
+        }
+        if !params.is_empty() && has_statement(db, tail) {
+            unsafe {
+                sqlite3_finalize(stmt);
+            }
+            return Err(
+                "parameters can only be bound to a single statement; run the others separately"
+                    .to_string(),
+            );
+        }
+        let result = step(db, stmt, params, &mut on_row);
+        unsafe {
+            sqlite3_finalize(stmt);
+        }
+        result?;
+    }
+}
+
+/// Whether the SQL at `tail` holds another statement rather than only
+/// whitespace or comments. A statement that fails to prepare counts.
+fn has_statement(db: *mut Sqlite3, tail: *const c_char) -> bool {
+    let mut stmt = ptr::null_mut();
+    let rc = unsafe { sqlite3_prepare_v2(db, tail, -1, &mut stmt, ptr::null_mut()) };
+    unsafe {
+        sqlite3_finalize(stmt);
+    }
+    rc != SQLITE_OK || !stmt.is_null()
+}
+
+fn step(
+    db: *mut Sqlite3,
+    stmt: *mut Sqlite3Stmt,
+    params: &[Value],
+    on_row: &mut impl FnMut(*mut Sqlite3Stmt),
+) -> Result<(), String> {
+    let wanted = unsafe { sqlite3_bind_parameter_count(stmt) } as usize;
+    if wanted != params.len() {
+        return Err(format!(
+            "statement needs {} parameters but got {}",
+            wanted,
+            params.len()
+        ));
+    }
+    for (i, param) in params.iter().enumerate() {
+        let index = i as c_int + 1;
+        let rc = unsafe {
+            match param {
+                Value::Nil => sqlite3_bind_null(stmt, index),
+                Value::Bool(b) => sqlite3_bind_int64(stmt, index, *b as i64),
+                Value::Int(n) => sqlite3_bind_int64(stmt, index, *n),
+                Value::Number(n) => sqlite3_bind_double(stmt, index, *n),
+                Value::String(s) => sqlite3_bind_text(
+                    stmt,
+                    index,
+                    s.as_ptr() as *const c_char,
+                    s.len() as c_int,
+                    SQLITE_TRANSIENT,
+                ),
+                _ => return Err(format!("cannot bind a {} parameter", param.type_name())),
+            }
+        };
+        if rc != SQLITE_OK {
+            return Err(errmsg(db));
+        }
+    }
+    loop {
+        match unsafe { sqlite3_step(stmt) } {
+            SQLITE_ROW => on_row(stmt),
+            SQLITE_DONE => return Ok(()),
+            _ => return Err(errmsg(db)),
+        }
+    }
+}
+
+fn row(stmt: *mut Sqlite3Stmt) -> Value {
+    let mut row = BTreeMap::new();
+    let columns = unsafe { sqlite3_column_count(stmt) };
+    for i in 0..columns {
+        let name = unsafe { CStr::from_ptr(sqlite3_column_name(stmt, i)) }
+            .to_string_lossy()
+            .into_owned();
+        let value = unsafe {
+            match sqlite3_column_type(stmt, i) {
+                SQLITE_INTEGER => Value::Int(sqlite3_column_int64(stmt, i)),
+                SQLITE_FLOAT => Value::Number(sqlite3_column_double(stmt, i)),
+                SQLITE_NULL => Value::Nil,
+                // Text, and blobs read as text.
+                _ => {
+                    let text = sqlite3_column_text(stmt, i);
+                    let len = sqlite3_column_bytes(stmt, i) as usize;
+                    if text.is_null() {
+                        Value::String(String::new())
+                    } else {
+                        let bytes = std::slice::from_raw_parts(text, len);
+                        Value::String(String::from_utf8_lossy(bytes).into_owned())
+                    }
+                }
+            }
+        };
+        row.insert(name, value);
+    }
+    Value::Map(Rc::new(RefCell::new(row)))
+}
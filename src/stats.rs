@@ -0,0 +1,103 @@
+use std::{
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use crate::{env::Value, output::flush_stdout};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static STATEMENTS: AtomicU64 = AtomicU64::new(0);
+static CALLS: AtomicU64 = AtomicU64::new(0);
+static ENV_DEPTH: AtomicU64 = AtomicU64::new(1);
+
+/// The value kinds that own heap storage, in report order.
+const KINDS: [&str; 8] = [
+    "string", "list", "tuple", "map", "struct", "function", "decimal", "bigint",
+];
+static ALLOCATIONS: [AtomicU64; KINDS.len()] = [const { AtomicU64::new(0) }; KINDS.len()];
+
+/// Starts counting, for `--stats`. Until this is called every hook below
+/// is a single relaxed load.
+pub fn enable() {
+    STARTED.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn statement() {
+    if enabled() {
+        STATEMENTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a call to any function, builtin or method.
+pub fn call() {
+    if enabled() {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Records a new scope `depth` environments deep, counting the global one.
+/// A deep chain makes looking up globals and captured names slower.
+pub fn env_depth(depth: u64) {
+    ENV_DEPTH.fetch_max(depth, Ordering::Relaxed);
+}
+
+/// Counts `value` as newly created if it owns heap storage, and passes it
+/// through. Called where the interpreter builds values, not where it copies
+/// references to existing ones.
+pub fn alloc(value: Value) -> Value {
+    if enabled() {
+        let kind = match &value {
+            Value::String(_) => 0,
+            Value::List(_) => 1,
+            Value::Tuple(_) => 2,
+            Value::Map(_) => 3,
+            Value::Struct(_) => 4,
+            Value::Function { .. } => 5,
+            Value::Decimal(_) => 6,
+            #[cfg(feature = "bigint")]
+            Value::BigInt(_) => 7,
+            _ => return value,
+        };
+        ALLOCATIONS[kind].fetch_add(1, Ordering::Relaxed);
+    }
+    value
+}
+
+/// Prints the counts to standard error, if `--stats` is on. Called when
+/// the interpreter exits, including on a fatal error.
+pub fn report() {
+    if !enabled() {
+        return;
+    }
+    flush_stdout();
+    let runtime = STARTED
+        .get()
+        .map_or(0.0, |s| s.elapsed().as_secs_f64() * 1000.0);
+    eprintln!("stats:");
+    eprintln!("  statements  {}", STATEMENTS.load(Ordering::Relaxed));
+    eprintln!("  calls       {}", CALLS.load(Ordering::Relaxed));
+    eprintln!("  env depth   {}", ENV_DEPTH.load(Ordering::Relaxed));
+    eprintln!("  runtime     {:.3} ms", runtime);
+    let counts = KINDS
+        .iter()
+        .zip(&ALLOCATIONS)
+        .map(|(kind, count)| (kind, count.load(Ordering::Relaxed)))
+        .filter(|(_, count)| *count > 0)
+        .collect::<Vec<_>>();
+    if counts.is_empty() {
+        eprintln!("  allocations none");
+    }
+    for (i, (kind, count)) in counts.iter().enumerate() {
+        let heading = if i == 0 { "allocations" } else { "" };
+        eprintln!("  {:<12}{:<10}{}", heading, kind, count);
+    }
+}
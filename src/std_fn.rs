@@ -1,130 +1,884 @@
+#[cfg(feature = "bigint")]
+use crate::bigint::BigInt;
+use crate::config;
+use crate::decimal::Decimal;
 use crate::env::{Env, Value};
-use crate::error::{ErrorType, error};
-use std::io::{Write, stdout};
-use std::process;
+use crate::error::{self, ErrorType, fatal, line_fatal};
+use crate::expr::{self, call_value, param_arity};
+use crate::format::FormatSpec;
+use crate::iter;
+use crate::output::{flush_stdout, write_stderr, write_stdout};
+#[cfg(feature = "sqlite")]
+use crate::sqlite;
+use crate::symbol::Symbol;
+use crate::timer;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io::{IsTerminal, stdout};
+use std::rc::Rc;
+use std::time::Instant;
 
-pub fn std_fn(env: &mut Env) {
-    print_fn(env);
-    println_fn(env);
-    input_fn(env);
-    int_fn(env);
-    str_fn(env);
-}
-
-fn str_fn(env: &mut Env) {
-    let name = "str".to_string();
-    fn to_str(args: Vec<Value>) -> Value {
-        if args.len() != 1 {
-            error(
-                ErrorType::RuntimeError,
-                "str() takes exactly one argument".to_string(),
-            );
-            process::exit(1);
+/// Number of arguments a builtin accepts. `max` is `None` for variadic
+/// functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub(crate) const fn exact(n: usize) -> Self {
+        Arity {
+            min: n,
+            max: Some(n),
         }
-        match &args[0] {
-            Value::Number(n) => Value::String(n.to_string()),
-            Value::Bool(b) => Value::String(b.to_string()),
-            Value::String(s) => Value::String(s.clone()),
-            _ => {
-                error(
-                    ErrorType::RuntimeError,
-                    "str() argument must be a number".to_string(),
-                );
-                process::exit(1);
-            }
+    }
+
+    pub(crate) const fn range(min: usize, max: usize) -> Self {
+        Arity {
+            min,
+            max: Some(max),
         }
     }
-    let func = Value::FuncBuiltIn {
-        name: name.clone(),
+
+    pub(crate) const fn variadic() -> Self {
+        Arity { min: 0, max: None }
+    }
+
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+}
+
+/// `2`, `1 to 3` or `at least 1`.
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+/// What a builtin touches outside the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Pure,
+    Stdout,
+    Stderr,
+    Stdin,
+    Files,
+    /// Calling functions of `--plugin` programs, which can do anything.
+    Plugins,
+}
+
+impl Capability {
+    /// Names accepted in a script's `//! requires:` header.
+    pub const NAMES: &[&str] = &["stdout", "stderr", "stdin", "files", "plugins"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stdout" => Some(Capability::Stdout),
+            "stderr" => Some(Capability::Stderr),
+            "stdin" => Some(Capability::Stdin),
+            "files" => Some(Capability::Files),
+            "plugins" => Some(Capability::Plugins),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Pure => "none",
+            Capability::Stdout => "stdout",
+            Capability::Stderr => "stderr",
+            Capability::Stdin => "stdin",
+            Capability::Files => "files",
+            Capability::Plugins => "plugins",
+        }
+    }
+}
+
+/// Description of a builtin function, for embedders and editor tooling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltinInfo {
+    pub name: &'static str,
+    pub arity: Arity,
+    /// Parameter names, used to point at the argument in type errors.
+    pub params: &'static [&'static str],
+    pub doc: &'static str,
+    pub capability: Capability,
+}
+
+struct Builtin {
+    info: BuiltinInfo,
+    body: fn(Vec<Value>) -> Value,
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        info: BuiltinInfo {
+            name: "print",
+            arity: Arity::variadic(),
+            params: &["values"],
+            doc: "Prints the arguments without a trailing newline.",
+            capability: Capability::Stdout,
+        },
+        body: print,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "println",
+            arity: Arity::variadic(),
+            params: &["values"],
+            doc: "Prints the arguments followed by a newline.",
+            capability: Capability::Stdout,
+        },
+        body: println,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "eprint",
+            arity: Arity::variadic(),
+            params: &["values"],
+            doc: "Prints the arguments to standard error without a trailing newline.",
+            capability: Capability::Stderr,
+        },
+        body: eprint,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "eprintln",
+            arity: Arity::variadic(),
+            params: &["values"],
+            doc: "Prints the arguments to standard error followed by a newline.",
+            capability: Capability::Stderr,
+        },
+        body: eprintln,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "input",
+            arity: Arity::variadic(),
+            params: &["prompt"],
+            doc: "Prints the arguments as a prompt and reads a line from standard input.",
+            capability: Capability::Stdin,
+        },
+        body: input,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "int",
+            arity: Arity::exact(1),
+            params: &["value"],
+            doc: "Converts a number, boolean or numeric string to a whole number.",
+            capability: Capability::Pure,
+        },
+        body: to_int,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "str",
+            arity: Arity::exact(1),
+            params: &["value"],
+            doc: "Converts a value to its string form.",
+            capability: Capability::Pure,
+        },
         body: to_str,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "bool",
+            arity: Arity::exact(1),
+            params: &["value"],
+            doc: "Returns whether a value counts as true in a condition.",
+            capability: Capability::Pure,
+        },
+        body: to_bool,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "len",
+            arity: Arity::exact(1),
+            params: &["collection"],
+            doc: "Returns the length of a string, list or map.",
+            capability: Capability::Pure,
+        },
+        body: len,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "list",
+            arity: Arity::exact(1),
+            params: &["iterable"],
+            doc: "Collects the values of anything a `for` loop can visit into a new list.",
+            capability: Capability::Pure,
+        },
+        body: list,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "sum",
+            arity: Arity::exact(1),
+            params: &["iterable"],
+            doc: "Adds up the values of anything a `for` loop can visit, starting from 0.",
+            capability: Capability::Pure,
+        },
+        body: sum,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "inspect",
+            arity: Arity::exact(1),
+            params: &["value"],
+            doc: "Prints a value's type, size, reference count and shallow structure, then returns it.",
+            capability: Capability::Stdout,
+        },
+        body: inspect,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "bench",
+            arity: Arity::exact(2),
+            params: &["function", "runs"],
+            doc: "Calls a function with no arguments the given number of times and prints the time taken.",
+            capability: Capability::Stdout,
+        },
+        body: bench,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "decimal",
+            arity: Arity::exact(1),
+            params: &["value"],
+            doc: "Converts a string such as \"0.1\", an integer or a float to an exact decimal.",
+            capability: Capability::Pure,
+        },
+        body: to_decimal,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "format",
+            arity: Arity::exact(2),
+            params: &["value", "spec"],
+            doc: "Formats a value with a spec such as \">10\" or \".2\", as in `${value:spec}`.",
+            capability: Capability::Pure,
+        },
+        body: format,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "every",
+            arity: Arity::range(2, 3),
+            params: &["seconds", "function", "limit"],
+            doc: "Calls a function every given number of seconds until it returns false, an optional tick limit is reached or Ctrl-C is pressed.",
+            capability: Capability::Pure,
+        },
+        body: timer::every,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "after",
+            arity: Arity::exact(2),
+            params: &["seconds", "function"],
+            doc: "Waits the given number of seconds, then calls a function; Ctrl-C cancels the wait.",
+            capability: Capability::Pure,
+        },
+        body: timer::after,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "at_exit",
+            arity: Arity::exact(1),
+            params: &["function"],
+            doc: "Registers a function to call when the program ends normally or through exit(); the last registered runs first.",
+            capability: Capability::Pure,
+        },
+        body: at_exit,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "exit",
+            arity: Arity::range(0, 1),
+            params: &["status"],
+            doc: "Runs the at_exit functions and ends the program with a status from 0 to 255, 0 by default.",
+            capability: Capability::Pure,
+        },
+        body: exit_program,
+    },
+];
+
+/// Database builtins, built with the `sqlite` feature. They link against the
+/// system SQLite library.
+#[cfg(feature = "sqlite")]
+const SQLITE_BUILTINS: &[Builtin] = &[
+    Builtin {
+        info: BuiltinInfo {
+            name: "db_open",
+            arity: Arity::exact(1),
+            params: &["path"],
+            doc: "Opens or creates an SQLite database file and returns its handle.",
+            capability: Capability::Files,
+        },
+        body: sqlite::db_open,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "db_close",
+            arity: Arity::exact(1),
+            params: &["db"],
+            doc: "Closes a database opened with db_open.",
+            capability: Capability::Files,
+        },
+        body: sqlite::db_close,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "db_query",
+            arity: Arity::range(2, 3),
+            params: &["db", "sql", "params"],
+            doc: "Runs a query with optional `?` parameters and returns its rows as a list of maps.",
+            capability: Capability::Files,
+        },
+        body: sqlite::db_query,
+    },
+    Builtin {
+        info: BuiltinInfo {
+            name: "db_exec",
+            arity: Arity::range(2, 3),
+            params: &["db", "sql", "params"],
+            doc: "Runs SQL statements with optional `?` parameters and returns the number of changed rows.",
+            capability: Capability::Files,
+        },
+        body: sqlite::db_exec,
+    },
+];
+
+#[cfg(not(feature = "sqlite"))]
+const SQLITE_BUILTINS: &[Builtin] = &[];
+
+#[cfg(feature = "bigint")]
+const BIGINT_BUILTINS: &[Builtin] = &[Builtin {
+    info: BuiltinInfo {
+        name: "bigint",
+        arity: Arity::exact(1),
+        params: &["value"],
+        doc: "Converts an integer or a string of digits to an arbitrary-precision integer.",
+        capability: Capability::Pure,
+    },
+    body: to_bigint,
+}];
+
+#[cfg(not(feature = "bigint"))]
+const BIGINT_BUILTINS: &[Builtin] = &[];
+
+fn all() -> impl Iterator<Item = &'static Builtin> {
+    BUILTINS
+        .iter()
+        .chain(SQLITE_BUILTINS)
+        .chain(BIGINT_BUILTINS)
+}
+
+pub fn std_fn(env: &mut Env) {
+    for builtin in all() {
+        let name = builtin.info.name.to_string();
+        let func = Value::FuncBuiltIn {
+            name: name.clone(),
+            body: builtin.body,
+        };
+        env.define(Symbol::intern(builtin.info.name), func);
+    }
+}
+
+/// Whether `name` is a builtin function.
+pub fn is_builtin(name: &str) -> bool {
+    all().any(|b| b.info.name == name)
+}
+
+/// The number of arguments the builtin `name` accepts.
+pub fn arity(name: &str) -> Option<Arity> {
+    all().find(|b| b.info.name == name).map(|b| b.info.arity)
+}
+
+/// Stops the script if the builtin `name` needs a capability that was not
+/// granted by the script header.
+pub fn check_capability(name: &str) {
+    if let Some(builtin) = all().find(|b| b.info.name == name) {
+        require(name, builtin.info.capability);
+    }
+}
+
+/// Stops the script if `name`, a builtin or plugin function, needs a
+/// `capability` that was not granted by the script header.
+pub fn require(name: &str, capability: Capability) {
+    if !config::granted(capability) {
+        fatal(
+            ErrorType::RuntimeError,
+            format!(
+                "`{}` needs the `{}` capability; add it to the script's `//! requires:` header",
+                name,
+                capability.name()
+            ),
+        );
+    }
+}
+
+/// Stops the script because argument `index` (from 0) of the builtin
+/// `function` is not `expected`, naming the parameter from its metadata.
+pub(crate) fn wrong_type(function: &str, index: usize, expected: &str, got: &Value) -> ! {
+    let param = all()
+        .find(|b| b.info.name == function)
+        .and_then(|b| b.info.params.get(index).copied());
+    argument_type_error(function, index, param, expected, got)
+}
+
+/// `split() expected string for argument 1 (separator), got number`, on
+/// the line of the running builtin call.
+pub(crate) fn argument_type_error(
+    function: &str,
+    index: usize,
+    param: Option<&str>,
+    expected: &str,
+    got: &Value,
+) -> ! {
+    let param = param.map_or(String::new(), |p| format!(" ({})", p));
+    let message = format!(
+        "{}() expected {} for argument {}{}, got {}",
+        function,
+        expected,
+        index + 1,
+        param,
+        got.type_name()
+    );
+    match CALL_LINE.get() {
+        Some(line) => line_fatal(ErrorType::TypeError, line, message),
+        None => fatal(ErrorType::TypeError, message),
+    }
+}
+
+thread_local! {
+    /// The line of the innermost running builtin function or method call.
+    static CALL_LINE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// One running builtin call on `line`, whose argument type errors name that
+/// line. Dropping it, also while a caught error unwinds, restores the line
+/// of the call around it, so a builtin that calls back into a script keeps
+/// its own.
+pub(crate) struct CallLine(Option<usize>);
+
+impl CallLine {
+    pub(crate) fn enter(line: Option<usize>) -> Self {
+        CallLine(CALL_LINE.replace(line))
+    }
+}
+
+impl Drop for CallLine {
+    fn drop(&mut self) {
+        CALL_LINE.set(self.0);
+    }
+}
+
+pub fn builtins() -> Vec<BuiltinInfo> {
+    all().map(|builtin| builtin.info.clone()).collect()
+}
+
+fn len(args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        fatal(
+            ErrorType::RuntimeError,
+            "len() takes exactly one argument".to_string(),
+        );
+    }
+    match &args[0] {
+        Value::String(s) => Value::Int(s.chars().count() as i64),
+        Value::List(items) => Value::Int(items.borrow().len() as i64),
+        Value::Tuple(items) => Value::Int(items.len() as i64),
+        Value::Map(map) => Value::Int(map.borrow().len() as i64),
+        value => wrong_type("len", 0, "string, list, tuple or map", value),
+    }
+}
+
+fn list(args: Vec<Value>) -> Value {
+    match iter::iter(&args[0]) {
+        Some(items) => Value::List(Rc::new(RefCell::new(items.collect()))),
+        None => wrong_type("list", 0, "something iterable", &args[0]),
+    }
+}
+
+fn sum(args: Vec<Value>) -> Value {
+    match iter::iter(&args[0]) {
+        Some(items) => items.fold(Value::Int(0), expr::add),
+        None => wrong_type("sum", 0, "something iterable", &args[0]),
+    }
+}
+
+/// Prints a line describing the value, then one line per element, field or
+/// entry. Reference counts leave out the copies `inspect` holds itself, so
+/// a list bound to one variable shows `1 ref` and a temporary `0 refs`.
+fn inspect(args: Vec<Value>) -> Value {
+    let value = args.into_iter().next().unwrap_or(Value::Nil);
+    write_stdout(format_args!("{}\n", describe(&value, 1)));
+    let children: Vec<(String, Value)> = match &value {
+        Value::List(items) => items
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v.clone()))
+            .collect(),
+        Value::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v.clone()))
+            .collect(),
+        Value::Map(map) => map
+            .borrow()
+            .iter()
+            .map(|(k, v)| (format!("{:?}", k), v.clone()))
+            .collect(),
+        Value::Struct(instance) => {
+            let instance = instance.borrow();
+            let names = instance.def.fields.iter().map(|f| f.to_string());
+            names.zip(instance.fields.iter().cloned()).collect()
+        }
+        _ => Vec::new(),
     };
-    env.define(name, func);
+    for (key, child) in children {
+        write_stdout(format_args!("  {}: {}\n", key, describe(&child, 1)));
+    }
+    value
 }
 
-fn int_fn(env: &mut Env) {
-    let name = "int".to_string();
-    fn to_int(args: Vec<Value>) -> Value {
-        if args.len() != 1 {
-            error(
-                ErrorType::RuntimeError,
-                "int() takes exactly one argument".to_string(),
-            );
-            process::exit(1);
+/// One line about a value for `inspect`, such as `list, len 3, 2 refs`.
+/// `held` is the number of references to leave out of the count.
+fn describe(value: &Value, held: usize) -> String {
+    let refs = |count: usize| {
+        let count = count.saturating_sub(held);
+        format!("{} ref{}", count, if count == 1 { "" } else { "s" })
+    };
+    match value {
+        Value::String(s) => format!("string {:?}, len {}", s, s.chars().count()),
+        Value::List(items) => format!(
+            "list, len {}, {}",
+            items.borrow().len(),
+            refs(Rc::strong_count(items))
+        ),
+        Value::Tuple(items) => format!(
+            "tuple, len {}, {}",
+            items.len(),
+            refs(Rc::strong_count(items))
+        ),
+        Value::Map(map) => format!(
+            "map, len {}, {}",
+            map.borrow().len(),
+            refs(Rc::strong_count(map))
+        ),
+        Value::Struct(instance) => {
+            let def = &instance.borrow().def;
+            let kind = if def.class { "class" } else { "struct" };
+            format!(
+                "{} {}, {} fields, {}",
+                kind,
+                def.name,
+                def.fields.len(),
+                refs(Rc::strong_count(instance))
+            )
         }
-        match &args[0] {
-            Value::Number(n) => Value::Number(n.floor()),
-            Value::Bool(b) => Value::Number(if *b { 1.0 } else { 0.0 }),
-            Value::String(s) => {
-                if let Ok(n) = s.parse::<f64>() {
-                    Value::Number(n)
-                } else {
-                    error(
-                        ErrorType::RuntimeError,
-                        format!("int() argument must be a number, not `{}`", s),
-                    );
-                    process::exit(1);
-                }
-            }
-            _ => {
-                error(
+        Value::StructDef(def) => format!("type {}", def.name),
+        Value::Function { name, params, .. } => {
+            format!("function {}, arity {}", name, param_arity(params))
+        }
+        Value::FuncBuiltIn { name, .. } => match arity(name) {
+            Some(arity) => format!("builtin {}, arity {}", name, arity),
+            None => format!("builtin {}", name),
+        },
+        Value::FuncPlugin { name, .. } => format!("plugin function {}", name),
+        Value::BoundMethod { receiver, name } => {
+            format!("method {}.{}", receiver.borrow().def.name, name)
+        }
+        Value::Nil => "nil".to_string(),
+        _ => format!("{} {}", value.type_name(), value),
+    }
+}
+
+/// Returns the mean time of one call in milliseconds.
+fn bench(args: Vec<Value>) -> Value {
+    if args.len() != 2 {
+        fatal(
+            ErrorType::RuntimeError,
+            "bench() takes exactly two arguments".to_string(),
+        );
+    }
+    let name = match &args[0] {
+        Value::Function { name, .. }
+        | Value::FuncBuiltIn { name, .. }
+        | Value::FuncPlugin { name, .. } => name.clone(),
+        value => wrong_type("bench", 0, "function", value),
+    };
+    let runs = match args[1] {
+        Value::Int(n) if n > 0 => n,
+        Value::Int(_) => fatal(
+            ErrorType::RuntimeError,
+            "bench() run count must be a positive integer".to_string(),
+        ),
+        ref value => wrong_type("bench", 1, "integer", value),
+    };
+    let start = Instant::now();
+    for _ in 0..runs {
+        call_value(args[0].clone(), Vec::new());
+    }
+    let total = start.elapsed().as_secs_f64() * 1000.0;
+    let mean = total / runs as f64;
+    write_stdout(format_args!(
+        "bench {}: {} runs, {:.3} ms per run, {:.3} ms total\n",
+        name, runs, mean, total
+    ));
+    Value::Number(mean)
+}
+
+fn format(args: Vec<Value>) -> Value {
+    if args.len() != 2 {
+        fatal(
+            ErrorType::RuntimeError,
+            "format() takes exactly two arguments".to_string(),
+        );
+    }
+    let Value::String(spec) = &args[1] else {
+        wrong_type("format", 1, "string", &args[1]);
+    };
+    match FormatSpec::parse(spec) {
+        Ok(spec) => Value::String(spec.apply(&args[0])),
+        Err(message) => fatal(ErrorType::RuntimeError, message),
+    }
+}
+
+fn to_str(args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        fatal(
+            ErrorType::RuntimeError,
+            "str() takes exactly one argument".to_string(),
+        );
+    }
+    match &args[0] {
+        Value::Int(n) => Value::String(n.to_string()),
+        Value::Number(_) => Value::String(args[0].to_string()),
+        Value::Decimal(n) => Value::String(n.to_string()),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => Value::String(n.to_string()),
+        Value::Bool(b) => Value::String(b.to_string()),
+        Value::String(s) => Value::String(s.clone()),
+        Value::List(_) | Value::Tuple(_) | Value::Map(_) | Value::Struct(_) => {
+            Value::String(args[0].to_string())
+        }
+        value => wrong_type(
+            "str",
+            0,
+            "number, bool, string, collection or struct",
+            value,
+        ),
+    }
+}
+
+fn to_bool(args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        fatal(
+            ErrorType::RuntimeError,
+            "bool() takes exactly one argument".to_string(),
+        );
+    }
+    Value::Bool(args[0].truthy())
+}
+
+fn to_int(args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        fatal(
+            ErrorType::RuntimeError,
+            "int() takes exactly one argument".to_string(),
+        );
+    }
+    match &args[0] {
+        Value::Int(n) => Value::Int(*n),
+        Value::Number(n) => float_to_int(*n),
+        Value::Decimal(n) => match i64::try_from(n.floor()) {
+            Ok(n) => Value::Int(n),
+            Err(_) => fatal(
+                ErrorType::RuntimeError,
+                format!("int() argument `{}` is too large for an integer", n),
+            ),
+        },
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => match n.to_i64() {
+            Some(n) => Value::Int(n),
+            None => fatal(
+                ErrorType::RuntimeError,
+                format!("int() argument `{}` is too large for an integer", n),
+            ),
+        },
+        Value::Bool(b) => Value::Int(*b as i64),
+        Value::String(s) => {
+            if let Ok(n) = s.parse::<i64>() {
+                Value::Int(n)
+            } else if let Ok(n) = s.parse::<f64>() {
+                float_to_int(n)
+            } else {
+                fatal(
                     ErrorType::RuntimeError,
-                    "int() argument must be a number".to_string(),
+                    format!("int() argument must be a number, not `{}`", s),
                 );
-                process::exit(1);
             }
         }
+        value => wrong_type("int", 0, "number, bool or string", value),
     }
-    let func = Value::FuncBuiltIn {
-        name: name.clone(),
-        body: to_int,
-    };
-    env.define(name, func);
 }
 
-fn println_fn(env: &mut Env) {
-    let name = "println".to_string();
-    let func = Value::FuncBuiltIn {
-        name: name.clone(),
-        body: |args| {
-            for arg in args.iter() {
-                print!("{}", arg);
-            }
-            println!();
-            Value::Number(args.len() as f64)
-        },
+#[cfg(feature = "bigint")]
+fn to_bigint(args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        fatal(
+            ErrorType::RuntimeError,
+            "bigint() takes exactly one argument".to_string(),
+        );
+    }
+    let n = match &args[0] {
+        Value::BigInt(n) => (**n).clone(),
+        Value::Int(n) => BigInt::from_i64(*n),
+        Value::Number(n) if n.fract() == 0.0 && n.is_finite() => {
+            BigInt::parse(&format!("{:.0}", n)).unwrap()
+        }
+        Value::String(s) => BigInt::parse(s.trim()).unwrap_or_else(|| {
+            fatal(
+                ErrorType::RuntimeError,
+                format!("bigint() argument must be a whole number, not `{}`", s),
+            )
+        }),
+        value @ Value::Number(_) => fatal(
+            ErrorType::RuntimeError,
+            format!("bigint() argument must be a whole number, not `{}`", value),
+        ),
+        value => wrong_type("bigint", 0, "number or string", value),
     };
-    env.define(name, func);
+    Value::BigInt(Rc::new(n))
 }
 
-fn print_fn(env: &mut Env) {
-    let name = "print".to_string();
-    let func = Value::FuncBuiltIn {
-        name: name.clone(),
-        body: |args| {
-            for arg in args.iter() {
-                print!("{}", arg);
-                stdout().flush().unwrap();
-            }
-            Value::Number(args.len() as f64)
-        },
+fn to_decimal(args: Vec<Value>) -> Value {
+    if args.len() != 1 {
+        fatal(
+            ErrorType::RuntimeError,
+            "decimal() takes exactly one argument".to_string(),
+        );
+    }
+    let n = match &args[0] {
+        Value::Decimal(n) => Some(**n),
+        Value::Int(n) => Some(Decimal::from_i64(*n)),
+        Value::Number(n) => Decimal::from_f64(*n),
+        Value::String(s) => Decimal::parse(s.trim()),
+        value => wrong_type("decimal", 0, "number or string", value),
     };
-    env.define(name, func);
+    match n {
+        Some(n) => Value::Decimal(std::rc::Rc::new(n)),
+        None => fatal(
+            ErrorType::RuntimeError,
+            format!("decimal() argument must be a number, not `{}`", args[0]),
+        ),
+    }
 }
 
-fn input_fn(env: &mut Env) {
-    let name = "input".to_string();
-    let func = Value::FuncBuiltIn {
-        name: name.clone(),
-        body: |args| {
-            for arg in args.iter() {
-                print!("{}", arg);
-                stdout().flush().unwrap();
-            }
-            let mut input = String::new();
-            stdout().flush().unwrap();
-            std::io::stdin().read_line(&mut input).unwrap();
-            Value::String(input.trim().to_string())
-        },
+/// Rounds toward negative infinity. Floats outside the integer range stay
+/// floats.
+fn float_to_int(n: f64) -> Value {
+    let n = n.floor();
+    if n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        Value::Int(n as i64)
+    } else {
+        Value::Number(n)
+    }
+}
+
+fn println(args: Vec<Value>) -> Value {
+    for arg in args.iter() {
+        write_stdout(format_args!("{}", arg));
+    }
+    write_stdout(format_args!("\n"));
+    Value::Int(args.len() as i64)
+}
+
+fn print(args: Vec<Value>) -> Value {
+    for arg in args.iter() {
+        write_stdout(format_args!("{}", arg));
+        flush_stdout();
+    }
+    Value::Int(args.len() as i64)
+}
+
+fn eprintln(args: Vec<Value>) -> Value {
+    for arg in args.iter() {
+        write_stderr(format_args!("{}", arg));
+    }
+    write_stderr(format_args!("\n"));
+    Value::Int(args.len() as i64)
+}
+
+fn eprint(args: Vec<Value>) -> Value {
+    for arg in args.iter() {
+        write_stderr(format_args!("{}", arg));
+    }
+    Value::Int(args.len() as i64)
+}
+
+thread_local! {
+    /// Functions registered with `at_exit`, in registration order.
+    static EXIT_HOOKS: RefCell<Vec<Value>> = const { RefCell::new(Vec::new()) };
+}
+
+fn at_exit(args: Vec<Value>) -> Value {
+    match &args[0] {
+        Value::Function { .. }
+        | Value::BoundMethod { .. }
+        | Value::FuncBuiltIn { .. }
+        | Value::FuncPlugin { .. } => {}
+        value => wrong_type("at_exit", 0, "function", value),
+    }
+    EXIT_HOOKS.with_borrow_mut(|hooks| hooks.push(args[0].clone()));
+    Value::Nil
+}
+
+/// Calls the `at_exit` functions, last registered first. Each is removed
+/// before it runs, so one that calls `exit()` does not run twice. File and
+/// CLI mode call this when the script ends; embedders call it themselves.
+pub fn run_exit_hooks() {
+    while let Some(hook) = EXIT_HOOKS.with_borrow_mut(|hooks| hooks.pop()) {
+        call_value(hook, Vec::new());
+    }
+}
+
+fn exit_program(args: Vec<Value>) -> Value {
+    let status = match args.first() {
+        None => 0,
+        Some(Value::Int(n)) if (0..=255).contains(n) => *n as i32,
+        Some(value @ Value::Int(_)) => fatal(
+            ErrorType::RuntimeError,
+            format!("exit() status must be from 0 to 255, not `{}`", value),
+        ),
+        Some(value) => wrong_type("exit", 0, "integer", value),
     };
-    env.define(name, func);
+    run_exit_hooks();
+    flush_stdout();
+    error::exit_program(status);
+}
+
+fn input(args: Vec<Value>) -> Value {
+    let prompt = !config::quiet_input() || stdout().is_terminal();
+    for arg in args.iter().filter(|_| prompt) {
+        write_stdout(format_args!("{}", arg));
+    }
+    flush_stdout();
+    let mut input = String::new();
+    if let Err(e) = std::io::stdin().read_line(&mut input) {
+        fatal(
+            ErrorType::RuntimeError,
+            format!("Unable to read input: {}", e),
+        );
+    }
+    Value::String(input.trim().to_string())
 }
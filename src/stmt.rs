@@ -1,59 +1,384 @@
+use crate::crash;
 use crate::env::Env;
-use crate::env::Value;
-use crate::expr::Expr;
+use crate::env::{Mutability, StructDef, Value};
+use crate::error::{ErrorType, fatal, line_fatal};
+use crate::expr::{Expr, TailCall, list_index, map_key};
+use crate::iter::{self, Iter};
+use crate::pattern::{MatchArm, Pattern};
+use crate::stats;
+use crate::symbol::Symbol;
 use crate::token::Token;
+use crate::types::Type;
 use std::cell::RefCell;
+use std::fmt;
 use std::rc::Rc;
 
+thread_local! {
+    /// The names whose `let` or `const` initializer is running.
+    static INITIALIZING: RefCell<Vec<Symbol>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Evaluates the initializer of `name`. `let x = x + 1` reads an outer `x`
+/// if there is one, which is how a variable is shadowed; without one the
+/// lookup fails, and [`is_initializing`] lets the error say why.
+fn initialize(name: Symbol, eval: impl FnOnce() -> Value) -> Value {
+    INITIALIZING.with(|names| names.borrow_mut().push(name));
+    let _done = Initialized;
+    eval()
+}
+
+/// Pops the name pushed by [`initialize`], also when a caught error
+/// unwinds out of the initializer.
+struct Initialized;
+
+impl Drop for Initialized {
+    fn drop(&mut self) {
+        INITIALIZING.with(|names| names.borrow_mut().pop());
+    }
+}
+
+/// Whether `name` is being declared by a `let` or `const` whose
+/// initializer is still running.
+pub fn is_initializing(name: Symbol) -> bool {
+    INITIALIZING.with(|names| names.borrow().contains(&name))
+}
+
+/// `target[index] = value`, for the statement and the expression.
+pub fn assign_index(target: &Value, index: &Value, value: Value) {
+    match target {
+        Value::List(items) => {
+            let mut items = items.borrow_mut();
+            let i = list_index(index, items.len());
+            items[i] = value;
+        }
+        Value::Map(map) => {
+            map.borrow_mut().insert(map_key(index), value);
+        }
+        Value::Tuple(_) => fatal(
+            ErrorType::TypeError,
+            format!(
+                "Cannot assign into tuple `{}`, tuples are immutable",
+                target
+            ),
+        ),
+        _ => fatal(
+            ErrorType::TypeError,
+            format!("`{}` does not support index assignment", target),
+        ),
+    }
+}
+
+/// `target.name = value`, for the statement and the expression.
+pub fn assign_field(target: &Value, name: Symbol, value: Value) {
+    let Value::Struct(instance) = target else {
+        fatal(
+            ErrorType::TypeError,
+            format!("`{}` has no fields, cannot assign `.{}`", target, name),
+        );
+    };
+    instance.borrow_mut().set(name, value);
+}
+
+/// Stops if `ty` names no type, or the initial value of `let name: ty` is
+/// not of that type.
+fn check_declared(name: &Token, ty: Option<Type>, value: &Value, env: &Rc<RefCell<Env>>) {
+    if let Some(ty) = ty {
+        ty.check_known(&env.borrow(), Some(name.line));
+    }
+    if let Some(ty) = ty
+        && !ty.accepts(value)
+    {
+        line_fatal(
+            ErrorType::TypeError,
+            name.line,
+            format!(
+                "`{}` is declared as `{}` but was given {} `{}`",
+                name.lexeme,
+                ty,
+                value.type_name(),
+                value
+            ),
+        );
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ControlFlow {
     Value(Value),
-    Break,
-    Continue,
+    /// `break`, with the label of the loop it leaves if one was given.
+    Break(Option<Symbol>),
+    Continue(Option<Symbol>),
     Return(Value),
+    /// `return f(x)` inside a function: the call still to be made, which
+    /// the caller makes in place of the returning function so that tail
+    /// recursion runs in constant stack space.
+    TailCall(Box<TailCall>),
     None,
 }
 
+/// A function parameter, with the expression for its default value if it
+/// has one. Defaults are evaluated at call time, after the parameters before
+/// them are bound, so `fn f(a, b = a * 2)` works.
+///
+/// `rest` marks a trailing `...args` parameter, which collects the remaining
+/// arguments into a list. `ty` is the annotation of `a: number`, which
+/// each argument is checked against.
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Symbol,
+    pub default: Option<Expr>,
+    pub rest: bool,
+    pub ty: Option<Type>,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rest {
+            write!(f, "...")?;
+        }
+        write!(f, "{}", self.name)?;
+        if let Some(ty) = self.ty {
+            write!(f, ": {}", ty)?;
+        }
+        match &self.default {
+            Some(Expr::String(s)) => write!(f, " = {:?}", s)?,
+            Some(default) => write!(f, " = {}", default)?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// A method in a `class` body. `params` leaves out the leading `self`,
+/// which is bound to the instance when the method is called.
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: Symbol,
+    pub params: Vec<Param>,
+    pub returns: Option<Type>,
+    pub body: Stmt,
+}
+
+/// `struct Point { x, y }`, or a `class` whose body also holds methods.
+#[derive(Debug, Clone)]
+pub struct StructDecl {
+    pub name: Token,
+    pub class: bool,
+    pub fields: Vec<Symbol>,
+    pub methods: Vec<Method>,
+}
+
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Expr(Expr),
-    Let(Token, Expr),
-    Assign(Token, Expr),
+    Semi(Expr),
+    /// `let name = value`, or `let mut name = value` when the flag is set,
+    /// with the type of `let name: number = value` if one is given.
+    Let(Box<Token>, Expr, bool, Option<Type>),
+    /// `const NAME = value`, which cannot be assigned to afterwards.
+    Const(Box<Token>, Expr, Option<Type>),
+    /// `let (a, b) = value` or `let [x, rest...] = value`, with the same
+    /// flag as `Let` and the line it is on.
+    Destructure(Box<Pattern>, Expr, bool, u32),
+    Assign(Box<Token>, Expr),
+    IndexAssign(Box<Expr>, Box<Expr>, Expr),
+    /// `target.field = value`.
+    FieldAssign(Box<Expr>, Symbol, Expr),
     Group(Vec<Stmt>),
     If(Expr, Box<Stmt>, Option<Box<Stmt>>),
     While(Expr, Box<Stmt>),
-    Function(Token, Vec<Token>, Box<Stmt>),
-    Break,
-    Continue,
+    /// `do { body } while condition`, which runs the body at least once.
+    DoWhile(Box<Stmt>, Expr),
+    /// `for i in start..end step n { ... }`; the step is optional.
+    For(
+        Box<Token>,
+        Box<Expr>,
+        Box<Expr>,
+        Option<Box<Expr>>,
+        Box<Stmt>,
+    ),
+    /// `for x in items { ... }`, or `for i, x in items` when the second
+    /// name is given: the index (or map key) and the element (or value).
+    ForIn(Box<Token>, Option<Box<Token>>, Expr, Box<Stmt>),
+    /// `fn name(params) -> type { body }`; the return type is optional.
+    Function(Box<Token>, Vec<Param>, Option<Type>, Box<Stmt>),
+    Struct(Box<StructDecl>),
+    /// `outer: while ...`; only loops are labelled.
+    Labeled(Symbol, Box<Stmt>),
+    Break(Option<Symbol>),
+    Continue(Option<Symbol>),
     Return(Option<Expr>),
+    Match(Expr, Vec<MatchArm>),
+    /// `assert condition, message`, with the line it is on.
+    Assert(Expr, Option<Box<Expr>>, u32),
 }
 
+// Tokens and nested statements are boxed to keep this limit; see the same
+// check on `Expr`.
+const _: () = assert!(std::mem::size_of::<Stmt>() <= 64);
+
 impl Stmt {
+    /// The statement without source positions, for telling whether two
+    /// pieces of code mean the same thing.
+    pub fn shape(&self) -> String {
+        let debug = format!("{:?}", self);
+        let mut out = String::with_capacity(debug.len());
+        let mut rest = debug.as_str();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("line: ") {
+                rest = after.trim_start_matches(|c: char| c.is_ascii_digit());
+            } else if rest.starts_with("span: Span {") {
+                rest = rest.find('}').map_or("", |end| &rest[end + 1..]);
+            } else {
+                let c = rest.chars().next().unwrap();
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        out
+    }
+
+    /// The line the statement starts on, as far as its tokens tell, for
+    /// crash reports.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Expr(expr)
+            | Stmt::Semi(expr)
+            | Stmt::If(expr, ..)
+            | Stmt::While(expr, _)
+            | Stmt::Return(Some(expr))
+            | Stmt::Match(expr, _) => expr.line(),
+            Stmt::IndexAssign(target, ..) | Stmt::FieldAssign(target, ..) => target.line(),
+            Stmt::Let(name, ..)
+            | Stmt::Const(name, ..)
+            | Stmt::Assign(name, _)
+            | Stmt::For(name, ..)
+            | Stmt::ForIn(name, ..)
+            | Stmt::Function(name, ..) => Some(name.line),
+            Stmt::Struct(decl) => Some(decl.name.line),
+            Stmt::Group(stmts) => stmts.first().and_then(Stmt::line),
+            Stmt::DoWhile(body, _) | Stmt::Labeled(_, body) => body.line(),
+            Stmt::Destructure(.., line) | Stmt::Assert(_, _, line) => Some(*line as usize),
+            Stmt::Break(_) | Stmt::Continue(_) | Stmt::Return(None) => None,
+        }
+    }
+
+    /// A short name for the kind of statement, for crash reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Stmt::Expr(_) | Stmt::Semi(_) => "expression",
+            Stmt::Let(..) | Stmt::Destructure(..) => "let",
+            Stmt::Const(..) => "const",
+            Stmt::Assign(..) | Stmt::IndexAssign(..) | Stmt::FieldAssign(..) => "assignment",
+            Stmt::Group(_) => "block",
+            Stmt::If(..) => "if",
+            Stmt::While(..) => "while",
+            Stmt::DoWhile(..) => "do-while",
+            Stmt::For(..) | Stmt::ForIn(..) => "for",
+            Stmt::Function(..) => "fn",
+            Stmt::Struct(decl) if decl.class => "class",
+            Stmt::Struct(_) => "struct",
+            Stmt::Labeled(_, body) => body.kind(),
+            Stmt::Break(_) => "break",
+            Stmt::Continue(_) => "continue",
+            Stmt::Return(_) => "return",
+            Stmt::Match(..) => "match",
+            Stmt::Assert(..) => "assert",
+        }
+    }
+
+    /// The name a top-level definition binds, for reloading a file.
+    pub fn defined_name(&self) -> Option<Symbol> {
+        match self {
+            Stmt::Let(name, ..) | Stmt::Const(name, ..) | Stmt::Function(name, ..) => {
+                Some(Symbol::intern(&name.lexeme))
+            }
+            Stmt::Struct(decl) => Some(Symbol::intern(&decl.name.lexeme)),
+            _ => None,
+        }
+    }
+
     pub fn eval(&self, env: &mut Rc<RefCell<Env>>) -> ControlFlow {
+        stats::statement();
+        crash::record(self);
         match self {
             Stmt::Expr(expr) => ControlFlow::Value(expr.eval(env)),
-            Stmt::Let(token, expr) => {
+            Stmt::Semi(expr) => {
+                expr.eval(env);
+                ControlFlow::None
+            }
+            Stmt::Let(token, expr, mutable, ty) => {
+                let name = Symbol::intern(&token.lexeme);
+                let value = initialize(name, || expr.eval(env));
+                check_declared(token, *ty, &value, env);
+                env.borrow().check_define(name, token.line);
+                env.borrow_mut()
+                    .define_as(name, value.clone(), Mutability::of_let(*mutable), *ty);
+                ControlFlow::Value(value)
+            }
+            Stmt::Const(token, expr, ty) => {
+                let name = Symbol::intern(&token.lexeme);
+                let value = initialize(name, || expr.eval(env));
+                check_declared(token, *ty, &value, env);
+                env.borrow().check_define(name, token.line);
+                env.borrow_mut().define_const(name, value.clone());
+                ControlFlow::Value(value)
+            }
+            Stmt::Destructure(pattern, expr, mutable, line) => {
+                let line = *line as usize;
                 let value = expr.eval(env);
-                env.borrow_mut().define(token.lexeme.clone(), value.clone());
+                let mut bindings = Vec::new();
+                if !pattern.bind(&value, &mut bindings) {
+                    line_fatal(
+                        ErrorType::RuntimeError,
+                        line,
+                        format!("Cannot destructure `{}` into `{}`", value, pattern),
+                    );
+                }
+                for (name, item) in bindings {
+                    env.borrow().check_define(name, line);
+                    env.borrow_mut()
+                        .define_as(name, item, Mutability::of_let(*mutable), None);
+                }
                 ControlFlow::Value(value)
             }
             Stmt::Assign(token, expr) => {
                 let value = expr.eval(env);
-                env.borrow_mut().assign(token.lexeme.clone(), value);
+                env.borrow_mut()
+                    .assign(Symbol::intern(&token.lexeme), value);
+                ControlFlow::None
+            }
+            Stmt::IndexAssign(target, index, expr) => {
+                let target = target.eval(env);
+                let index = index.eval(env);
+                let value = expr.eval(env);
+                assign_index(&target, &index, value);
+                ControlFlow::None
+            }
+            Stmt::FieldAssign(target, name, expr) => {
+                let target = target.eval(env);
+                let value = expr.eval(env);
+                assign_field(&target, *name, value);
                 ControlFlow::None
             }
             Stmt::Group(stmts) => {
                 let mut child_env = Env::child_env(env.clone());
+                let mut last = ControlFlow::None;
                 for stmt in stmts {
                     let res = stmt.eval(&mut child_env);
                     match res {
-                        ControlFlow::Break | ControlFlow::Continue | ControlFlow::Return(_) => {
+                        ControlFlow::Break(_)
+                        | ControlFlow::Continue(_)
+                        | ControlFlow::Return(_)
+                        | ControlFlow::TailCall(_) => {
                             return res;
                         }
-                        _ => {}
+                        // Only a trailing bare expression gives the block its value.
+                        ControlFlow::Value(_) if matches!(stmt, Stmt::Expr(_)) => last = res,
+                        _ => last = ControlFlow::None,
                     }
                 }
-                ControlFlow::None
+                last
             }
             Stmt::If(con, then, else_stmt) => {
                 if con.condition_eval(env) {
@@ -63,36 +388,221 @@ impl Stmt {
                 }
                 ControlFlow::None
             }
-            Stmt::Break => ControlFlow::Break,
-            Stmt::Continue => ControlFlow::Continue,
-            Stmt::While(expr, then) => {
-                while expr.condition_eval(env) {
-                    let res = then.eval(env);
-                    match res {
-                        ControlFlow::Break => break,
-                        ControlFlow::Continue => continue,
-                        ControlFlow::Return(_) => return res,
-                        _ => {}
-                    }
+            Stmt::Match(subject, arms) => {
+                let value = subject.eval(env);
+                MatchArm::eval(arms, value, env).unwrap_or(ControlFlow::None)
+            }
+            Stmt::Break(label) => ControlFlow::Break(*label),
+            Stmt::Continue(label) => ControlFlow::Continue(*label),
+            Stmt::While(..) | Stmt::DoWhile(..) | Stmt::For(..) | Stmt::ForIn(..) => {
+                self.eval_loop(env, None)
+            }
+            Stmt::Labeled(label, stmt) => stmt.eval_loop(env, Some(*label)),
+            Stmt::Assert(condition, message, line) => {
+                if !condition.condition_eval(env) {
+                    // Operators print in parentheses; the outer pair is noise.
+                    let text = condition.to_string();
+                    let text = match condition {
+                        Expr::Binary(..) | Expr::Logic(..) | Expr::Chain(..) => {
+                            &text[1..text.len() - 1]
+                        }
+                        _ => &text,
+                    };
+                    let message = match message {
+                        Some(message) => {
+                            format!("Assertion failed: `{}`: {}", text, message.eval(env))
+                        }
+                        None => format!("Assertion failed: `{}`", text),
+                    };
+                    line_fatal(ErrorType::RuntimeError, *line as usize, message);
                 }
                 ControlFlow::None
             }
             Stmt::Return(expr) => {
                 if let Some(expr) = expr {
-                    return ControlFlow::Return(expr.eval(env));
+                    return expr.eval_return(env);
                 }
                 ControlFlow::Return(Value::Nil)
             }
-            Stmt::Function(name, args, body) => {
-                let function = Value::Function {
+            Stmt::Function(name, params, returns, body) => {
+                let function = stats::alloc(Value::Function {
                     name: name.lexeme.clone(),
-                    params: args.iter().map(|arg| arg.lexeme.clone()).collect(),
+                    params: params.as_slice().into(),
+                    returns: *returns,
                     body: body.clone(),
                     closure: env.clone(),
+                });
+                let symbol = Symbol::intern(&name.lexeme);
+                env.borrow().check_define(symbol, name.line);
+                env.borrow_mut().define(symbol, function);
+                ControlFlow::None
+            }
+            Stmt::Struct(decl) => {
+                let name = Symbol::intern(&decl.name.lexeme);
+                let def = StructDef {
+                    name,
+                    class: decl.class,
+                    fields: decl.fields.clone(),
+                    methods: decl.methods.clone(),
+                    closure: env.clone(),
                 };
-                env.borrow_mut().define(name.lexeme.clone(), function);
+                env.borrow().check_define(name, decl.name.line);
+                env.borrow_mut()
+                    .define(name, Value::StructDef(Rc::new(def)));
                 ControlFlow::None
             }
         }
     }
+
+    /// Runs a loop statement. `label` names the loop, so a labelled `break`
+    /// or `continue` from an inner loop can target it.
+    fn eval_loop(&self, env: &mut Rc<RefCell<Env>>, label: Option<Symbol>) -> ControlFlow {
+        match self {
+            Stmt::While(expr, then) => {
+                while expr.condition_eval(env) {
+                    match Step::after(then.eval(env), label) {
+                        Step::Next => {}
+                        Step::Break => break,
+                        Step::Leave(res) => return res,
+                    }
+                }
+                ControlFlow::None
+            }
+            Stmt::DoWhile(body, expr) => {
+                loop {
+                    match Step::after(body.eval(env), label) {
+                        Step::Next => {}
+                        Step::Break => break,
+                        Step::Leave(res) => return res,
+                    }
+                    if !expr.condition_eval(env) {
+                        break;
+                    }
+                }
+                ControlFlow::None
+            }
+            Stmt::For(var, start, end, step, then) => {
+                let start = start.eval(env);
+                let end = end.eval(env);
+                let step = step.as_ref().map_or(Value::Int(1), |step| step.eval(env));
+                let (Some(first), Some(last), Some(by)) =
+                    (start.as_f64(), end.as_f64(), step.as_f64())
+                else {
+                    fatal(
+                        ErrorType::TypeError,
+                        format!(
+                            "Invalid range `{}..{} step {}`, expected numbers",
+                            start, end, step
+                        ),
+                    );
+                };
+                if by == 0.0 {
+                    fatal(
+                        ErrorType::RuntimeError,
+                        format!("Range `{}..{}` cannot have a step of 0", start, end),
+                    );
+                }
+                // Integer bounds count with integers, anything else with floats.
+                let ints = matches!(
+                    (&start, &end, &step),
+                    (Value::Int(_), Value::Int(_), Value::Int(_))
+                );
+                let var = Symbol::intern(&var.lexeme);
+                let mut loop_env = Env::child_env(env.clone());
+                // Counting steps instead of adding them up keeps float
+                // ranges from drifting.
+                let mut n = 0.0;
+                loop {
+                    let i = first + n * by;
+                    if (by > 0.0 && i >= last) || (by < 0.0 && i <= last) {
+                        break;
+                    }
+                    let value = if ints {
+                        Value::Int(i as i64)
+                    } else {
+                        Value::Number(i)
+                    };
+                    loop_env.borrow_mut().define(var, value);
+                    n += 1.0;
+                    match Step::after(then.eval(&mut loop_env), label) {
+                        Step::Next => {}
+                        Step::Break => break,
+                        Step::Leave(res) => return res,
+                    }
+                }
+                ControlFlow::None
+            }
+            Stmt::ForIn(var, second, iter, then) => {
+                let value = iter.eval(env);
+                let items = match &value {
+                    Value::Map(map) if second.is_some() => Iter::Items(
+                        map.borrow()
+                            .values()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .into_iter(),
+                    ),
+                    value => iter::iter(value).unwrap_or_else(|| {
+                        fatal(ErrorType::TypeError, format!("`{}` is not iterable", value));
+                    }),
+                };
+                // With two names the first gets the key of a map entry, or
+                // the position of anything else.
+                let mut keys: Box<dyn Iterator<Item = Value>> = match &value {
+                    Value::Map(map) => Box::new(
+                        map.borrow()
+                            .keys()
+                            .cloned()
+                            .map(Value::String)
+                            .collect::<Vec<_>>()
+                            .into_iter(),
+                    ),
+                    _ => Box::new((0..).map(Value::Int)),
+                };
+                let var = Symbol::intern(&var.lexeme);
+                let second = second.as_ref().map(|t| Symbol::intern(&t.lexeme));
+                let mut loop_env = Env::child_env(env.clone());
+                for item in items {
+                    match second {
+                        Some(second) => {
+                            loop_env.borrow_mut().define(var, keys.next().unwrap());
+                            loop_env.borrow_mut().define(second, item);
+                        }
+                        None => loop_env.borrow_mut().define(var, item),
+                    }
+                    match Step::after(then.eval(&mut loop_env), label) {
+                        Step::Next => {}
+                        Step::Break => break,
+                        Step::Leave(res) => return res,
+                    }
+                }
+                ControlFlow::None
+            }
+            _ => unreachable!("eval_loop on a statement that is not a loop"),
+        }
+    }
+}
+
+/// What a loop does after its body has run once.
+enum Step {
+    Next,
+    Break,
+    /// Stop the loop and pass the flow on to the enclosing statement.
+    Leave(ControlFlow),
+}
+
+impl Step {
+    fn after(res: ControlFlow, label: Option<Symbol>) -> Self {
+        match res {
+            ControlFlow::Break(None) => Step::Break,
+            ControlFlow::Continue(None) => Step::Next,
+            ControlFlow::Break(Some(target)) if Some(target) == label => Step::Break,
+            ControlFlow::Continue(Some(target)) if Some(target) == label => Step::Next,
+            ControlFlow::Break(_)
+            | ControlFlow::Continue(_)
+            | ControlFlow::Return(_)
+            | ControlFlow::TailCall(_) => Step::Leave(res),
+            ControlFlow::Value(_) | ControlFlow::None => Step::Next,
+        }
+    }
 }
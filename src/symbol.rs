@@ -0,0 +1,50 @@
+use std::{cell::RefCell, collections::HashMap, fmt};
+
+/// An interned identifier. Comparing and hashing a symbol is an integer
+/// operation, and the name itself is stored once per thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&symbol) = interner.ids.get(name) {
+                return symbol;
+            }
+            // Names live for the whole run, so leaking them keeps `as_str`
+            // free of borrows.
+            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+            let symbol = Symbol(interner.names.len() as u32);
+            interner.names.push(name);
+            interner.ids.insert(name, symbol);
+            symbol
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
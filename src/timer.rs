@@ -0,0 +1,156 @@
+use std::{
+    ffi::c_int,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    env::Value,
+    error::{ErrorType, fatal},
+    expr::call_value,
+    std_fn::wrong_type,
+};
+
+const SIGINT: c_int = 2;
+/// How often a sleeping timer looks for Ctrl-C.
+const POLL: Duration = Duration::from_millis(50);
+
+unsafe extern "C" {
+    /// The C library's `signal`, with handlers passed as addresses so the
+    /// previous one can be put back.
+    fn signal(signum: c_int, handler: usize) -> usize;
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Turns Ctrl-C into a flag while a timer runs. Dropping it restores the
+/// previous handler, so Ctrl-C outside `every` and `after` still ends the
+/// script.
+struct InterruptGuard {
+    previous: usize,
+}
+
+impl InterruptGuard {
+    fn new() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let handler = on_interrupt as extern "C" fn(c_int) as usize;
+        // SAFETY: the handler only stores to an atomic.
+        let previous = unsafe { signal(SIGINT, handler) };
+        InterruptGuard { previous }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        // SAFETY: `previous` came from `signal`.
+        unsafe { signal(SIGINT, self.previous) };
+    }
+}
+
+/// Sleeps until `deadline`. Returns false if Ctrl-C was pressed first.
+fn sleep_until(deadline: Instant) -> bool {
+    loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(POLL));
+    }
+}
+
+fn seconds(name: &str, value: &Value) -> Duration {
+    match value.as_f64() {
+        Some(s) if s >= 0.0 && s.is_finite() => {
+            Duration::try_from_secs_f64(s).unwrap_or_else(|_| too_long(name, value))
+        }
+        None => wrong_type(name, 0, "number", value),
+        Some(_) => fatal(
+            ErrorType::RuntimeError,
+            format!(
+                "{}() interval must be a non-negative number of seconds, not `{}`",
+                name, value
+            ),
+        ),
+    }
+}
+
+/// Reports an interval that does not fit a `Duration`, or whose deadline
+/// lies past what the clock can represent.
+fn too_long(name: &str, value: &Value) -> ! {
+    fatal(
+        ErrorType::RuntimeError,
+        format!("{}() interval `{}` is too long", name, value),
+    )
+}
+
+/// `every(seconds, fn)` calls `fn` once per interval until it returns
+/// `false`, the optional tick limit is reached or Ctrl-C is pressed, and
+/// returns the number of calls. Ticks keep to a fixed schedule, so a slow
+/// callback does not make later ones drift.
+pub fn every(args: Vec<Value>) -> Value {
+    if !(2..=3).contains(&args.len()) {
+        fatal(
+            ErrorType::RuntimeError,
+            "every() takes an interval, a function and an optional tick limit".to_string(),
+        );
+    }
+    let interval = seconds("every", &args[0]);
+    let limit = match args.get(2) {
+        None => None,
+        Some(Value::Int(n)) if *n >= 0 => Some(*n),
+        Some(value @ Value::Int(_)) => fatal(
+            ErrorType::RuntimeError,
+            format!(
+                "every() tick limit must be a non-negative integer, not `{}`",
+                value
+            ),
+        ),
+        Some(value) => wrong_type("every", 2, "integer", value),
+    };
+    let _guard = InterruptGuard::new();
+    let mut deadline = Instant::now();
+    let mut ticks = 0;
+    while limit.is_none_or(|limit| ticks < limit) {
+        deadline = deadline
+            .checked_add(interval)
+            .unwrap_or_else(|| too_long("every", &args[0]));
+        if !sleep_until(deadline) {
+            break;
+        }
+        ticks += 1;
+        let result = call_value(args[1].clone(), Vec::new());
+        if matches!(result, Value::Bool(false)) || INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    Value::Int(ticks)
+}
+
+/// `after(seconds, fn)` waits, then calls `fn` and returns its result. Ctrl-C
+/// while waiting cancels the call and returns `nil`.
+pub fn after(args: Vec<Value>) -> Value {
+    if args.len() != 2 {
+        fatal(
+            ErrorType::RuntimeError,
+            "after() takes an interval and a function".to_string(),
+        );
+    }
+    let delay = seconds("after", &args[0]);
+    let deadline = Instant::now()
+        .checked_add(delay)
+        .unwrap_or_else(|| too_long("after", &args[0]));
+    let guard = InterruptGuard::new();
+    if !sleep_until(deadline) {
+        return Value::Nil;
+    }
+    drop(guard);
+    call_value(args[1].clone(), Vec::new())
+}
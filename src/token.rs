@@ -1,3 +1,4 @@
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, Copy, Eq)]
 pub enum TokenType {
     Number,
@@ -10,46 +11,177 @@ pub enum TokenType {
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
+    Colon,
+    Question,
+    QuestionDot,
+    Dot,
+    DotDot,
+    Ellipsis,
     String,
+    InterpStart,
+    InterpMid,
+    InterpEnd,
+    FormatSpec,
     Equal,
+    FatArrow,
+    Arrow,
+    PlusEqual,
+    MinusEqual,
+    /// `++` and `--`, before or after a variable, index or field.
+    PlusPlus,
+    MinusMinus,
+    StarEqual,
+    SlashEqual,
+    ModuloEqual,
     EqualEqual,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
     Bang,
+    /// `^`, only in math mode.
+    Caret,
     BangEqual,
     Ampersand,
     Pipe,
     Let,
+    Const,
     If,
     Else,
     Ident,
     True,
     False,
+    Nil,
     While,
+    For,
     Break,
     Continue,
     Fn,
+    Struct,
+    Class,
     Return,
     EOL,
     EOF,
 }
 
+impl TokenType {
+    /// Words reserved by the language, which cannot name variables.
+    pub fn is_keyword(self) -> bool {
+        matches!(
+            self,
+            TokenType::Let
+                | TokenType::Const
+                | TokenType::If
+                | TokenType::Else
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Nil
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::Fn
+                | TokenType::Return
+                | TokenType::Struct
+                | TokenType::Class
+        )
+    }
+}
+
+/// Words that are keywords only where the grammar expects one, and plain
+/// names everywhere else, so `let match = 1` still works. New keywords go
+/// here unless they could never have been a name.
+pub const CONTEXTUAL_KEYWORDS: &[&str] = &[
+    "in", "match", "mut", "loop", "do", "step", "assert", "mod", "div", "elif",
+];
+
+/// Whether a token of this type can start an operand. An `assert` that
+/// starts a statement, or a `mod` or `div` after an operand, is a keyword
+/// when one follows. After `=`, `.` or the end of the line they are names.
+pub fn starts_operand(next: TokenType) -> bool {
+    matches!(
+        next,
+        TokenType::Ident
+            | TokenType::Number
+            | TokenType::String
+            | TokenType::InterpStart
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::Bang
+            | TokenType::Minus
+            | TokenType::LParen
+            | TokenType::LBracket
+    )
+}
+
+/// Whether `match` followed by tokens of these types starts a match. A
+/// name or literal could not follow a variable, while after `(` or `[` it is
+/// a call or an index unless the closing bracket is followed by the `{` of
+/// the arms. The parser and the formatter both decide this way.
+pub fn match_subject_follows(after: impl IntoIterator<Item = TokenType>) -> bool {
+    let mut after = after.into_iter();
+    let Some(next) = after.next() else {
+        return false;
+    };
+    match next {
+        TokenType::Ident
+        | TokenType::Number
+        | TokenType::String
+        | TokenType::InterpStart
+        | TokenType::True
+        | TokenType::False
+        | TokenType::Nil
+        | TokenType::Bang => true,
+        TokenType::LParen | TokenType::LBracket => {
+            let mut depth = 1;
+            for token_type in after.by_ref() {
+                match token_type {
+                    TokenType::LParen | TokenType::LBracket | TokenType::LBrace => depth += 1,
+                    TokenType::RParen | TokenType::RBracket | TokenType::RBrace => depth -= 1,
+                    TokenType::EOL | TokenType::EOF => return false,
+                    _ => {}
+                }
+                if depth == 0 {
+                    return after.next() == Some(TokenType::LBrace);
+                }
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Byte range of a token in the original source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(s: &str, line: usize, token_type: TokenType) -> Self {
+    pub fn new(s: &str, line: usize, token_type: TokenType, span: Span) -> Self {
         Token {
             token_type,
             lexeme: s.to_string(),
             line,
+            span,
         }
     }
+
+    /// Whether this is the name `word`, as a contextual keyword is lexed.
+    pub fn is_word(&self, word: &str) -> bool {
+        self.token_type == TokenType::Ident && self.lexeme == word
+    }
 }
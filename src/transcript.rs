@@ -0,0 +1,55 @@
+use std::{fs::File, io::Write};
+
+use crate::env::Value;
+
+/// A REPL session written out as a script, for `riku repl --record`. Each
+/// line that runs without a fatal error is kept as written, followed by
+/// its results as `// =>` comments, so running the file repeats the
+/// session.
+pub struct Transcript {
+    path: String,
+    file: File,
+}
+
+impl Transcript {
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Unable to create `{}`: {}", path, e))?;
+        let mut transcript = Transcript {
+            path: path.to_string(),
+            file,
+        };
+        transcript.write("// Recorded riku session. Run it with `riku <file>`.\n")?;
+        Ok(transcript)
+    }
+
+    /// Adds a line of input and the values it produced.
+    pub fn record(&mut self, line: &str, values: &[Value]) -> Result<(), String> {
+        let mut text = format!("{}\n", line);
+        for value in values {
+            for (i, part) in value.to_string().lines().enumerate() {
+                let marker = if i == 0 { "=>" } else { "  " };
+                text.push_str(&format!("// {} {}\n", marker, part));
+            }
+        }
+        self.write(&text)
+    }
+
+    /// Adds a `:load`, which is a REPL command rather than code, as a
+    /// comment followed by the `source` that it ran.
+    pub fn record_load(&mut self, path: &str, source: &str) -> Result<(), String> {
+        let mut text = format!("// :load {}\n", path);
+        text.push_str(source);
+        if !source.is_empty() && !source.ends_with('\n') {
+            text.push('\n');
+        }
+        self.write(&text)
+    }
+
+    /// Writes straight to the file, since a fatal error ends the process
+    /// without unwinding.
+    fn write(&mut self, text: &str) -> Result<(), String> {
+        self.file
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Unable to write `{}`: {}", self.path, e))
+    }
+}
@@ -0,0 +1,649 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    expr::{Expr, Op, arity_message, param_arity},
+    pattern::{MatchArm, Pattern},
+    stmt::{Param, Stmt, StructDecl},
+    symbol::Symbol,
+    types::{Type, unknown_type},
+};
+
+/// A type error found before the program runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Checks a parsed program against its type annotations without running
+/// it, for `riku check`. Reports, in line order:
+///
+/// - a `let`, `const` or assignment whose value is known to be of another
+///   type than the variable was declared with,
+/// - a call with the wrong number of arguments, or an argument known to be
+///   of another type than its parameter,
+/// - a `return` whose value is known to be of another type than the
+///   function declares,
+/// - an annotation naming neither a builtin type nor a struct or class.
+///
+/// Types come from literals, annotations, the declared results of
+/// functions and methods, and operators on known operands. Anything else
+/// is unknown and never an error, so unannotated code passes unchecked.
+pub fn check(stmts: &[Stmt]) -> Vec<TypeError> {
+    let mut checker = Checker {
+        scopes: vec![HashMap::new()],
+        classes: HashMap::new(),
+        functions: Vec::new(),
+        line: 0,
+        errors: Vec::new(),
+    };
+    checker.block(stmts);
+    let mut errors = checker.errors;
+    errors.sort_by_key(|e| e.line);
+    errors
+}
+
+/// The parameters and declared result of a function or method.
+struct Signature {
+    name: String,
+    params: Vec<Param>,
+    returns: Option<Type>,
+}
+
+enum Binding {
+    /// A variable, with its declared type and, if it cannot be reassigned,
+    /// the type of its value.
+    Var {
+        declared: Option<Type>,
+        inferred: Option<Type>,
+    },
+    Function(Rc<Signature>),
+}
+
+struct Checker {
+    scopes: Vec<HashMap<Symbol, Binding>>,
+    /// The methods of each class.
+    classes: HashMap<Symbol, HashMap<Symbol, Rc<Signature>>>,
+    /// The name and declared result of the functions being checked,
+    /// innermost last. Lambdas declare no result.
+    functions: Vec<(String, Option<Type>)>,
+    /// The line of the statement being checked, for errors in expressions
+    /// that have no line of their own, such as literals.
+    line: usize,
+    errors: Vec<TypeError>,
+}
+
+impl Checker {
+    fn error(&mut self, line: Option<usize>, message: String) {
+        self.errors.push(TypeError {
+            line: line.unwrap_or(self.line),
+            message,
+        });
+    }
+
+    fn define(&mut self, name: Symbol, binding: Binding) {
+        self.scopes.last_mut().unwrap().insert(name, binding);
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name))
+    }
+
+    fn scoped(&mut self, check: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        check(self);
+        self.scopes.pop();
+    }
+
+    /// Checks the statements of a block. Functions and classes are
+    /// declared first, so calls before their definition are checked too.
+    fn block(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Function(name, params, returns, _) => {
+                    let signature = Signature {
+                        name: name.lexeme.clone(),
+                        params: params.clone(),
+                        returns: *returns,
+                    };
+                    self.define(
+                        Symbol::intern(&name.lexeme),
+                        Binding::Function(Rc::new(signature)),
+                    );
+                }
+                Stmt::Struct(decl) => self.declare_class(decl),
+                _ => {}
+            }
+        }
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn declare_class(&mut self, decl: &StructDecl) {
+        let methods = decl
+            .methods
+            .iter()
+            .map(|method| {
+                let signature = Signature {
+                    name: method.name.to_string(),
+                    params: method.params.clone(),
+                    returns: method.returns,
+                };
+                (method.name, Rc::new(signature))
+            })
+            .collect();
+        self.classes
+            .insert(Symbol::intern(&decl.name.lexeme), methods);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        if let Some(line) = stmt.line() {
+            self.line = line;
+        }
+        match stmt {
+            Stmt::Expr(expr) | Stmt::Semi(expr) => {
+                self.expr(expr);
+            }
+            Stmt::Let(name, expr, mutable, declared) => {
+                let declared = self.known(name.line, *declared);
+                let actual = self.expr(expr);
+                self.check_declared(name.line, &name.lexeme, declared, actual);
+                let inferred = if *mutable { None } else { actual };
+                self.define(
+                    Symbol::intern(&name.lexeme),
+                    Binding::Var { declared, inferred },
+                );
+            }
+            Stmt::Const(name, expr, declared) => {
+                let declared = self.known(name.line, *declared);
+                let actual = self.expr(expr);
+                self.check_declared(name.line, &name.lexeme, declared, actual);
+                self.define(
+                    Symbol::intern(&name.lexeme),
+                    Binding::Var {
+                        declared,
+                        inferred: actual,
+                    },
+                );
+            }
+            Stmt::Destructure(pattern, expr, ..) => {
+                self.expr(expr);
+                self.bind(pattern);
+            }
+            Stmt::Assign(name, expr) => {
+                let actual = self.expr(expr);
+                self.check_assign(name.line, Symbol::intern(&name.lexeme), actual);
+            }
+            Stmt::IndexAssign(target, index, value) => {
+                self.expr(target);
+                self.expr(index);
+                self.expr(value);
+            }
+            Stmt::FieldAssign(target, _, value) => {
+                self.expr(target);
+                self.expr(value);
+            }
+            Stmt::Group(stmts) => self.scoped(|checker| checker.block(stmts)),
+            Stmt::If(condition, then, otherwise) => {
+                self.expr(condition);
+                self.stmt(then);
+                if let Some(otherwise) = otherwise {
+                    self.stmt(otherwise);
+                }
+            }
+            Stmt::While(condition, body) | Stmt::DoWhile(body, condition) => {
+                self.expr(condition);
+                self.stmt(body);
+            }
+            Stmt::For(name, start, end, step, body) => {
+                self.expr(start);
+                self.expr(end);
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                self.scoped(|checker| {
+                    checker.define(
+                        Symbol::intern(&name.lexeme),
+                        Binding::Var {
+                            declared: None,
+                            inferred: Some(named("number")),
+                        },
+                    );
+                    checker.stmt(body);
+                });
+            }
+            Stmt::ForIn(name, second, items, body) => {
+                self.expr(items);
+                self.scoped(|checker| {
+                    for name in std::iter::once(name).chain(second) {
+                        checker.define(Symbol::intern(&name.lexeme), unknown());
+                    }
+                    checker.stmt(body);
+                });
+            }
+            Stmt::Function(name, params, returns, body) => {
+                self.function(&name.lexeme, params, *returns, None, body);
+            }
+            Stmt::Struct(decl) => {
+                let class = named(&decl.name.lexeme);
+                for method in &decl.methods {
+                    self.function(
+                        method.name.as_str(),
+                        &method.params,
+                        method.returns,
+                        Some(class),
+                        &method.body,
+                    );
+                }
+            }
+            Stmt::Labeled(_, body) => self.stmt(body),
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::Return(expr) => {
+                let actual = match expr {
+                    Some(expr) => self.expr(expr),
+                    None => Some(named("nil")),
+                };
+                if let Some((name, Some(declared))) = self.functions.last()
+                    && let Some(actual) = actual
+                    && !compatible(*declared, actual)
+                {
+                    let message = format!(
+                        "`{}` is declared to return `{}` but returns `{}`",
+                        name, declared, actual
+                    );
+                    self.error(expr.as_ref().and_then(Expr::line), message);
+                }
+            }
+            Stmt::Match(subject, arms) => {
+                self.expr(subject);
+                self.arms(arms);
+            }
+            Stmt::Assert(condition, message, _) => {
+                self.expr(condition);
+                if let Some(message) = message {
+                    self.expr(message);
+                }
+            }
+        }
+    }
+
+    fn check_assign(&mut self, line: usize, name: Symbol, actual: Option<Type>) {
+        let declared = match self.lookup(name) {
+            Some(Binding::Var { declared, .. }) => *declared,
+            _ => None,
+        };
+        if let (Some(declared), Some(actual)) = (declared, actual)
+            && !compatible(declared, actual)
+        {
+            self.error(
+                Some(line),
+                format!(
+                    "Cannot assign `{}` to `{}`, which is declared as `{}`",
+                    actual, name, declared
+                ),
+            );
+        }
+    }
+
+    fn check_declared(
+        &mut self,
+        line: usize,
+        name: &str,
+        declared: Option<Type>,
+        actual: Option<Type>,
+    ) {
+        if let (Some(declared), Some(actual)) = (declared, actual)
+            && !compatible(declared, actual)
+        {
+            self.error(
+                Some(line),
+                format!(
+                    "`{}` is declared as `{}` but is given `{}`",
+                    name, declared, actual
+                ),
+            );
+        }
+    }
+
+    /// Checks the body of a function, method (with the type of `self`) or
+    /// lambda in a scope holding its parameters.
+    fn function(
+        &mut self,
+        name: &str,
+        params: &[Param],
+        returns: Option<Type>,
+        receiver: Option<Type>,
+        body: &Stmt,
+    ) {
+        let returns = self.known(self.line, returns);
+        self.functions.push((name.to_string(), returns));
+        self.scoped(|checker| {
+            if let Some(receiver) = receiver {
+                checker.define(
+                    Symbol::intern("self"),
+                    Binding::Var {
+                        declared: None,
+                        inferred: Some(receiver),
+                    },
+                );
+            }
+            for param in params {
+                let declared = checker.known(checker.line, param.ty);
+                if let Some(default) = &param.default {
+                    let actual = checker.expr(default);
+                    checker.check_declared(
+                        default.line().unwrap_or(checker.line),
+                        param.name.as_str(),
+                        declared,
+                        actual,
+                    );
+                }
+                let inferred = param.rest.then(|| named("list"));
+                checker.define(param.name, Binding::Var { declared, inferred });
+            }
+            checker.stmt(body);
+        });
+        self.functions.pop();
+    }
+
+    fn arms(&mut self, arms: &[MatchArm]) {
+        for arm in arms {
+            self.scoped(|checker| {
+                for pattern in &arm.patterns {
+                    checker.bind(pattern);
+                }
+                if let Some(guard) = &arm.guard {
+                    checker.expr(guard);
+                }
+                checker.stmt(&arm.body);
+            });
+        }
+    }
+
+    /// Defines the names a pattern binds. Only `number n` tells the type.
+    fn bind(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Type(name, Some(bound)) => self.define(
+                *bound,
+                Binding::Var {
+                    declared: None,
+                    inferred: Some(named(name)),
+                },
+            ),
+            Pattern::Bind(name) => self.define(*name, unknown()),
+            Pattern::Tuple(items) => items.iter().for_each(|item| self.bind(item)),
+            Pattern::List(items, rest) => {
+                items.iter().for_each(|item| self.bind(item));
+                if let Some(rest) = rest {
+                    self.define(
+                        *rest,
+                        Binding::Var {
+                            declared: None,
+                            inferred: Some(named("list")),
+                        },
+                    );
+                }
+            }
+            Pattern::Struct(_, fields) => fields.iter().for_each(|(_, field)| self.bind(field)),
+            Pattern::Literal(_) | Pattern::Type(_, None) | Pattern::Wildcard => {}
+        }
+    }
+
+    /// Checks an expression and gives its type, if it is known.
+    fn expr(&mut self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Int(_) | Expr::Number(_) => Some(named("number")),
+            Expr::Bool(_) => Some(named("bool")),
+            Expr::Nil => Some(named("nil")),
+            Expr::String(_) => Some(named("string")),
+            Expr::Binary(left, op, right) => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                binary(op, left, right)
+            }
+            Expr::Logic(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+                Some(named("bool"))
+            }
+            Expr::Chain(first, rest) => {
+                self.expr(first);
+                for (_, operand) in rest.iter() {
+                    self.expr(operand);
+                }
+                Some(named("bool"))
+            }
+            Expr::Unary(op, operand) => {
+                let operand = self.expr(operand);
+                match op {
+                    Op::Not => Some(named("bool")),
+                    _ => operand.filter(|t| is(*t, "number") || is(*t, "decimal")),
+                }
+            }
+            Expr::Group(inner) => self.expr(inner),
+            Expr::Variable { name, .. } => match self.lookup(*name) {
+                Some(Binding::Var { declared, inferred }) => declared.or(*inferred),
+                Some(Binding::Function(_)) => Some(named("function")),
+                None => None,
+            },
+            Expr::Call { callee, args, line } => self.call(callee, args, *line as usize),
+            Expr::List(items) => {
+                items.iter().for_each(|item| {
+                    self.expr(item);
+                });
+                Some(named("list"))
+            }
+            Expr::Spread(inner) => {
+                self.expr(inner);
+                None
+            }
+            Expr::Tuple(items) => {
+                items.iter().for_each(|item| {
+                    self.expr(item);
+                });
+                Some(named("tuple"))
+            }
+            Expr::Map(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+                Some(named("map"))
+            }
+            Expr::Index(target, index) | Expr::OptionalIndex(target, index) => {
+                self.expr(target);
+                self.expr(index);
+                None
+            }
+            Expr::Field(target, _) | Expr::OptionalField(target, _) => {
+                self.expr(target);
+                None
+            }
+            Expr::StructLit(name, fields) => {
+                for (_, value) in fields.iter() {
+                    self.expr(value);
+                }
+                match &**name {
+                    Expr::Variable { name, .. } => Some(Type {
+                        name: *name,
+                        optional: false,
+                    }),
+                    _ => None,
+                }
+            }
+            Expr::Ternary(condition, then, otherwise) => {
+                self.expr(condition);
+                let then = self.expr(then);
+                let otherwise = self.expr(otherwise);
+                then.filter(|_| then == otherwise)
+            }
+            Expr::Format(inner, _) => {
+                self.expr(inner);
+                Some(named("string"))
+            }
+            Expr::Match(subject, arms) => {
+                self.expr(subject);
+                self.arms(arms);
+                None
+            }
+            Expr::If(condition, then, otherwise) => {
+                self.expr(condition);
+                self.stmt(then);
+                if let Some(otherwise) = otherwise {
+                    self.stmt(otherwise);
+                }
+                None
+            }
+            Expr::Assign(target, value) => {
+                let actual = self.expr(value);
+                match &**target {
+                    Expr::Variable { name, line, .. } => self.check_assign(*line, *name, actual),
+                    target => {
+                        self.expr(target);
+                    }
+                }
+                actual
+            }
+            Expr::CompoundAssign { target, op, value } => {
+                let current = self.expr(target);
+                let value = self.expr(value);
+                let actual = binary(op, current, value);
+                if let Expr::Variable { name, line, .. } = &**target {
+                    self.check_assign(*line, *name, actual);
+                }
+                actual
+            }
+            Expr::Increment { target, .. } => self
+                .expr(target)
+                .filter(|t| is(*t, "number") || is(*t, "decimal")),
+            Expr::Capture { lambda, .. } => self.expr(lambda),
+            Expr::Lambda(params, body) => {
+                self.function("lambda", params, None, None, body);
+                Some(named("function"))
+            }
+        }
+    }
+
+    /// Checks a call against the signature of the function or method it
+    /// calls, when that is known, and gives its declared result.
+    fn call(&mut self, callee: &Expr, args: &[Expr], line: usize) -> Option<Type> {
+        let signature = match callee {
+            Expr::Variable { name, .. } => match self.lookup(*name) {
+                Some(Binding::Function(signature)) => Some(signature.clone()),
+                _ => None,
+            },
+            Expr::Field(target, method) => self.expr(target).and_then(|class| {
+                self.classes
+                    .get(&class.name)
+                    .filter(|_| !class.optional)
+                    .and_then(|methods| methods.get(method).cloned())
+            }),
+            callee => {
+                self.expr(callee);
+                None
+            }
+        };
+        let actual = args.iter().map(|arg| self.expr(arg)).collect::<Vec<_>>();
+        let signature = signature?;
+        // A spread hides how many arguments follow it.
+        let spread = args.iter().position(|arg| matches!(arg, Expr::Spread(_)));
+        let arity = param_arity(&signature.params);
+        if spread.is_none() && !arity.accepts(args.len()) {
+            let message = arity_message(&signature.name, arity, args.len());
+            self.error(Some(line), message);
+        }
+        let checked = spread.unwrap_or(args.len());
+        for (param, actual) in signature.params.iter().zip(&actual[..checked]) {
+            if param.rest {
+                break;
+            }
+            if let (Some(declared), Some(actual)) = (param.ty, *actual)
+                && self.is_known(declared)
+                && !compatible(declared, actual)
+            {
+                let message = format!(
+                    "Parameter `{}` of `{}` is declared as `{}` but is given `{}`",
+                    param.name, signature.name, declared, actual
+                );
+                self.error(Some(line), message);
+            }
+        }
+        signature.returns.filter(|ty| self.is_known(*ty))
+    }
+
+    /// Whether `ty` is a builtin type or a struct or class of the program.
+    fn is_known(&self, ty: Type) -> bool {
+        ty.is_builtin() || self.classes.contains_key(&ty.name)
+    }
+
+    /// The annotation `ty`, or `None` after reporting it if it names no
+    /// type, so that it is not checked against any value.
+    fn known(&mut self, line: usize, ty: Option<Type>) -> Option<Type> {
+        let ty = ty?;
+        if self.is_known(ty) {
+            return Some(ty);
+        }
+        self.error(Some(line), unknown_type(ty.name));
+        None
+    }
+}
+
+fn named(name: &str) -> Type {
+    Type {
+        name: Symbol::intern(name),
+        optional: false,
+    }
+}
+
+fn unknown() -> Binding {
+    Binding::Var {
+        declared: None,
+        inferred: None,
+    }
+}
+
+fn is(ty: Type, name: &str) -> bool {
+    !ty.optional && ty.name.as_str() == name
+}
+
+/// The type of `left op right`, when the operands' types decide it.
+fn binary(op: &Op, left: Option<Type>, right: Option<Type>) -> Option<Type> {
+    let (left, right) = (left?, right?);
+    let numeric = |t: Type| is(t, "number") || is(t, "decimal");
+    match op {
+        Op::Add if is(left, "string") || is(right, "string") => Some(named("string")),
+        Op::Mul if is(left, "string") || is(right, "string") => Some(named("string")),
+        Op::Mul if is(left, "list") || is(right, "list") => Some(named("list")),
+        Op::Add | Op::Sub | Op::Mul | Op::Div | Op::FloorDiv | Op::Mod | Op::Pow
+            if numeric(left) && numeric(right) =>
+        {
+            if is(left, "decimal") || is(right, "decimal") {
+                Some(named("decimal"))
+            } else {
+                Some(named("number"))
+            }
+        }
+        Op::Eq | Op::Ne | Op::Lt | Op::Le | Op::Gt | Op::Ge => Some(named("bool")),
+        _ => None,
+    }
+}
+
+/// Whether a value of type `actual` may be stored where `declared` is
+/// expected. `number?` may hold `nil`, but not the other way around.
+fn compatible(declared: Type, actual: Type) -> bool {
+    let (declared_name, actual_name) = (declared.name.as_str(), actual.name.as_str());
+    if declared_name == "any" || actual_name == "any" {
+        return true;
+    }
+    if actual_name == "nil" {
+        return declared.optional || declared_name == "nil";
+    }
+    if actual.optional && !declared.optional {
+        return false;
+    }
+    match declared_name {
+        "struct" => actual_name == "struct" || !actual.is_builtin(),
+        _ => declared_name == actual_name,
+    }
+}
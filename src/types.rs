@@ -0,0 +1,82 @@
+use std::fmt;
+
+use crate::{
+    env::{Env, Value},
+    error::{ErrorType, fatal, line_fatal},
+    pattern::TYPE_NAMES,
+    symbol::Symbol,
+};
+
+/// A type annotation, as in `let x: number = 5` or `fn f(s: string) -> bool`.
+///
+/// The name is one of the type names of `Value::type_name`, `nil`, `any`,
+/// or the name of a struct or class, whose instances it then accepts.
+/// `name?` also accepts `nil`. Annotations are checked when a value is
+/// bound, assigned, passed or returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Type {
+    pub name: Symbol,
+    pub optional: bool,
+}
+
+impl Type {
+    /// Whether a value of this type may be stored where it is declared.
+    pub fn accepts(&self, value: &Value) -> bool {
+        if self.optional && matches!(value, Value::Nil) {
+            return true;
+        }
+        match (self.name.as_str(), value) {
+            ("any", _) => true,
+            (name, Value::Struct(instance)) if !self.is_builtin() => {
+                instance.borrow().def.name.as_str() == name
+            }
+            (name, value) => value.type_name() == name,
+        }
+    }
+
+    /// Whether the name is a builtin type rather than a struct or class.
+    pub fn is_builtin(&self) -> bool {
+        matches!(self.name.as_str(), "any" | "nil" | "struct")
+            || TYPE_NAMES.contains(&self.name.as_str())
+    }
+
+    /// Stops the script if the name is neither a builtin type nor a struct
+    /// or class that `env` can see, as in `let x: int = 1`.
+    pub fn check_known(&self, env: &Env, line: Option<usize>) {
+        if self.is_builtin() || matches!(env.get(self.name), Some(Value::StructDef(_))) {
+            return;
+        }
+        let message = unknown_type(self.name);
+        match line {
+            Some(line) => line_fatal(ErrorType::TypeError, line, message),
+            None => fatal(ErrorType::TypeError, message),
+        }
+    }
+}
+
+/// The error for an annotation that names no type, pointing at the riku
+/// name of types that other languages spell this way.
+pub fn unknown_type(name: Symbol) -> String {
+    let riku_name = match name.as_str() {
+        "int" | "integer" | "float" | "double" => Some("number"),
+        "str" => Some("string"),
+        "boolean" => Some("bool"),
+        "array" => Some("list"),
+        "dict" => Some("map"),
+        _ => None,
+    };
+    match riku_name {
+        Some(riku_name) => format!("Unknown type `{}`; use `{}`", name, riku_name),
+        None => format!("Unknown type `{}`", name),
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.optional {
+            write!(f, "?")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::{path::Path, process::Command};
+
+use crate::error::exit;
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/Sanu-2004/riku/releases/latest";
+
+/// Replaces the running binary with the latest GitHub release, if it is
+/// newer. Release assets are named `riku-<arch>-<os>`, with `.exe` on Windows.
+pub fn self_update() {
+    let release = curl(&["-fsSL", LATEST_RELEASE]).unwrap_or_else(|e| fail(&e));
+    let release = String::from_utf8_lossy(&release);
+    let Some(tag) = json_string(&release, "tag_name") else {
+        fail("the release has no tag");
+    };
+    let latest = tag.trim_start_matches('v');
+    let current = env!("CARGO_PKG_VERSION");
+    if latest == current {
+        println!("riku {} is up to date", current);
+        return;
+    }
+
+    let asset = format!(
+        "riku-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    );
+    let Some(url) = release
+        .split("\"browser_download_url\"")
+        .skip(1)
+        .filter_map(string_value)
+        .find(|url| url.ends_with(&format!("/{}", asset)))
+    else {
+        fail(&format!("release {} has no `{}` binary", tag, asset));
+    };
+
+    let exe = std::env::current_exe().unwrap_or_else(|e| fail(&e.to_string()));
+    let download = exe.with_extension("download");
+    let download_path = download.to_string_lossy();
+    curl(&["-fsSL", "-o", &download_path, &url]).unwrap_or_else(|e| fail(&e));
+    replace(&exe, &download).unwrap_or_else(|e| {
+        let _ = std::fs::remove_file(&download);
+        fail(&e.to_string());
+    });
+    println!("updated riku {} -> {}", current, latest);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("self update failed: {}", message);
+    std::process::exit(exit::UNAVAILABLE);
+}
+
+fn curl(args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("curl")
+        .args(args)
+        .output()
+        .map_err(|e| format!("unable to run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(output.stdout)
+}
+
+/// Finds `"key": "value"` in a JSON document. Good enough for the flat
+/// strings in a GitHub release.
+fn json_string(json: &str, key: &str) -> Option<String> {
+    let start = json.find(&format!("\"{}\"", key))? + key.len() + 2;
+    string_value(&json[start..])
+}
+
+/// Reads the `: "value"` that follows a key.
+fn string_value(rest: &str) -> Option<String> {
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_string())
+}
+
+fn replace(exe: &Path, download: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(download, std::fs::Permissions::from_mode(0o755))?;
+    }
+    // A running executable cannot be overwritten on Windows, but it can be
+    // renamed out of the way.
+    let old = exe.with_extension("old");
+    let _ = std::fs::remove_file(&old);
+    std::fs::rename(exe, &old)?;
+    if let Err(e) = std::fs::rename(download, exe) {
+        std::fs::rename(&old, exe)?;
+        return Err(e);
+    }
+    let _ = std::fs::remove_file(&old);
+    Ok(())
+}
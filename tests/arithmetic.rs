@@ -0,0 +1,85 @@
+mod common;
+
+use common::{output, run};
+
+#[test]
+fn div_and_mod_round_toward_negative_infinity() {
+    let out = output(
+        "println(-7 div 2, \" \", 7 div -2, \" \", -7 div -2, \" \", 7 div 2)
+println(-7 mod 2, \" \", 7 mod -2, \" \", -7 mod -2, \" \", 7 mod 2)
+println(-8 div 2, \" \", -8 mod 2)
+",
+    );
+    assert_eq!(out, "-4 -4 3 3\n1 -1 -1 1\n-4 0\n");
+}
+
+#[test]
+fn div_floors_floats() {
+    let out = output(
+        "println(7.5 div 2, \" \", -7.5 div 2, \" \", 7.5 div -2, \" \", -7 div 2.0)
+println(7.5 mod 2)
+",
+    );
+    assert_eq!(out, "3 -4 -4 -4\n1.5\n");
+}
+
+#[test]
+fn mod_floors_floats() {
+    let out = output(
+        "println(-7.0 mod 2, \" \", 7.0 % -2.0, \" \", 7.5 mod -2, \" \", -7.5 mod -2)
+",
+    );
+    assert_eq!(out, "1 -1 -0.5 -1.5\n");
+}
+
+#[test]
+fn div_and_mod_of_floats_rebuild_the_dividend() {
+    let out = output(
+        "for a in [7.5, -7.5, 7.0, -7.0, 0.5, -0.25] {
+    for b in [2.0, -2.0, 0.5, -3.0] {
+        if (a div b) * b + (a mod b) != a {
+            println(a, \" \", b)
+        }
+    }
+}
+println(\"ok\")
+",
+    );
+    assert_eq!(out, "ok\n");
+}
+
+#[test]
+fn div_and_mod_floor_decimals() {
+    let out = output(
+        "let a = decimal(\"-7.5\")
+let b = decimal(\"7.5\")
+println(a div 2, \" \", b div decimal(\"-2\"), \" \", b div 2)
+println(a mod 2, \" \", b mod decimal(\"-2\"), \" \", b mod 2)
+",
+    );
+    assert_eq!(out, "-4 -4 3\n0.5 -0.5 1.5\n");
+}
+
+#[test]
+fn div_of_the_smallest_integer_by_minus_one_becomes_a_float() {
+    let out = output(
+        "let min = -9223372036854775807 - 1
+println(min div -1 == 9223372036854775808.0)
+println(min mod -1)
+",
+    );
+    assert_eq!(out, "true\n0\n");
+}
+
+#[test]
+fn div_and_mod_by_zero_are_errors() {
+    for source in [
+        "println(7 div 0)\n",
+        "println(7 mod 0)\n",
+        "println(decimal(\"1\") div 0)\n",
+    ] {
+        let run = run(source);
+        assert_eq!(run.code, 70, "{}", source);
+        assert!(run.stderr.contains("Division by zero"), "{}", run.stderr);
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::{output, run};
+
+#[test]
+fn compound_assignment_evaluates_an_index_once() {
+    let out = output(
+        "let mut calls = 0
+fn next() {
+    calls += 1
+    return 0
+}
+let xs = [10, 20]
+xs[next()] += 1
+println(calls, \" \", xs)
+let y = (xs[next()] *= 2)
+println(calls, \" \", y)
+",
+    );
+    assert_eq!(out, "1 [11, 20]\n2 22\n");
+}
+
+#[test]
+fn compound_assignment_evaluates_an_instance_once() {
+    let out = output(
+        "struct P { f }
+let p = P { f: 1 }
+let mut calls = 0
+fn get() {
+    calls += 1
+    return p
+}
+get().f += 5
+println(calls, \" \", p.f)
+",
+    );
+    assert_eq!(out, "1 6\n");
+}
+
+#[test]
+fn compound_assignment_on_variables_and_maps() {
+    let out = output(
+        "let mut s = \"a\"
+s += \"b\"
+let m = {\"k\": 1}
+m[\"k\"] -= 3
+println(s, \" \", m[\"k\"])
+",
+    );
+    assert_eq!(out, "ab -2\n");
+}
+
+#[test]
+fn destructuring_the_wrong_shape_reports_its_line() {
+    for source in [
+        "let x = 1\nlet (a, b) = (1, 2, 3)\n",
+        "let x = 1\nlet [a, b] = [1]\n",
+        "let x = 1\nlet mut (a, b) = [1, 2]\n",
+    ] {
+        let run = run(source);
+        assert_eq!(run.code, 70, "{}", source);
+        assert!(
+            run.stderr.contains("line: 2: Cannot destructure"),
+            "{}: {}",
+            source,
+            run.stderr
+        );
+    }
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn call_results_can_be_called() {
+    let out = output(
+        "fn adder(a) {
+    return |b| |c| a + b + c
+}
+println(adder(1)(2)(3))
+let twice = adder(0)
+println(twice(2)(2))
+",
+    );
+    assert_eq!(out, "6\n4\n");
+}
+
+#[test]
+fn functions_in_lists_maps_and_groups_can_be_called() {
+    let out = output(
+        "let handlers = [|x| x + 1, |x| x * 10]
+let named = {\"neg\": |x| -x}
+println(handlers[1](3), \" \", named[\"neg\"](2), \" \", (|x| x - 1)(10))
+",
+    );
+    assert_eq!(out, "30 -2 9\n");
+}
+
+#[test]
+fn called_results_work_in_tail_position() {
+    let out = output(
+        "fn id(f) {
+    return f
+}
+fn down(n) {
+    if n == 0 {
+        return \"done\"
+    }
+    return id(down)(n - 1)
+}
+println(down(50000))
+",
+    );
+    assert_eq!(out, "done\n");
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{run, run_with};
+
+#[test]
+fn requires_stops_builtins_outside_the_header() {
+    let run = run("//! requires: stdout\nprintln(\"hi\")\ninput()\n");
+    assert_eq!(run.code, 70);
+    assert_eq!(run.stdout, "hi\n");
+    assert!(
+        run.stderr.contains("`input` needs the `stdin` capability"),
+        "{}",
+        run.stderr
+    );
+}
+
+/// A plugin with one function, `hi`, that always answers `"hi"`.
+#[cfg(unix)]
+fn plugin() -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = common::script(
+        "#!/bin/sh\n\
+         echo '{\"riku_plugin\": 1, \"functions\": [\"hi\"]}'\n\
+         while read -r line; do echo '{\"ok\": \"hi\"}'; done\n",
+    );
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn plugins_need_the_plugins_capability() {
+    let plugin = plugin();
+    let plugin = plugin.to_str().unwrap();
+
+    let denied = run_with(
+        &["--plugin", plugin],
+        "//! requires: stdout\nprintln(hi())\n",
+    );
+    assert_eq!(denied.code, 70);
+    assert!(
+        denied
+            .stderr
+            .contains("`hi` needs the `plugins` capability"),
+        "{}",
+        denied.stderr
+    );
+
+    let granted = run_with(
+        &["--plugin", plugin],
+        "//! requires: stdout, plugins\nprintln(hi())\n",
+    );
+    assert_eq!(granted.code, 0, "{}", granted.stderr);
+    assert_eq!(granted.stdout, "hi\n\n");
+
+    let undeclared = run_with(&["--plugin", plugin], "println(hi())\n");
+    assert_eq!(undeclared.stdout, "hi\n\n");
+    let _ = std::fs::remove_file(plugin);
+}
@@ -0,0 +1,76 @@
+//! Runs scripts through the `riku` binary, the way users run them.
+
+#![allow(dead_code)]
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// What a finished run printed, and its exit code.
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    pub code: i32,
+}
+
+/// Runs `source` as a script file.
+pub fn run(source: &str) -> Run {
+    run_with(&[], source)
+}
+
+/// Runs `source` as a script file, passing `args` before its path.
+pub fn run_with(args: &[&str], source: &str) -> Run {
+    let path = script(source);
+    let run = riku(args.iter().copied().chain([path.to_str().unwrap()]), "");
+    let _ = std::fs::remove_file(path);
+    run
+}
+
+/// Runs `riku` with `args`, writing `stdin` to its input.
+pub fn riku<'a>(args: impl IntoIterator<Item = &'a str>, stdin: &str) -> Run {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_riku"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("riku starts");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        code: output.status.code().unwrap_or(-1),
+    }
+}
+
+/// Writes `source` to a file of its own in the temporary directory.
+pub fn script(source: impl AsRef<[u8]>) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "riku-test-{}-{}.riku",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+/// The output of a script that must succeed, without the newline `riku`
+/// prints at the end of a file.
+pub fn output(source: &str) -> String {
+    let run = run(source);
+    assert_eq!(run.code, 0, "script failed: {}", run.stderr);
+    run.stdout
+        .strip_suffix('\n')
+        .unwrap_or(&run.stdout)
+        .to_string()
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::{output, riku, run, script};
+
+/// A script touching the places where a line ending matters: comments,
+/// blocks, a multi-line list and a multi-line string, which must not keep
+/// the `\r`.
+const SCRIPT: &str = "// comment
+let xs = [
+    1,
+    2,
+]
+let text = \"\"\"
+one
+two\"\"\"
+if len(xs) == 2 {
+    println(text.split(\"\\n\"))
+} else {
+    println(\"no\")
+}
+println(\"${xs[0]}|\")
+";
+
+#[test]
+fn crlf_scripts_print_what_lf_scripts_print() {
+    let lf = output(SCRIPT);
+    assert_eq!(output(&SCRIPT.replace('\n', "\r\n")), lf);
+    assert_eq!(lf, "[\"one\", \"two\"]\n1|\n");
+}
+
+#[test]
+fn crlf_errors_name_the_same_line() {
+    let source = "let a = 1\n\nlet b = a +\n";
+    let lf = run(source);
+    let crlf = run(&source.replace('\n', "\r\n"));
+    assert_eq!(lf.code, 65);
+    assert_eq!((crlf.code, crlf.stderr), (lf.code, lf.stderr));
+}
+
+#[test]
+fn crlf_example_reads_crlf_input() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/example9");
+    let source = std::fs::read(path).unwrap();
+    assert!(source.windows(2).any(|w| w == b"\r\n"));
+    let run = riku([path], "Ann\r\n");
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert!(run.stdout.contains("hello Ann\n"), "{:?}", run.stdout);
+    let lf = script(String::from_utf8(source).unwrap().replace("\r\n", "\n"));
+    let lf_run = riku([lf.to_str().unwrap()], "Ann\n");
+    let _ = std::fs::remove_file(&lf);
+    assert_eq!(run.stdout, lf_run.stdout);
+}
+
+#[test]
+fn crlf_repl_input_runs_like_lf_input() {
+    let input = "let x = 1\nprintln(x + 1)\n";
+    let lf = riku(["repl"], input);
+    let crlf = riku(["repl"], &input.replace('\n', "\r\n"));
+    assert_eq!(crlf.code, 0, "{}", crlf.stderr);
+    assert_eq!(crlf.stdout, lf.stdout);
+    assert!(crlf.stdout.contains("2\n"), "{:?}", crlf.stdout);
+}
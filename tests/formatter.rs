@@ -0,0 +1,164 @@
+use riku::formatter::{BraceStyle, FormatConfig, format, format_with, verify, verify_with};
+use std::{fs, path::PathBuf};
+
+/// Formats `input`, which is already formatted, and checks that it stays
+/// the same.
+fn assert_unchanged(input: &str) {
+    assert_eq!(format(input), input);
+}
+
+/// The example scripts, with their paths. The prebuilt binaries kept next
+/// to them are not text and are left out.
+fn examples() -> Vec<(PathBuf, String)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut examples: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter_map(|path| Some((path.clone(), fs::read_to_string(path).ok()?)))
+        .collect();
+    examples.sort();
+    assert!(!examples.is_empty());
+    examples
+}
+
+fn brace_style(brace_style: BraceStyle) -> FormatConfig {
+    FormatConfig {
+        brace_style,
+        ..FormatConfig::default()
+    }
+}
+
+#[test]
+fn examples_verify_in_both_brace_styles() {
+    let (same_line, next_line) = (
+        brace_style(BraceStyle::SameLine),
+        brace_style(BraceStyle::NextLine),
+    );
+    for (path, source) in examples() {
+        for config in [&same_line, &next_line] {
+            if let Err(message) = verify_with(&source, config) {
+                panic!("{}: {}", path.display(), message);
+            }
+        }
+        let formatted = format_with(&source, &same_line);
+        assert_eq!(
+            format_with(&format_with(&source, &next_line), &same_line),
+            formatted,
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn contextual_keywords_keep_keyword_spacing() {
+    assert_unchanged("for x in [1, 2] {\n    println(x)\n}\n");
+    assert_unchanged("for i, x in [1, 2] {\n    println(i)\n}\n");
+    assert_unchanged("for i in 10..0 step -3 {\n    println(i)\n}\n");
+    assert_unchanged("let mut xs = [1]\n");
+    assert_unchanged("let y = match (1) {\n    _ => 2,\n}\n");
+}
+
+#[test]
+fn contextual_keywords_keep_name_spacing() {
+    assert_unchanged("let in = [1]\nprintln(in[0])\n");
+    assert_unchanged("let step = 1\nprintln(step - 1)\n");
+    assert_unchanged("let match = print\nmatch(1)\n");
+    assert_unchanged("let mut = 1\nprintln(mut + 1)\n");
+}
+
+#[test]
+fn contextual_keywords_are_spaced_from_tight_input() {
+    assert_eq!(
+        format("for x in[1]{\nprintln(x)\n}\n"),
+        "for x in [1] {\n    println(x)\n}\n"
+    );
+    assert_eq!(
+        format("let mut[a, b] = [1, 2]\n"),
+        "let mut [a, b] = [1, 2]\n"
+    );
+}
+
+#[test]
+fn elif_is_kept_with_its_if_in_both_brace_styles() {
+    let same_line =
+        "if x {\n    println(1)\n} elif x > 1 {\n    println(2)\n} else {\n    println(3)\n}\n";
+    let next_line = "if x\n{\n    println(1)\n}\nelif x > 1\n{\n    println(2)\n}\nelse\n{\n    println(3)\n}\n";
+    let config = brace_style(BraceStyle::NextLine);
+    assert_unchanged(same_line);
+    assert_eq!(format_with(same_line, &config), next_line);
+    assert_eq!(format(next_line), same_line);
+}
+
+#[test]
+fn elif_as_a_name_is_not_joined_to_a_block() {
+    assert_unchanged("let mut elif = 1\nif elif > 0 {\n    println(elif)\n}\nelif = 2\n");
+}
+
+/// A xorshift generator, so that every run checks the same expressions.
+struct Rng(u64);
+
+impl Rng {
+    /// A number below `n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+const BINARY: &[&str] = &[
+    "+", "-", "*", "/", "%", "mod", "div", "==", "!=", "<", "<=", ">", ">=", "&", "|",
+];
+
+const LEAVES: &[&str] = &["a", "b", "1", "2.5", "\"s\"", "nil", "f(a)", "xs[0]", "p.x"];
+
+/// A random expression up to `depth` operators deep, written with as few
+/// spaces as the tokens allow or with the usual spacing, and with groups
+/// only where the generator happens to put them.
+fn expression(rng: &mut Rng, depth: usize) -> String {
+    if depth == 0 || rng.below(5) == 0 {
+        return rng.pick(LEAVES).to_string();
+    }
+    match rng.below(8) {
+        0 => format!("({})", expression(rng, depth - 1)),
+        1 => format!("-{}", rng.pick(&["a", "1", "f(a)", "(a + b)"])),
+        2 => format!("!{}", rng.pick(&["a", "f(a)", "(a & b)"])),
+        3 => format!(
+            "{} ? {} : {}",
+            expression(rng, depth - 1),
+            expression(rng, depth - 1),
+            expression(rng, depth - 1)
+        ),
+        _ => {
+            let (left, op, right) = (
+                expression(rng, depth - 1),
+                rng.pick(BINARY),
+                expression(rng, depth - 1),
+            );
+            let word = op.chars().all(char::is_alphabetic);
+            // Without spaces `a - -b` would read as `a--b`.
+            if word || right.starts_with(['-', '!']) || rng.below(2) == 0 {
+                format!("{} {} {}", left, op, right)
+            } else {
+                format!("{}{}{}", left, op, right)
+            }
+        }
+    }
+}
+
+#[test]
+fn random_expressions_keep_their_precedence_when_formatted() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..500 {
+        let source = format!("let x = {}\n", expression(&mut rng, 5));
+        if let Err(message) = verify(&source) {
+            panic!("{}{}", source, message);
+        }
+    }
+}
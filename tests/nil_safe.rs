@@ -0,0 +1,52 @@
+mod common;
+
+use common::{output, run};
+
+const CLASS: &str = "class P {
+    x
+    fn get(self, d) {
+        return self.x + d
+    }
+}
+let p = P { x: 1 }
+let none = nil
+";
+
+#[test]
+fn optional_field_reads_a_field_or_gives_nil() {
+    let out = output(&format!("{}println(p?.x, \" \", none?.x)", CLASS));
+    assert_eq!(out, "1 nil\n");
+}
+
+#[test]
+fn optional_method_call_skips_its_arguments_on_nil() {
+    let out = output(&format!(
+        "{}fn loud() {{
+    println(\"evaluated\")
+    return 0
+}}
+println(p?.get(2), \" \", none?.get(loud()), \" \", \"ab\"?.len(), \" \", none?.len())",
+        CLASS
+    ));
+    assert_eq!(out, "3 nil 2 nil\n");
+}
+
+#[test]
+fn optional_access_chains_with_indexing() {
+    let out = output(&format!(
+        "{}let xs = [p, nil]\nprintln(xs[1]?.x, \" \", xs?.[0]?.x)",
+        CLASS
+    ));
+    assert_eq!(out, "nil 1\n");
+}
+
+#[test]
+fn optional_field_is_not_assignable() {
+    let run = run("let p = nil\np?.x = 1\n");
+    assert_eq!(run.code, 65);
+    assert!(
+        run.stderr.contains("Invalid assignment target"),
+        "{}",
+        run.stderr
+    );
+}
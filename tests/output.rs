@@ -0,0 +1,19 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn to_str_may_print() {
+    let out = output(
+        "class P {
+    x
+    fn to_str(self) {
+        println(\"making\")
+        return \"P(${self.x})\"
+    }
+}
+println(P { x: 1 })
+",
+    );
+    assert_eq!(out, "making\nP(1)\n");
+}
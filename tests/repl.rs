@@ -0,0 +1,101 @@
+mod common;
+
+use common::{riku, script};
+use riku::{
+    Interpreter,
+    output::{self, Stream},
+};
+
+#[test]
+fn recorded_load_replays() {
+    let loaded = script("fn area(r) {\n    return r * r\n}\nprintln(\"loaded\")\n");
+    let record = script("");
+    let session = riku(
+        ["repl", "-q", "--record", record.to_str().unwrap()],
+        &format!(":load {}\narea(3)\n", loaded.display()),
+    );
+    assert_eq!(session.code, 0, "{}", session.stderr);
+
+    // The transcript no longer needs the loaded file.
+    std::fs::remove_file(&loaded).unwrap();
+    let replay = riku([record.to_str().unwrap()], "");
+    assert_eq!(replay.code, 0, "{}", replay.stderr);
+    assert_eq!(replay.stdout, "loaded\n\n");
+    let transcript = std::fs::read_to_string(&record).unwrap();
+    assert!(transcript.contains("area(3)\n// => 9\n"), "{}", transcript);
+    let _ = std::fs::remove_file(record);
+}
+
+#[test]
+fn reload_gives_the_source_of_what_changed() {
+    let path =
+        script("fn area(r) {\n    return r * r\n}\n// shapes\nlet unit = \"cm\"\nprintln(unit)\n");
+    let path_str = path.to_str().unwrap();
+    output::set_sink(Stream::Stdout, Some(Box::new(std::io::sink())));
+    let mut interpreter = Interpreter::new();
+    let first = interpreter.load(path_str).unwrap();
+    assert_eq!(first.source, std::fs::read_to_string(&path).unwrap());
+
+    std::fs::write(
+        &path,
+        "fn area(r) {\n    return 3 * r * r\n}\n// shapes\nlet unit = \"cm\"\nprintln(unit)\n",
+    )
+    .unwrap();
+    let second = interpreter.load(path_str).unwrap();
+    assert_eq!(
+        second.source,
+        "fn area(r) {\n    return 3 * r * r\n}\n// shapes\n"
+    );
+    assert!(second.summary.ends_with(": area"), "{}", second.summary);
+
+    let unchanged = interpreter.load(path_str).unwrap();
+    assert_eq!(unchanged.source, "");
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn load_errors_are_reported_and_the_repl_goes_on() {
+    let broken = script("let before = 41\nlen(5)\nlet later = 1\n");
+    let session = riku(
+        ["repl", "-q"],
+        &format!(
+            ":load {}\nbefore + 1\nlet later = 2\nlater\n",
+            broken.display()
+        ),
+    );
+    let _ = std::fs::remove_file(&broken);
+    assert_eq!(session.code, 0, "{}", session.stderr);
+    assert!(
+        session.stderr.contains(&format!(
+            "Unable to load `{}`: TypeError on line: 2: len() expected",
+            broken.display()
+        )),
+        "{}",
+        session.stderr
+    );
+    assert!(session.stdout.contains("42\n"), "{}", session.stdout);
+    assert!(session.stdout.contains("2\n"), "{}", session.stdout);
+}
+
+#[test]
+fn load_returns_syntax_errors() {
+    let path = script("let x = 1\nlet = 2\n");
+    let mut interpreter = Interpreter::new();
+    let error = interpreter.load(path.to_str().unwrap()).err().unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert!(error.contains("SyntaxError on line: 2"), "{}", error);
+}
+
+#[test]
+fn precision_is_warned_about_once_per_input() {
+    let input = "println(9007199254740993 + 0.5)\nprintln(9007199254740995 + 0.5)\n";
+    let warnings = |stderr: &str| stderr.matches("may be inexact").count();
+    let session = riku(["repl", "-q", "--strict"], input);
+    assert_eq!(session.code, 0, "{}", session.stderr);
+    assert_eq!(warnings(&session.stderr), 2, "{}", session.stderr);
+    let path = script(input);
+    let run = riku(["--strict", path.to_str().unwrap()], "");
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert_eq!(warnings(&run.stderr), 1, "{}", run.stderr);
+    let _ = std::fs::remove_file(path);
+}
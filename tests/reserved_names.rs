@@ -0,0 +1,103 @@
+mod common;
+
+use common::{output, run, run_with};
+
+/// Runs `source`, which must fail with `code`, and returns its error.
+fn error(source: &str, code: i32) -> String {
+    let run = run(source);
+    assert_eq!(run.code, code, "{}: {}", source, run.stderr);
+    run.stderr
+}
+
+#[test]
+fn keywords_cannot_name_anything() {
+    for (source, word) in [
+        ("let true = 1\n", "true"),
+        ("let struct = 1\n", "struct"),
+        ("fn if() {\n}\n", "if"),
+        ("fn f(nil) {\n}\n", "nil"),
+        ("for while in [1] {\n}\n", "while"),
+        ("for i, else in [1] {\n}\n", "else"),
+        ("let (a, true) = (1, 2)\n", "true"),
+    ] {
+        let stderr = error(source, 65);
+        assert!(
+            stderr.contains(&format!(
+                "`{}` is a reserved word and cannot be used as a name",
+                word
+            )),
+            "{}: {}",
+            source,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn only_names_fields_and_indexes_can_be_assigned() {
+    for (source, target) in [
+        ("true = 5\n", "true"),
+        ("nil = 5\n", "nil"),
+        ("fn f() {\n}\nf() = 1\n", "f()"),
+        ("let x = 1\nx + 1 = 2\n", "x + 1"),
+    ] {
+        let stderr = error(source, 65);
+        assert!(
+            stderr.contains(&format!("Invalid assignment target `{}`", target)),
+            "{}: {}",
+            source,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn contextual_keywords_can_name_variables() {
+    let out =
+        output("let in = 1\nlet mut match = 2\nlet elif = 3\nmatch += in\nprintln(match + elif)\n");
+    assert_eq!(out, "6\n");
+}
+
+#[test]
+fn builtins_cannot_be_redefined_at_the_top_level() {
+    for (source, message) in [
+        ("let len = 1\n", "line: 1: Cannot redefine builtin `len`"),
+        (
+            "let x = 1\nfn print(x) {\n}\n",
+            "line: 2: Cannot redefine builtin `print`",
+        ),
+        (
+            "\nlet (a, str) = (1, 2)\n",
+            "line: 2: Cannot redefine builtin `str`",
+        ),
+        (
+            "\n\nconst len = 1\n",
+            "line: 3: Cannot redefine builtin `len`",
+        ),
+        ("len = 3\n", "Cannot assign to builtin `len`"),
+    ] {
+        let stderr = error(source, 70);
+        assert!(stderr.contains(message), "{}: {}", source, stderr);
+    }
+}
+
+#[test]
+fn builtins_can_be_shadowed_in_blocks_and_functions_with_a_warning() {
+    let source = "{\n    let len = 2\n    println(len)\n}\nfn g() {\n    let print = 1\n    return print\n}\nprintln(g(), len(\"ab\"))\n";
+    let run = run(source);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert_eq!(run.stdout, "2\n12\n\n");
+    assert!(
+        run.stderr
+            .contains("`len` on line 2 hides the builtin function `len`")
+    );
+    assert!(
+        run.stderr
+            .contains("`print` on line 6 hides the builtin function `print`")
+    );
+    let quiet = run_with(&["--allow-shadow-builtins"], source);
+    assert_eq!(
+        (quiet.stdout.as_str(), quiet.stderr.as_str()),
+        ("2\n12\n\n", "")
+    );
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::riku;
+
+#[test]
+fn exit_stops_the_cell_but_not_the_server() {
+    let requests = concat!(
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "execute", "params": {"code": "println(1)\nexit(3)\nprintln(2)"}}"#,
+        "\n",
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "execute", "params": {"code": "1 + 1"}}"#,
+        "\n",
+    );
+    let run = riku(["serve", "--jsonrpc"], requests);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    let lines = run.stdout.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            r#"{"id":1,"jsonrpc":"2.0","result":{"diagnostics":[{"kind":"Exit","line":null,"message":"exit(3) ended the cell","status":3}],"ok":false,"results":[],"stderr":"","stdout":"1\n"}}"#,
+            r#"{"id":2,"jsonrpc":"2.0","result":{"diagnostics":[],"ok":true,"results":["2"],"stderr":"","stdout":""}}"#,
+        ]
+    );
+}
+
+#[test]
+fn errors_are_diagnostics() {
+    let request = r#"{"jsonrpc": "2.0", "id": 1, "method": "execute", "params": {"code": "nope"}}"#;
+    let run = riku(["serve", "--jsonrpc"], &format!("{}\n", request));
+    assert_eq!(
+        run.stdout,
+        "{\"id\":1,\"jsonrpc\":\"2.0\",\"result\":{\"diagnostics\":[{\"kind\":\"RuntimeError\",\"line\":1,\"message\":\"Undefined variable `nope`\"}],\"ok\":false,\"results\":[],\"stderr\":\"\",\"stdout\":\"\"}}\n"
+    );
+}
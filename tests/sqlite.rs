@@ -0,0 +1,76 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::{output, run};
+
+const OPEN: &str = "//! requires: files, stdout\nlet db = db_open(\":memory:\")\n";
+
+fn error(source: &str) -> String {
+    let run = run(&format!("{}{}", OPEN, source));
+    assert_eq!(run.code, 70, "{}: {}", source, run.stdout);
+    run.stderr
+}
+
+#[test]
+fn parameters_bind_to_placeholders() {
+    let out = output(&format!(
+        "{}db_exec(db, \"create table t (a, b); insert into t values (1, 'x')\")
+println(db_exec(db, \"insert into t values (?, ?) -- added\", [2, nil]))
+println(db_query(db, \"select * from t where a > ?\", [0]))
+",
+        OPEN
+    ));
+    assert_eq!(
+        out,
+        "1\n[{\"a\": 1, \"b\": \"x\"}, {\"a\": 2, \"b\": nil}]\n"
+    );
+}
+
+#[test]
+fn parameter_counts_must_match_the_placeholders() {
+    for (source, message) in [
+        (
+            "db_query(db, \"select ?\", [1, 2])\n",
+            "db_query(): statement needs 1 parameters but got 2",
+        ),
+        (
+            "db_query(db, \"select ?, ?\", [1])\n",
+            "db_query(): statement needs 2 parameters but got 1",
+        ),
+        (
+            "db_exec(db, \"create table t (a)\", [1])\n",
+            "db_exec(): statement needs 0 parameters but got 1",
+        ),
+    ] {
+        let stderr = error(source);
+        assert!(stderr.contains(message), "{}: {}", source, stderr);
+    }
+}
+
+#[test]
+fn parameters_are_refused_for_several_statements_before_any_runs() {
+    let path = std::env::temp_dir().join(format!("riku-test-{}.db", std::process::id()));
+    let open = format!(
+        "//! requires: files, stdout\nlet db = db_open(\"{}\")\n",
+        path.display()
+    );
+    let failed = run(&format!(
+        "{}db_exec(db, \"create table t (a); insert into t values (?)\", [1])\n",
+        open
+    ));
+    assert_eq!(failed.code, 70);
+    assert!(
+        failed
+            .stderr
+            .contains("db_exec(): parameters can only be bound to a single statement"),
+        "{}",
+        failed.stderr
+    );
+    let tables = output(&format!(
+        "{}println(db_query(db, \"select name from sqlite_master\"))\n",
+        open
+    ));
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(tables, "[]\n");
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::{output, run};
+
+#[test]
+fn every_counts_its_ticks() {
+    let out = output("println(every(0.001, fn() { return true }, 3))");
+    assert_eq!(out, "3\n");
+}
+
+#[test]
+fn huge_intervals_are_runtime_errors() {
+    for (source, name) in [
+        ("after(1e300, fn() { println(1) })", "after"),
+        ("every(1e300, fn() { println(1) }, 1)", "every"),
+        ("every(1e19, fn() { println(1) }, 1)", "every"),
+    ] {
+        let run = run(source);
+        assert_eq!(run.code, 70, "{}: {}", source, run.stderr);
+        assert!(
+            run.stderr
+                .contains(&format!("RuntimeError: {}() interval", name)),
+            "{}: {}",
+            source,
+            run.stderr
+        );
+        assert!(!run.stderr.contains("crashed"), "{}", run.stderr);
+    }
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::{output, riku, run, script};
+
+#[test]
+fn unknown_types_are_rejected_when_declared() {
+    for (source, line, name) in [
+        ("let x: int = 1\n", 1, "`int`; use `number`"),
+        ("let x: bogus? = nil\n", 1, "`bogus`"),
+        ("fn f(n: Num) {\n    return n\n}\nf(1)\n", 4, "`Num`"),
+        ("fn f(n) -> Res {\n    return n\n}\nf(1)\n", 4, "`Res`"),
+    ] {
+        let run = run(source);
+        assert_eq!(run.code, 70, "{}", source);
+        assert!(
+            run.stderr
+                .contains(&format!("on line: {}: Unknown type {}", line, name)),
+            "{}: {}",
+            source,
+            run.stderr
+        );
+    }
+}
+
+#[test]
+fn classes_declared_later_are_known() {
+    let out = output(
+        "fn first(p: Point) -> Point {
+    return p
+}
+struct Point { x }
+let p: Point? = first(Point { x: 1 })
+println(p.x)
+",
+    );
+    assert_eq!(out, "1\n");
+}
+
+#[test]
+fn check_reports_unknown_types() {
+    let path = script(
+        "fn f(p: P, n: int) -> Q {
+    return p
+}
+struct P { x }
+let a: bogus = 1
+let b: P = P { x: 1 }
+",
+    );
+    let run = riku(["check", path.to_str().unwrap()], "");
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(run.code, 65);
+    let errors = run
+        .stderr
+        .lines()
+        .map(|line| line.split_once(": ").unwrap().1)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        [
+            "TypeError: Unknown type `Q`",
+            "TypeError: Unknown type `int`; use `number`",
+            "TypeError: Unknown type `bogus`",
+        ]
+    );
+}
+
+#[test]
+fn builtin_argument_errors_name_the_line_of_the_call() {
+    for (source, line, message) in [
+        ("let a = 1\n\nlen(5)\n", 3, "len() expected"),
+        (
+            "let s = \"a b\"\ns.split(3)\n",
+            2,
+            "string.split() expected",
+        ),
+        (
+            "fn f(x) {\n    return len(x)\n}\nprintln([1].map(f))\n",
+            2,
+            "len() expected",
+        ),
+    ] {
+        let run = run(source);
+        assert_eq!(run.code, 70, "{}", source);
+        assert!(
+            run.stderr
+                .contains(&format!("TypeError on line: {}: {}", line, message)),
+            "{}: {}",
+            source,
+            run.stderr
+        );
+    }
+}